@@ -0,0 +1,254 @@
+/*
+
+Block-structure analysis of encryption oracles
+
+Every chosen-plaintext attack in set 2 starts the same way: feed the oracle runs of "A" until the
+ciphertext grows, work out the block size, check for repeated blocks, then figure out how much
+unknown stuff sits in front of and behind our input. This does all of it once and hands back a
+profile that the attacks can work from.
+
+Assumes the oracle returns E(prefix || input || suffix) and nothing else. Randomized oracles may be
+prepending an IV or nonce, so for those the prefix/suffix split isn't reported.
+
+*/
+
+use crate::oracle::Oracle;
+
+// how far we'll go looking for the ciphertext length to change
+const MAX_BLOCK_SIZE: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ModeGuess {
+	Ecb,
+	Cbc,
+	Stream,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PaddingGuess {
+	Pkcs7,
+	NoPadding,
+	Unknown,
+}
+
+#[derive(Debug, Clone)]
+pub struct OracleProfile {
+	pub block_size: usize,	// 1 for length-preserving oracles
+	pub mode: ModeGuess,
+	pub length_preserving: bool,
+	pub deterministic: bool,
+	pub prefix_len: Option<usize>,
+	pub suffix_len: Option<usize>,
+	pub padding: PaddingGuess,
+}
+
+// returns the block size and the number of filler bytes it took to push the ciphertext into a new block
+pub fn find_block_size<O: Oracle>(oracle: &O) -> Result<(usize, usize), String> {
+	let initial_len = oracle.query(&[])?.len();
+	for filler_len in 1..=MAX_BLOCK_SIZE {
+		let current_len = oracle.query(&vec![b'A'; filler_len])?.len();
+		if current_len != initial_len {
+			return Ok((current_len - initial_len, filler_len));
+		}
+	}
+	Err(format!("ciphertext length did not change with up to {} bytes of input", MAX_BLOCK_SIZE))
+}
+
+pub fn has_repeated_block(ciphertext: &[u8], block_size: usize) -> bool {
+	let blocks: Vec<&[u8]> = ciphertext.chunks(block_size).collect();
+	(1..blocks.len()).any(|i| blocks[i] == blocks[i - 1])
+}
+
+pub fn first_differing_block(a: &[u8], b: &[u8], block_size: usize) -> Option<usize> {
+	a.iter().zip(b.iter()).position(|(x, y)| x != y).map(|i| i / block_size)
+}
+
+pub fn analyse<O: Oracle>(oracle: &O) -> Result<OracleProfile, String> {
+	let initial_len = oracle.query(&[])?.len();
+	let (block_size, jump_filler_len) = find_block_size(oracle)?;
+	let length_preserving = block_size == 1;
+
+	let probe = vec![b'A'; 3 * block_size];
+	let deterministic = oracle.query(&probe)? == oracle.query(&probe)?;
+
+	// a stream cipher with a fixed keystream will repeat blocks for a run of A's too, so check length first
+	let mode = if length_preserving {
+		ModeGuess::Stream
+	} else if has_repeated_block(&oracle.query(&vec![b'A'; 3 * block_size])?, block_size) {
+		ModeGuess::Ecb
+	} else {
+		ModeGuess::Cbc
+	};
+
+	let prefix_len = match deterministic {
+		true => Some(find_prefix_len(oracle, block_size)?),
+		false => None,
+	};
+
+	let padding = match (mode, prefix_len) {
+		(ModeGuess::Stream, _) => PaddingGuess::NoPadding,
+		(ModeGuess::Ecb, Some(p)) => detect_ecb_pkcs7(oracle, block_size, p, jump_filler_len)?,
+		(ModeGuess::Cbc, Some(p)) => detect_cbc_pkcs7(oracle, block_size, p, jump_filler_len)?,
+		(_, None) => PaddingGuess::Unknown,
+	};
+
+	let suffix_len = match (padding, prefix_len) {
+		(PaddingGuess::NoPadding, Some(p)) => Some(initial_len - p),
+		// the ciphertext grew at jump_filler_len because prefix + input + suffix had just hit a block boundary,
+		// which means pkcs7 tacked on a whole block of padding
+		(PaddingGuess::Pkcs7, Some(p)) => Some(initial_len - jump_filler_len - p),
+		_ => None,
+	};
+
+	Ok(OracleProfile {
+		block_size: block_size,
+		mode: mode,
+		length_preserving: length_preserving,
+		deterministic: deterministic,
+		prefix_len: prefix_len,
+		suffix_len: suffix_len,
+		padding: padding,
+	})
+}
+
+// works for anything deterministic (ecb, cbc with a fixed iv, a stream cipher with a fixed nonce): two inputs that
+// share their first n bytes produce ciphertexts that first differ in the block holding byte (prefix_len + n).
+// better than lining up two identical blocks of filler, which gets fooled if the prefix ends in filler bytes.
+fn find_prefix_len<O: Oracle>(oracle: &O, block_size: usize) -> Result<usize, String> {
+	let differing_block = |shared: usize| -> Result<usize, String> {
+		let mut a = vec![b'A'; shared];
+		let mut b = a.clone();
+		a.push(b'A');
+		b.push(b'B');
+		first_differing_block(&oracle.query(&a)?, &oracle.query(&b)?, block_size)
+			.ok_or("changing input did not change ciphertext".to_string())
+	};
+	let first = differing_block(0)?;
+	for shared in 1..=block_size {
+		if differing_block(shared)? > first {
+			return Ok((first + 1) * block_size - shared);
+		}
+	}
+	Err("could not find prefix length".to_string())
+}
+
+// line the prefix up with a block boundary and submit a whole block of pkcs7 padding. if its ciphertext is the same as
+// the extra block the oracle appends once the plaintext is block-aligned, that's pkcs7.
+fn detect_ecb_pkcs7<O: Oracle>(oracle: &O, block_size: usize, prefix_len: usize, jump_filler_len: usize) -> Result<PaddingGuess, String> {
+	let align = (block_size - prefix_len % block_size) % block_size;
+	let mut inp = vec![b'A'; align];
+	inp.append(&mut vec![block_size as u8; block_size]);
+	let start = prefix_len + align;
+	let chosen = oracle.query(&inp)?[start..start + block_size].to_vec();
+
+	let aligned = oracle.query(&vec![b'A'; jump_filler_len])?;
+	match aligned[aligned.len() - block_size..] == chosen[..] {
+		true => Ok(PaddingGuess::Pkcs7),
+		false => Ok(PaddingGuess::Unknown),
+	}
+}
+
+// same idea, but in cbc the padding block was xored with the ciphertext block before it, and so is ours.
+// with a fixed iv we know both of those blocks in advance, so we can cancel one out and swap in the other.
+fn detect_cbc_pkcs7<O: Oracle>(oracle: &O, block_size: usize, prefix_len: usize, jump_filler_len: usize) -> Result<PaddingGuess, String> {
+	let aligned = oracle.query(&vec![b'A'; jump_filler_len])?;
+	let n = aligned.len() / block_size - 1;
+	let padding_ct = &aligned[n * block_size..];
+	let before_padding_ct = &aligned[(n - 1) * block_size..n * block_size];
+
+	// one extra block of filler so the block before ours is ciphertext we can see, not the iv
+	let align = (block_size - prefix_len % block_size) % block_size + block_size;
+	let filler = vec![b'A'; align];
+	let start = prefix_len + align;
+	let before_ours_ct = oracle.query(&filler)?[start - block_size..start].to_vec();
+
+	let mut inp = filler.clone();
+	for i in 0..block_size {
+		inp.push(block_size as u8 ^ before_padding_ct[i] ^ before_ours_ct[i]);
+	}
+	let chosen = oracle.query(&inp)?[start..start + block_size].to_vec();
+	match chosen[..] == padding_ct[..] {
+		true => Ok(PaddingGuess::Pkcs7),
+		false => Ok(PaddingGuess::Unknown),
+	}
+}
+
+pub fn print_profile(profile: &OracleProfile) {
+	let show = |x: Option<usize>| x.map_or("unknown".to_string(), |v| v.to_string());
+	println!("block size: {}, mode: {:?}, length preserving: {}, deterministic: {}, prefix len: {}, suffix len: {}, padding: {:?}",
+		profile.block_size, profile.mode, profile.length_preserving, profile.deterministic,
+		show(profile.prefix_len), show(profile.suffix_len), profile.padding
+	);
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::analysis::{analyse, ModeGuess, PaddingGuess};
+	use crate::set2::{ex9, ex10, ex11, ex12};
+	use crate::set3::ex18;
+
+	fn wrap(prefix: &[u8], inp: &[u8], suffix: &[u8]) -> Vec<u8> {
+		let mut res = prefix.to_vec();
+		res.extend_from_slice(inp);
+		res.extend_from_slice(suffix);
+		res
+	}
+
+	#[test]
+	fn test_ecb_profiles() {
+		let key = ex11::gen_aes128_key();
+		for prefix_len in 0..40 {
+			let prefix = vec![b'A'; prefix_len];	// worst case for the filler trick
+			let suffix = ex12::suffix()[..prefix_len + 3].to_vec();
+			let oracle = |inp: &[u8]| {
+				let mut padded = ex9::pkcs7_padding(&wrap(&prefix, inp, &suffix), 16);
+				ex10::encrypt_aes128ecb(&mut padded, &key).unwrap();
+				padded
+			};
+			let profile = analyse(&oracle).unwrap();
+			assert_eq!(profile.block_size, 16);
+			assert_eq!(profile.mode, ModeGuess::Ecb);
+			assert!(profile.deterministic);
+			assert_eq!(profile.prefix_len, Some(prefix_len));
+			assert_eq!(profile.suffix_len, Some(suffix.len()));
+			assert_eq!(profile.padding, PaddingGuess::Pkcs7);
+		}
+	}
+
+	#[test]
+	fn test_cbc_profiles() {
+		let key = ex11::gen_aes128_key();
+		for prefix_len in 0..20 {
+			let prefix: Vec<u8> = (0..prefix_len as u8).collect();
+			let suffix = vec![b';'; 42];
+			let fixed_iv = |inp: &[u8]| {
+				ex10::encrypt_aes128cbc(&ex9::pkcs7_padding(&wrap(&prefix, inp, &suffix), 16), &key, &[0; 16])
+			};
+			let profile = analyse(&fixed_iv).unwrap();
+			assert_eq!(profile.mode, ModeGuess::Cbc);
+			assert!(profile.deterministic);
+			assert_eq!(profile.prefix_len, Some(prefix_len));
+			assert_eq!(profile.suffix_len, Some(42));
+			assert_eq!(profile.padding, PaddingGuess::Pkcs7);
+		}
+		let random_iv = |inp: &[u8]| {
+			ex10::encrypt_aes128cbc(&ex9::pkcs7_padding(inp, 16), &key, &ex11::gen_aes128_key())
+		};
+		let profile = analyse(&random_iv).unwrap();
+		assert_eq!(profile.mode, ModeGuess::Cbc);
+		assert!(!profile.deterministic);
+		assert_eq!(profile.prefix_len, None);
+	}
+
+	#[test]
+	fn test_ctr_profile() {
+		let key = ex11::gen_aes128_key().to_vec();
+		let oracle = |inp: &[u8]| ex18::encrypt_ctr(&wrap(b"comment1=", inp, b";comment2"), &key, &vec![0; 8]);
+		let profile = analyse(&oracle).unwrap();
+		assert_eq!(profile.mode, ModeGuess::Stream);
+		assert!(profile.length_preserving);
+		assert_eq!(profile.prefix_len, Some(9));
+		assert_eq!(profile.suffix_len, Some(9));
+		assert_eq!(profile.padding, PaddingGuess::NoPadding);
+	}
+}
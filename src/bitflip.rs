@@ -0,0 +1,249 @@
+/*
+
+Automatic bitflipping injection
+
+ex16 and ex26 hard-code which ciphertext bytes to flip for "AAAA:admin<true". This works it out for
+any target string: find where our input lands (or take the prefix length if we already know it),
+find out which of the target's bytes the oracle won't let through untouched, submit stand-ins for
+those, and flip the stand-ins into place.
+
+CTR is easy: ciphertext byte i only affects plaintext byte i, so the flips go right on top of the
+stand-ins, any number of blocks long.
+
+CBC flips go in the block before, which gets scrambled, so we give it a sacrificial block of our own.
+That works as long as every flip lands in the first block of the target: a flip for the second block
+would have to go in the first, and scramble it. For those we stop flipping individual bytes and
+recompute the ciphertext blocks outright. With a fixed IV we know the ciphertext block in front of
+anything we submit, so submitting (x XOR that block) gets us AES(x) for any x we like. Chaining those
+gives ciphertext that decrypts to the whole target; the block after it is scrambled instead, so it's
+one of ours too.
+
+Either way the scrambled block decrypts to random bytes, which a receiver that actually parses what it
+got can choke on. What it scrambles to depends on the filler we submitted, so a few different fillers
+get tried before giving up.
+
+*/
+
+use crate::analysis;
+use crate::oracle::Oracle;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FlipMode {
+	Cbc,
+	Ctr,
+}
+
+#[derive(Debug, Clone)]
+pub struct InjectionPlan {
+	pub input: Vec<u8>,				// what to submit to the encryption oracle
+	pub deltas: Vec<(usize, u8)>,	// (ciphertext index, xor mask) applied to what it returns
+}
+
+// which bytes survive the oracle's input filtering. anything escaped or stripped changes the ciphertext length
+// at the right filler length; this checks both sides of a length jump so either shows up.
+struct ByteFilter<'a, O: Oracle> {
+	oracle: &'a O,
+	block_size: usize,
+	jump_filler_len: usize,
+	jump_len: usize,
+	known: HashMap<u8, bool>,
+}
+
+impl<'a, O: Oracle> ByteFilter<'a, O> {
+	fn new(oracle: &'a O, block_size: usize) -> Result<ByteFilter<'a, O>, String> {
+		let (_, jump_filler_len) = analysis::find_block_size(oracle)?;
+		let jump_len = oracle.query(&vec![b'A'; jump_filler_len])?.len();
+		Ok(ByteFilter{oracle: oracle, block_size: block_size, jump_filler_len: jump_filler_len, jump_len: jump_len, known: HashMap::new()})
+	}
+
+	fn passes(&mut self, b: u8) -> Result<bool, String> {
+		if let Some(res) = self.known.get(&b) {
+			return Ok(*res);
+		}
+		// one byte short of the jump plus b should jump exactly like a full run of filler...
+		let mut inp = vec![b'A'; self.jump_filler_len - 1];
+		inp.push(b);
+		let reaches_jump = self.oracle.query(&inp)?.len() == self.jump_len;
+		// ...and two short plus b, a block further along so there's room, should not reach the next one
+		let mut inp = vec![b'A'; self.jump_filler_len + self.block_size - 2];
+		inp.push(b);
+		let stays_under = self.oracle.query(&inp)?.len() == self.jump_len;
+		let res = reaches_jump && stays_under;
+		self.known.insert(b, res);
+		Ok(res)
+	}
+
+	fn stand_in(&mut self, b: u8) -> Result<u8, String> {
+		if self.passes(b)? {
+			return Ok(b);
+		}
+		for bit in 0..8 {
+			if self.passes(b ^ (1 << bit))? {
+				return Ok(b ^ (1 << bit));
+			}
+		}
+		Err(format!("no single-bit stand-in for {:02x} gets past the oracle", b))
+	}
+}
+
+const REJECTED: &str = "modified ciphertext did not pass the verifying oracle";
+
+// submit input, apply deltas, and check the result with the verifying oracle. None if it didn't pass
+fn run_plan<O: Oracle, V: Fn(&[u8]) -> bool>(oracle: &O, verify: &V, plan: InjectionPlan) -> Result<Option<(InjectionPlan, Vec<u8>)>, String> {
+	let mut ciphertext = oracle.query(&plan.input)?;
+	for (i, mask) in plan.deltas.iter() {
+		ciphertext[*i] ^= mask;
+	}
+	match verify(&ciphertext) {
+		true => Ok(Some((plan, ciphertext))),
+		false => Ok(None),
+	}
+}
+
+// returns the plan that was used and the modified ciphertext. mode and prefix_len are detected if not given.
+pub fn inject<O: Oracle, V: Fn(&[u8]) -> bool>(oracle: &O, verify: &V, target: &[u8], mode: Option<FlipMode>, prefix_len: Option<usize>)
+	-> Result<(InjectionPlan, Vec<u8>), String> {
+	let profile = analysis::analyse(oracle)?;
+	let mode = match (mode, profile.mode) {
+		(Some(m), _) => m,
+		(None, analysis::ModeGuess::Stream) => FlipMode::Ctr,
+		(None, analysis::ModeGuess::Cbc) => FlipMode::Cbc,
+		(None, m) => return Err(format!("can't bitflip {:?}", m)),
+	};
+	let prefix_len = prefix_len.or(profile.prefix_len).ok_or("could not determine prefix length")?;
+	let block_size = profile.block_size;
+
+	let mut filter = ByteFilter::new(oracle, block_size)?;
+	let mut stand_ins = vec![];
+	for b in target.iter() {
+		stand_ins.push(filter.stand_in(*b)?);
+	}
+	let flips: Vec<(usize, u8)> = stand_ins.iter().zip(target.iter()).enumerate()
+		.filter(|(_, (s, t))| s != t)
+		.map(|(i, (s, t))| (i, s ^ t))
+		.collect();
+
+	match mode {
+		FlipMode::Ctr => {
+			let deltas = flips.iter().map(|(i, mask)| (prefix_len + i, *mask)).collect();
+			run_plan(oracle, verify, InjectionPlan{input: stand_ins, deltas: deltas})?.ok_or(REJECTED.to_string())
+		},
+		FlipMode::Cbc => {
+			let align = (block_size - prefix_len % block_size) % block_size;
+			let single_block = flips.iter().all(|(i, _)| *i < block_size);
+			if !single_block && !profile.deterministic {
+				return Err("flips past the first block need a deterministic (fixed iv) oracle".to_string());
+			}
+			for filler in b'A'..=b'P' {
+				let res = match single_block {
+					// target starts right after a sacrificial block of ours
+					true => {
+						let sacrificial = prefix_len + align;
+						let mut input = vec![filler; align + block_size];
+						input.extend_from_slice(&stand_ins);
+						let deltas = flips.iter().map(|(i, mask)| (sacrificial + i, *mask)).collect();
+						run_plan(oracle, verify, InjectionPlan{input: input, deltas: deltas})?
+					},
+					false => chain_cbc_blocks(oracle, verify, &mut filter, target, prefix_len, block_size, filler)?,
+				};
+				if let Some(res) = res {
+					return Ok(res);
+				}
+			}
+			Err(REJECTED.to_string())
+		},
+	}
+}
+
+fn chain_cbc_blocks<O: Oracle, V: Fn(&[u8]) -> bool>(oracle: &O, verify: &V, filter: &mut ByteFilter<O>, target: &[u8], prefix_len: usize, block_size: usize,
+	filler: u8) -> Result<Option<(InjectionPlan, Vec<u8>)>, String> {
+	// line up with a block boundary, at least one block in so the block before the target isn't the iv
+	let mut align = (block_size - prefix_len % block_size) % block_size;
+	if prefix_len + align < block_size {
+		align += block_size;
+	}
+	let start = prefix_len + align;
+	let num_blocks = (target.len() + block_size - 1) / block_size;
+
+	// AES(x): in front of the chosen block goes a filler block whose ciphertext we've just seen. try different
+	// fillers until x XOR its ciphertext is something the oracle will take as-is.
+	let mut encrypt_block = |x: &[u8]| -> Result<Vec<u8>, String> {
+		for c in b'A'..=b'Z' {
+			let mut inp = vec![b'A'; align];
+			inp.append(&mut vec![c; block_size]);
+			let before = oracle.query(&inp)?[start..start + block_size].to_vec();
+			let chosen: Vec<u8> = x.iter().zip(before.iter()).map(|(a, b)| a ^ b).collect();
+			let mut usable = true;
+			for b in chosen.iter() {
+				if !filter.passes(*b)? {
+					usable = false;
+					break;
+				}
+			}
+			if usable {
+				inp.extend_from_slice(&chosen);
+				return Ok(oracle.query(&inp)?[start + block_size..start + 2 * block_size].to_vec());
+			}
+		}
+		Err("could not find a filler block that gets the chosen block past the oracle".to_string())
+	};
+
+	// placeholders for the target blocks, then a sacrificial block for the scrambling to land in
+	let input = vec![filler; align + (num_blocks + 1) * block_size];
+	let original = oracle.query(&input)?;
+	let mut padded_target = target.to_vec();
+	padded_target.resize(num_blocks * block_size, b'A');
+
+	let mut previous = original[start - block_size..start].to_vec();
+	let mut deltas = vec![];
+	for (k, chunk) in padded_target.chunks(block_size).enumerate() {
+		let x: Vec<u8> = chunk.iter().zip(previous.iter()).map(|(a, b)| a ^ b).collect();
+		let new_block = encrypt_block(&x)?;
+		let offset = start + k * block_size;
+		for i in 0..block_size {
+			deltas.push((offset + i, original[offset + i] ^ new_block[i]));
+		}
+		previous = new_block;
+	}
+	run_plan(oracle, verify, InjectionPlan{input: input, deltas: deltas})
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::bitflip::inject;
+	use crate::set2::{ex11, ex16};
+	use crate::set3::ex18;
+	use crate::set4::ex26;
+
+	#[test]
+	fn test_cbc_injection() {
+		let key = ex11::gen_aes128_key();
+		let iv = [0u8; 16];
+		let oracle = |inp: &[u8]| ex16::assemble_and_encrypt(&mut inp.to_vec(), key.to_vec(), &iv);
+		for target in [";admin=true", ";admin=true;role=root", "x;admin=true;role=root;uid=0;gid=0"].iter() {
+			let verify = |ct: &[u8]| {
+				let mut decrypted = ct.to_vec();
+				ex16::is_encrypted_admin(&mut decrypted, &key, &iv).unwrap_or(false)
+					&& String::from_utf8_lossy(&decrypted).contains(target)
+			};
+			let (plan, _) = inject(&oracle, &verify, target.as_bytes(), None, None).unwrap();
+			assert!(!plan.deltas.is_empty());
+		}
+	}
+
+	#[test]
+	fn test_ctr_injection() {
+		let key = ex11::gen_aes128_key().to_vec();
+		let nonce = vec![7u8; 8];
+		let oracle = |inp: &[u8]| ex26::ctr_assemble_and_encrypt(&mut inp.to_vec(), &key, &nonce);
+		let target = ";admin=true;role=root;uid=0;gid=0";
+		let verify = |ct: &[u8]| {
+			ex26::ctr_is_encrypted_admin(&ct.to_vec(), &key, &nonce)
+				&& String::from_utf8_lossy(&ex18::encrypt_ctr(&ct.to_vec(), &key, &nonce)).contains(target)
+		};
+		let (plan, _) = inject(&oracle, &verify, target.as_bytes(), None, Some(32)).unwrap();
+		// only the escaped bytes need flipping
+		assert_eq!(plan.deltas.len(), target.bytes().filter(|c| *c == b';' || *c == b'=').count());
+	}
+}
@@ -0,0 +1,357 @@
+/*
+
+Chunked authenticated random-access storage
+
+ex25's edit re-encrypts the new text under the same keystream, so anyone who can see the ciphertext
+before and after an edit (or ask for edits) gets the keystream and the plaintext with it. This is
+what edit-in-place should look like instead.
+
+The blob is split into fixed-size chunks. Every write to a chunk re-encrypts it, AES-128-CTR under a
+fresh random nonce, and tags it with HMAC-SHA1 over its index, a version stamp, the nonce and the
+ciphertext. Rewriting the same bytes gives a completely different ciphertext.
+
+The tag stops a chunk being modified or moved to another index. What it can't stop on its own is
+rollback: an old copy of chunk i is still a perfectly valid chunk i. So the blob keeps a little
+trusted state that doesn't live with the ciphertext: the version stamp each chunk should have (8
+bytes per chunk) and the plaintext length. Stamps come from one counter for the whole blob and are
+never reused, so a chunk written, truncated away and written again can't be swapped for its older self.
+
+`storage` is the untrusted part, what an attacker with the disk gets to play with.
+
+*/
+
+use crate::mac::{constant_time_eq, hmac_sha1};
+use crate::set2::ex11;
+use crate::set3::ex18;
+use rand::{Rng, thread_rng};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoredChunk {
+	pub nonce: Vec<u8>,
+	pub ciphertext: Vec<u8>,
+	pub tag: Vec<u8>,
+}
+
+pub struct EncryptedBlob {
+	pub storage: Vec<StoredChunk>,
+	key: Vec<u8>,
+	mac_key: Vec<u8>,
+	chunk_size: usize,
+	// trusted
+	versions: Vec<u64>,
+	next_version: u64,
+	len: usize,
+	position: usize,
+}
+
+impl EncryptedBlob {
+	pub fn new(chunk_size: usize) -> Result<EncryptedBlob, String> {
+		if chunk_size == 0 {
+			return Err("chunk size must be positive".to_string());
+		}
+		Ok(EncryptedBlob {
+			storage: vec![],
+			key: ex11::gen_aes128_key().to_vec(),
+			mac_key: ex11::gen_aes128_key().to_vec(),
+			chunk_size: chunk_size,
+			versions: vec![],
+			next_version: 0,
+			len: 0,
+			position: 0,
+		})
+	}
+
+	pub fn len(&self) -> usize {
+		self.len
+	}
+
+	pub fn position(&self) -> usize {
+		self.position
+	}
+
+	// like a file, seeking past the end is fine. the gap reads as zeroes once something is written after it
+	pub fn seek(&mut self, position: usize) {
+		self.position = position;
+	}
+
+	fn tag(&self, index: usize, version: u64, nonce: &[u8], ciphertext: &[u8]) -> Vec<u8> {
+		let mut authenticated = (index as u64).to_be_bytes().to_vec();
+		authenticated.extend_from_slice(&version.to_be_bytes());
+		authenticated.extend_from_slice(nonce);
+		authenticated.extend_from_slice(ciphertext);
+		hmac_sha1(&self.mac_key, &authenticated)
+	}
+
+	// how many plaintext bytes chunk index should hold
+	fn expected_chunk_len(&self, index: usize) -> usize {
+		match (index + 1) * self.chunk_size <= self.len {
+			true => self.chunk_size,
+			false => self.len - index * self.chunk_size,
+		}
+	}
+
+	fn read_chunk(&self, index: usize) -> Result<Vec<u8>, String> {
+		let chunk = self.storage.get(index).ok_or(format!("chunk {}: missing", index))?;
+		if chunk.ciphertext.len() != self.expected_chunk_len(index) {
+			return Err(format!("chunk {}: wrong length", index));
+		}
+		if !constant_time_eq(&self.tag(index, self.versions[index], &chunk.nonce, &chunk.ciphertext), &chunk.tag) {
+			return Err(format!("chunk {}: authentication failed (tampered, moved or rolled back)", index));
+		}
+		Ok(ex18::encrypt_ctr(&chunk.ciphertext, &self.key, &chunk.nonce))
+	}
+
+	fn write_chunk(&mut self, index: usize, plaintext: &Vec<u8>) {
+		let mut nonce = vec![0u8; 8];
+		thread_rng().fill(&mut nonce[..]);
+		let version = self.next_version;
+		self.next_version += 1;
+		let ciphertext = ex18::encrypt_ctr(plaintext, &self.key, &nonce);
+		let tag = self.tag(index, version, &nonce, &ciphertext);
+		let chunk = StoredChunk{nonce: nonce, ciphertext: ciphertext, tag: tag};
+		match index < self.storage.len() {
+			true => {
+				self.storage[index] = chunk;
+				self.versions[index] = version;
+			},
+			false => {
+				self.storage.push(chunk);
+				self.versions.push(version);
+			},
+		}
+	}
+
+	// checks the chunk count and every chunk
+	pub fn verify(&self) -> Result<(), String> {
+		let num_chunks = (self.len + self.chunk_size - 1) / self.chunk_size;
+		if self.storage.len() != num_chunks {
+			return Err(format!("expected {} chunks, storage has {}", num_chunks, self.storage.len()));
+		}
+		for i in 0..num_chunks {
+			self.read_chunk(i)?;
+		}
+		Ok(())
+	}
+
+	// up to len bytes from the current position, fewer at the end of the blob
+	pub fn read(&mut self, len: usize) -> Result<Vec<u8>, String> {
+		let end = std::cmp::min(self.position + len, self.len);
+		let mut res = vec![];
+		let mut pos = self.position;
+		while pos < end {
+			let index = pos / self.chunk_size;
+			let chunk = self.read_chunk(index)?;
+			let start = pos - index * self.chunk_size;
+			let stop = std::cmp::min(chunk.len(), end - index * self.chunk_size);
+			res.extend_from_slice(&chunk[start..stop]);
+			pos = index * self.chunk_size + stop;
+		}
+		self.position = std::cmp::max(self.position, end);
+		Ok(res)
+	}
+
+	pub fn read_all(&mut self) -> Result<Vec<u8>, String> {
+		self.seek(0);
+		let len = self.len;
+		self.read(len)
+	}
+
+	// overwrites from the current position, growing the blob if it runs past the end. every chunk it
+	// touches is read and checked before any of them is rewritten, so a bad one leaves the blob as it was
+	pub fn write(&mut self, data: &[u8]) -> Result<(), String> {
+		// a gap past the end gets written as zeroes, as if it had been part of data
+		let (from, data) = match self.position > self.len {
+			true => {
+				let mut padded = vec![0u8; self.position - self.len];
+				padded.extend_from_slice(data);
+				(self.len, padded)
+			},
+			false => (self.position, data.to_vec()),
+		};
+		let end = from + data.len();
+		let mut updated = vec![];
+		let mut pos = from;
+		while pos < end {
+			let index = pos / self.chunk_size;
+			let mut chunk = match index * self.chunk_size < self.len {
+				true => self.read_chunk(index)?,
+				false => vec![],
+			};
+			let start = pos - index * self.chunk_size;
+			let stop = std::cmp::min(self.chunk_size, end - index * self.chunk_size);
+			if chunk.len() < stop {
+				chunk.resize(stop, 0);
+			}
+			chunk[start..stop].copy_from_slice(&data[pos - from..pos - from + stop - start]);
+			updated.push((index, chunk));
+			pos = index * self.chunk_size + stop;
+		}
+		for (index, chunk) in updated {
+			self.write_chunk(index, &chunk);
+			self.len = std::cmp::max(self.len, index * self.chunk_size + chunk.len());
+		}
+		self.position = end;
+		Ok(())
+	}
+
+	pub fn append(&mut self, data: &[u8]) -> Result<(), String> {
+		self.position = self.len;
+		self.write(data)
+	}
+
+	pub fn truncate(&mut self, len: usize) -> Result<(), String> {
+		if len >= self.len {
+			return Ok(());
+		}
+		let num_chunks = (len + self.chunk_size - 1) / self.chunk_size;
+		let last = match len % self.chunk_size {
+			0 => None,
+			partial => Some(self.read_chunk(num_chunks - 1)?[..partial].to_vec()),
+		};
+		self.storage.truncate(num_chunks);
+		self.versions.truncate(num_chunks);
+		self.len = len;
+		if let Some(chunk) = last {
+			self.write_chunk(num_chunks - 1, &chunk);
+		}
+		self.position = std::cmp::min(self.position, len);
+		Ok(())
+	}
+}
+
+pub fn demonstrate() {
+	let mut blob = EncryptedBlob::new(16).expect("could not create blob");
+	blob.append(b"I'm back and I'm ringin' the bell, a rockin' on the mike while the fly girls yell").expect("could not append");
+	let before = blob.storage[1].clone();
+	blob.seek(16);
+	blob.write(b"RINGIN'").expect("could not write");
+	println!("after overwrite: {}", String::from_utf8_lossy(&blob.read_all().expect("could not read")));
+	println!("rewritten chunk has a new nonce: {}", before.nonce != blob.storage[1].nonce);
+
+	// put the old chunk back
+	let current = blob.storage[1].clone();
+	blob.storage[1] = before;
+	println!("rolled back: {:?}", blob.verify());
+	blob.storage[1] = current;
+	blob.storage.swap(2, 3);
+	println!("reordered: {:?}", blob.verify());
+	blob.storage.swap(2, 3);
+
+	let half = blob.len() / 2;
+	blob.truncate(half).expect("could not truncate");
+	println!("truncated to {} bytes, position {}: {}", blob.len(), blob.position(), String::from_utf8_lossy(&blob.read_all().expect("could not read")));
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::blob_store::EncryptedBlob;
+	use crate::oracle::Oracle;
+	use crate::set4::ex25;
+	use std::cell::RefCell;
+
+	// ex25's edit api in front of a blob
+	struct EditOracle<'a>(&'a RefCell<EncryptedBlob>);
+
+	impl<'a> Oracle for EditOracle<'a> {
+		fn query(&self, request: &[u8]) -> Result<Vec<u8>, String> {
+			let (offset, newtext) = ex25::parse_edit_request(request)?;
+			let mut blob = self.0.borrow_mut();
+			blob.seek(offset);
+			blob.write(&newtext)?;
+			Ok(blob.storage.iter().map(|c| c.ciphertext.clone()).flatten().collect())
+		}
+	}
+
+	#[test]
+	fn test_random_access() {
+		let mut blob = EncryptedBlob::new(7).unwrap();
+		let mut model: Vec<u8> = vec![];
+		blob.append(b"hello world, this is a test").unwrap();
+		model.extend_from_slice(b"hello world, this is a test");
+
+		blob.seek(6);
+		blob.write(b"WORLD").unwrap();
+		model[6..11].copy_from_slice(b"WORLD");
+		blob.seek(3);
+		assert_eq!(blob.read(10).unwrap(), model[3..13].to_vec());
+		assert_eq!(blob.position(), 13);
+
+		// past the end, leaving a gap
+		blob.seek(40);
+		blob.write(b"!!").unwrap();
+		model.resize(40, 0);
+		model.extend_from_slice(b"!!");
+		assert_eq!(blob.read_all().unwrap(), model);
+
+		blob.truncate(12).unwrap();
+		model.truncate(12);
+		assert_eq!(blob.read_all().unwrap(), model);
+		blob.append(b" and more").unwrap();
+		model.extend_from_slice(b" and more");
+		assert_eq!(blob.read_all().unwrap(), model);
+		assert_eq!(blob.len(), model.len());
+		blob.seek(blob.len() - 2);
+		assert_eq!(blob.read(100).unwrap(), b"re".to_vec());
+		assert!(blob.verify().is_ok());
+		assert!(EncryptedBlob::new(0).is_err());
+	}
+
+	#[test]
+	fn test_tampering() {
+		let mut blob = EncryptedBlob::new(8).unwrap();
+		blob.append(b"0123456789abcdefghijklmnopqrstuv").unwrap();
+		let old = blob.storage.clone();
+		blob.seek(9);
+		blob.write(b"X").unwrap();
+
+		// modify
+		blob.storage[1].ciphertext[0] ^= 1;
+		assert!(blob.verify().is_err());
+		blob.storage[1].ciphertext[0] ^= 1;
+		assert!(blob.verify().is_ok());
+		// reorder
+		blob.storage.swap(0, 2);
+		assert!(blob.verify().is_err());
+		blob.storage.swap(0, 2);
+		// roll back
+		let current = blob.storage[1].clone();
+		blob.storage[1] = old[1].clone();
+		assert!(blob.verify().is_err());
+		blob.seek(8);
+		assert!(blob.read(1).is_err());
+		blob.storage[1] = current;
+		// drop a chunk
+		let last = blob.storage.pop().unwrap();
+		assert!(blob.verify().is_err());
+		blob.storage.push(last);
+
+		// a chunk truncated away and written again can't be swapped for the old one
+		let before_truncate = blob.storage[3].clone();
+		blob.truncate(24).unwrap();
+		blob.append(b"yzyzyzyz").unwrap();
+		blob.storage[3] = before_truncate;
+		assert!(blob.verify().is_err());
+
+		// a write across a tampered chunk doesn't rewrite the good ones before it
+		let mut blob = EncryptedBlob::new(8).unwrap();
+		blob.append(b"0123456789abcdefghijklmnopqrstuv").unwrap();
+		blob.storage[2].ciphertext[0] ^= 1;
+		let before = blob.storage.clone();
+		blob.seek(4);
+		assert!(blob.write(b"XXXXXXXXXXXXXXXX").is_err());
+		assert_eq!(blob.storage, before);
+		assert_eq!(blob.position(), 4);
+	}
+
+	#[test]
+	fn test_ex25_attack() {
+		// the edit attack from ex25, against a blob: every edit comes back under new nonces
+		let plaintext = b"attack at dawn, bring snacks";
+		let mut blob = EncryptedBlob::new(16).unwrap();
+		blob.append(plaintext).unwrap();
+		let encrypted: Vec<u8> = blob.storage.iter().map(|c| c.ciphertext.clone()).flatten().collect();
+		let blob = RefCell::new(blob);
+		let recovered = ex25::recover_plaintext_with_edit_oracle(&encrypted, &EditOracle(&blob));
+		assert_eq!(recovered, Err("no edit reproduced the reference ciphertext for byte 0".to_string()));
+	}
+}
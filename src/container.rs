@@ -0,0 +1,399 @@
+/*
+
+Authenticated file container
+
+AES-128-CTR and HMAC-SHA1, encrypt-then-MAC, keys derived from a password. Data is split into
+chunks so a file never has to be in memory all at once, and each chunk is checked before any of
+its plaintext is written out.
+
+Layout (integers big-endian):
+
+	header, 40 bytes:
+		magic			4	"RPCT"
+		version			1	1
+		cipher			1	1 = AES-128-CTR (ex18's counter layout)
+		mac				1	1 = HMAC-SHA1
+		kdf				1	1 = PBKDF2-HMAC-SHA1, giving 16 bytes of cipher key then 16 of mac key
+		kdf iterations	4
+		kdf salt		16
+		nonce			8	chunk i is encrypted under nonce XOR i
+		chunk size		4	plaintext bytes per chunk, only the last may be shorter
+
+	then one or more chunks:
+		final			1	1 on the last chunk, 0 otherwise
+		length			4	ciphertext bytes
+		ciphertext		length
+		tag				20	HMAC(mac key, header || chunk index (8 bytes) || final || length || ciphertext)
+
+Every tag covers the whole header, so changing any header field fails the first chunk even where it
+wouldn't change the derived keys. The index stops chunks being reordered or swapped between
+positions, and the final flag stops the file being cut short at a chunk boundary. An empty file is
+still one (empty, final) chunk.
+
+decrypt_file writes to a temporary file next to the output and only renames it into place once the
+final chunk checks out, so a tampered container never leaves partial plaintext behind.
+
+*/
+
+use crate::mac::{constant_time_eq, hmac_sha1};
+use crate::set3::ex18;
+use rand::{Rng, thread_rng};
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Read, Write};
+
+const MAGIC: &[u8; 4] = b"RPCT";
+const VERSION: u8 = 1;
+const CIPHER_AES128_CTR: u8 = 1;
+const MAC_HMAC_SHA1: u8 = 1;
+const KDF_PBKDF2_HMAC_SHA1: u8 = 1;
+const HEADER_LEN: usize = 40;
+const TAG_LEN: usize = 20;
+// upper limits, so a tampered header can't make us allocate or hash forever before the tag check fails
+const MAX_CHUNK_SIZE: u32 = 1 << 24;
+const MAX_ITERATIONS: u32 = 1 << 24;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ContainerParams {
+	pub iterations: u32,
+	pub chunk_size: u32,
+}
+
+impl Default for ContainerParams {
+	fn default() -> ContainerParams {
+		ContainerParams{iterations: 4096, chunk_size: 1 << 16}
+	}
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Header {
+	iterations: u32,
+	salt: [u8; 16],
+	nonce: [u8; 8],
+	chunk_size: u32,
+}
+
+impl Header {
+	fn to_bytes(&self) -> Vec<u8> {
+		let mut res = MAGIC.to_vec();
+		res.extend_from_slice(&[VERSION, CIPHER_AES128_CTR, MAC_HMAC_SHA1, KDF_PBKDF2_HMAC_SHA1]);
+		res.extend_from_slice(&self.iterations.to_be_bytes());
+		res.extend_from_slice(&self.salt);
+		res.extend_from_slice(&self.nonce);
+		res.extend_from_slice(&self.chunk_size.to_be_bytes());
+		res
+	}
+
+	fn from_bytes(bytes: &[u8]) -> Result<Header, String> {
+		let u32_at = |i: usize| u32::from_be_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]);
+		if &bytes[..4] != MAGIC {
+			return Err("not a container (bad magic)".to_string());
+		}
+		if bytes[4] != VERSION {
+			return Err(format!("unsupported container version {}", bytes[4]));
+		}
+		match (bytes[5], bytes[6], bytes[7]) {
+			(CIPHER_AES128_CTR, MAC_HMAC_SHA1, KDF_PBKDF2_HMAC_SHA1) => (),
+			ids => return Err(format!("unsupported algorithms (cipher, mac, kdf) = {:?}", ids)),
+		}
+		let header = Header {
+			iterations: u32_at(8),
+			salt: {
+				let mut salt = [0u8; 16];
+				salt.copy_from_slice(&bytes[12..28]);
+				salt
+			},
+			nonce: {
+				let mut nonce = [0u8; 8];
+				nonce.copy_from_slice(&bytes[28..36]);
+				nonce
+			},
+			chunk_size: u32_at(36),
+		};
+		if header.iterations == 0 || header.iterations > MAX_ITERATIONS {
+			return Err(format!("kdf iterations {} out of range", header.iterations));
+		}
+		if header.chunk_size == 0 || header.chunk_size > MAX_CHUNK_SIZE {
+			return Err(format!("chunk size {} out of range", header.chunk_size));
+		}
+		Ok(header)
+	}
+
+	fn chunk_nonce(&self, index: u64) -> Vec<u8> {
+		self.nonce.iter().zip(index.to_be_bytes().iter()).map(|(a, b)| a ^ b).collect()
+	}
+}
+
+pub fn pbkdf2_hmac_sha1(password: &[u8], salt: &[u8], iterations: u32, len: usize) -> Vec<u8> {
+	let mut res = vec![];
+	let mut block_index: u32 = 1;
+	while res.len() < len {
+		let mut u = salt.to_vec();
+		u.extend_from_slice(&block_index.to_be_bytes());
+		u = hmac_sha1(password, &u);
+		let mut t = u.clone();
+		for _ in 1..iterations {
+			u = hmac_sha1(password, &u);
+			for (a, b) in t.iter_mut().zip(u.iter()) {
+				*a ^= b;
+			}
+		}
+		res.append(&mut t);
+		block_index += 1;
+	}
+	res.truncate(len);
+	res
+}
+
+// (cipher key, mac key)
+fn derive_keys(password: &[u8], header: &Header) -> (Vec<u8>, Vec<u8>) {
+	let material = pbkdf2_hmac_sha1(password, &header.salt, header.iterations, 32);
+	(material[..16].to_vec(), material[16..].to_vec())
+}
+
+fn chunk_tag(mac_key: &[u8], header_bytes: &[u8], index: u64, last: bool, ciphertext: &[u8]) -> Vec<u8> {
+	let mut authenticated = header_bytes.to_vec();
+	authenticated.extend_from_slice(&index.to_be_bytes());
+	authenticated.push(last as u8);
+	authenticated.extend_from_slice(&(ciphertext.len() as u32).to_be_bytes());
+	authenticated.extend_from_slice(ciphertext);
+	hmac_sha1(mac_key, &authenticated)
+}
+
+// like read_exact, but a short read at the end of the input isn't an error. returns how much was read
+fn read_up_to<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<usize, String> {
+	let mut filled = 0;
+	while filled < buf.len() {
+		match reader.read(&mut buf[filled..]) {
+			Ok(0) => break,
+			Ok(n) => filled += n,
+			Err(e) => return Err(format!("read failed: {}", e)),
+		}
+	}
+	Ok(filled)
+}
+
+fn read_exactly<R: Read>(reader: &mut R, len: usize, what: &str) -> Result<Vec<u8>, String> {
+	let mut buf = vec![0u8; len];
+	match read_up_to(reader, &mut buf)? == len {
+		true => Ok(buf),
+		false => Err(format!("container truncated in {}", what)),
+	}
+}
+
+pub fn encrypt_stream<R: Read, W: Write>(reader: &mut R, writer: &mut W, password: &[u8], params: &ContainerParams) -> Result<(), String> {
+	if params.chunk_size == 0 || params.chunk_size > MAX_CHUNK_SIZE || params.iterations == 0 || params.iterations > MAX_ITERATIONS {
+		return Err(format!("invalid container parameters {:?}", params));
+	}
+	let mut header = Header{iterations: params.iterations, salt: [0; 16], nonce: [0; 8], chunk_size: params.chunk_size};
+	thread_rng().fill(&mut header.salt);
+	thread_rng().fill(&mut header.nonce);
+	let header_bytes = header.to_bytes();
+	let (key, mac_key) = derive_keys(password, &header);
+	writer.write_all(&header_bytes).map_err(|e| format!("write failed: {}", e))?;
+
+	// read one chunk ahead, so we know which one is last
+	let chunk_size = params.chunk_size as usize;
+	let mut current = vec![0u8; chunk_size];
+	let len = read_up_to(reader, &mut current)?;
+	current.truncate(len);
+	let mut index: u64 = 0;
+	loop {
+		let mut next = vec![0u8; chunk_size];
+		let next_len = match current.len() == chunk_size {
+			true => read_up_to(reader, &mut next)?,
+			false => 0,
+		};
+		next.truncate(next_len);
+		let last = next.is_empty();
+
+		let ciphertext = ex18::encrypt_ctr(&current, &key, &header.chunk_nonce(index));
+		let mut record = vec![last as u8];
+		record.extend_from_slice(&(ciphertext.len() as u32).to_be_bytes());
+		record.extend_from_slice(&ciphertext);
+		record.append(&mut chunk_tag(&mac_key, &header_bytes, index, last, &ciphertext));
+		writer.write_all(&record).map_err(|e| format!("write failed: {}", e))?;
+
+		if last {
+			break;
+		}
+		current = next;
+		index += 1;
+	}
+	writer.flush().map_err(|e| format!("write failed: {}", e))
+}
+
+pub fn decrypt_stream<R: Read, W: Write>(reader: &mut R, writer: &mut W, password: &[u8]) -> Result<(), String> {
+	let header_bytes = read_exactly(reader, HEADER_LEN, "header")?;
+	let header = Header::from_bytes(&header_bytes)?;
+	let (key, mac_key) = derive_keys(password, &header);
+
+	let mut index: u64 = 0;
+	loop {
+		let chunk_header = read_exactly(reader, 5, "chunk header")?;
+		let last = match chunk_header[0] {
+			0 => false,
+			1 => true,
+			f => return Err(format!("chunk {}: invalid final flag {}", index, f)),
+		};
+		let len = u32::from_be_bytes([chunk_header[1], chunk_header[2], chunk_header[3], chunk_header[4]]);
+		if len > header.chunk_size || (!last && len != header.chunk_size) {
+			return Err(format!("chunk {}: invalid length {}", index, len));
+		}
+		let ciphertext = read_exactly(reader, len as usize, "chunk")?;
+		let tag = read_exactly(reader, TAG_LEN, "chunk tag")?;
+		if !constant_time_eq(&chunk_tag(&mac_key, &header_bytes, index, last, &ciphertext), &tag) {
+			return Err(format!("chunk {}: authentication failed (wrong password or tampered container)", index));
+		}
+		let plaintext = ex18::encrypt_ctr(&ciphertext, &key, &header.chunk_nonce(index));
+		writer.write_all(&plaintext).map_err(|e| format!("write failed: {}", e))?;
+
+		if last {
+			break;
+		}
+		index += 1;
+	}
+	let mut trailing = [0u8; 1];
+	if read_up_to(reader, &mut trailing)? != 0 {
+		return Err("trailing data after final chunk".to_string());
+	}
+	writer.flush().map_err(|e| format!("write failed: {}", e))
+}
+
+pub fn encrypt_file(input: &str, output: &str, password: &[u8], params: &ContainerParams) -> Result<(), String> {
+	let mut reader = BufReader::new(File::open(input).map_err(|e| format!("could not open {}: {}", input, e))?);
+	let mut writer = BufWriter::new(File::create(output).map_err(|e| format!("could not create {}: {}", output, e))?);
+	encrypt_stream(&mut reader, &mut writer, password, params)
+}
+
+pub fn decrypt_file(input: &str, output: &str, password: &[u8]) -> Result<(), String> {
+	let partial = format!("{}.partial", output);
+	let res = (|| {
+		let mut reader = BufReader::new(File::open(input).map_err(|e| format!("could not open {}: {}", input, e))?);
+		let mut writer = BufWriter::new(File::create(&partial).map_err(|e| format!("could not create {}: {}", partial, e))?);
+		decrypt_stream(&mut reader, &mut writer, password)
+	})();
+	match res {
+		Ok(()) => fs::rename(&partial, output).map_err(|e| format!("could not rename {} to {}: {}", partial, output, e)),
+		Err(e) => {
+			let _ = fs::remove_file(&partial);
+			Err(e)
+		},
+	}
+}
+
+// rustpals container encrypt|decrypt <input> <output> <password>
+pub fn run_cli(args: &[String]) -> Result<(), String> {
+	let usage = "usage: container encrypt|decrypt <input> <output> <password>".to_string();
+	match args {
+		[command, input, output, password] => match command.as_str() {
+			"encrypt" => encrypt_file(input, output, password.as_bytes(), &ContainerParams::default()),
+			"decrypt" => decrypt_file(input, output, password.as_bytes()),
+			_ => Err(usage),
+		},
+		_ => Err(usage),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::container::*;
+	use crate::utils;
+
+	const PARAMS: ContainerParams = ContainerParams{iterations: 2, chunk_size: 16};
+
+	fn seal(plaintext: &[u8], password: &[u8]) -> Vec<u8> {
+		let mut res = vec![];
+		encrypt_stream(&mut &plaintext[..], &mut res, password, &PARAMS).unwrap();
+		res
+	}
+
+	fn open(container: &[u8], password: &[u8]) -> Result<Vec<u8>, String> {
+		let mut res = vec![];
+		decrypt_stream(&mut &container[..], &mut res, password).map(|_| res)
+	}
+
+	#[test]
+	fn test_pbkdf2() {
+		// rfc 6070
+		assert_eq!(utils::bytes_to_hex_string(&pbkdf2_hmac_sha1(b"password", b"salt", 1, 20)), "0c60c80f961f0e71f3a9b524af6012062fe037a6");
+		assert_eq!(utils::bytes_to_hex_string(&pbkdf2_hmac_sha1(b"password", b"salt", 4096, 20)), "4b007901b765489abead49d926f721d065a429c1");
+		assert_eq!(
+			utils::bytes_to_hex_string(&pbkdf2_hmac_sha1(b"passwordPASSWORDpassword", b"saltSALTsaltSALTsaltSALTsaltSALTsalt", 4096, 25)),
+			"3d2eec4fe41c849b80c8d83662c0e44a8b291a964cf2f07038"
+		);
+	}
+
+	#[test]
+	fn test_round_trip() {
+		for len in [0, 1, 15, 16, 17, 32, 100].iter() {
+			let plaintext: Vec<u8> = (0..*len).map(|i| i as u8).collect();
+			let container = seal(&plaintext, b"hunter2");
+			assert_eq!(open(&container, b"hunter2").unwrap(), plaintext);
+			assert!(open(&container, b"hunter3").is_err());
+		}
+	}
+
+	#[test]
+	fn test_header_tampering() {
+		let container = seal(b"attack at dawn, bring snacks and a towel", b"hunter2");
+		// one byte from each field: magic, version, cipher, mac, kdf, iterations, salt, nonce, chunk size
+		for i in [0, 4, 5, 6, 7, 11, 12, 27, 28, 35, 39].iter() {
+			let mut tampered = container.clone();
+			tampered[*i] ^= 1;
+			assert!(open(&tampered, b"hunter2").is_err(), "header byte {} not protected", i);
+		}
+		assert!(open(&container[..HEADER_LEN - 1], b"hunter2").is_err());
+	}
+
+	#[test]
+	fn test_chunk_tampering() {
+		let container = seal(b"attack at dawn, bring snacks and a towel", b"hunter2");
+		let record_len = 5 + 16 + TAG_LEN;
+		// final flag, length, ciphertext, tag of the first chunk
+		for i in [0, 4, 5, 20, 21, 40].iter() {
+			let mut tampered = container.clone();
+			tampered[HEADER_LEN + *i] ^= 1;
+			assert!(open(&tampered, b"hunter2").is_err(), "chunk byte {} not protected", i);
+		}
+
+		// swap the first two chunks
+		let mut swapped = container[..HEADER_LEN].to_vec();
+		swapped.extend_from_slice(&container[HEADER_LEN + record_len..HEADER_LEN + 2 * record_len]);
+		swapped.extend_from_slice(&container[HEADER_LEN..HEADER_LEN + record_len]);
+		swapped.extend_from_slice(&container[HEADER_LEN + 2 * record_len..]);
+		assert!(open(&swapped, b"hunter2").is_err());
+
+		// cut off at a chunk boundary, cut off mid-chunk, extra data on the end
+		assert!(open(&container[..HEADER_LEN + 2 * record_len], b"hunter2").is_err());
+		assert!(open(&container[..container.len() - 1], b"hunter2").is_err());
+		let mut extended = container.clone();
+		extended.push(0);
+		assert!(open(&extended, b"hunter2").is_err());
+
+		// a chunk from a different container under the same password
+		let other = seal(b"attack at dusk, bring snacks and a towel", b"hunter2");
+		let mut spliced = container[..HEADER_LEN].to_vec();
+		spliced.extend_from_slice(&other[HEADER_LEN..]);
+		assert!(open(&spliced, b"hunter2").is_err());
+	}
+
+	#[test]
+	fn test_files() {
+		let dir = std::env::temp_dir();
+		let path = |name: &str| dir.join(format!("rustpals_container_{}", name)).to_str().unwrap().to_string();
+		let plaintext: Vec<u8> = (0..100_000).map(|i| (i % 251) as u8).collect();
+		File::create(path("plain")).unwrap().write_all(&plaintext).unwrap();
+
+		encrypt_file(&path("plain"), &path("sealed"), b"hunter2", &PARAMS).unwrap();
+		decrypt_file(&path("sealed"), &path("opened"), b"hunter2").unwrap();
+		assert_eq!(utils::read_file(&path("opened")), plaintext);
+
+		// a failed decrypt leaves nothing behind
+		assert!(decrypt_file(&path("sealed"), &path("wrong"), b"hunter3").is_err());
+		assert!(File::open(path("wrong")).is_err());
+		assert!(File::open(path("wrong.partial")).is_err());
+		for name in ["plain", "sealed", "opened"].iter() {
+			fs::remove_file(path(name)).unwrap();
+		}
+	}
+}
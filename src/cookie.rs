@@ -0,0 +1,283 @@
+/*
+
+Key-value cookie codec
+
+The k=v&k=v format from ex13 (and k=v;k=v from ex16), done properly: pairs keep their order,
+duplicate keys are handled according to an explicit policy instead of silently overwriting each
+other in a HashMap, metacharacters in keys and values are percent-escaped rather than eaten, and
+anything malformed is an error that says where it went wrong.
+
+Only the metacharacters themselves (and %) are escaped. Everything else passes through untouched,
+which keeps encoded values byte-for-byte predictable for the oracles built on top of this.
+
+*/
+
+use std::fmt;
+
+const ESCAPED: &[u8] = b"%&;=";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+	Ampersand,	// k=v&k=v
+	Semicolon,	// k=v;k=v
+}
+
+impl Format {
+	fn separator(&self) -> u8 {
+		match self {
+			Format::Ampersand => b'&',
+			Format::Semicolon => b';',
+		}
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DuplicatePolicy {
+	Reject,
+	KeepLast,	// the later pair replaces the earlier one, and takes its place in the order
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseErrorKind {
+	EmptyPair,
+	MissingEquals,
+	ExtraEquals,
+	EmptyKey,
+	BadEscape,
+	InvalidUtf8,
+	DuplicateKey(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+	pub offset: usize,	// byte offset into the input
+	pub kind: ParseErrorKind,
+}
+
+impl fmt::Display for ParseError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let description = match &self.kind {
+			ParseErrorKind::EmptyPair => "empty pair".to_string(),
+			ParseErrorKind::MissingEquals => "pair has no '='".to_string(),
+			ParseErrorKind::ExtraEquals => "unescaped '=' in value".to_string(),
+			ParseErrorKind::EmptyKey => "empty key".to_string(),
+			ParseErrorKind::BadEscape => "invalid percent escape".to_string(),
+			ParseErrorKind::InvalidUtf8 => "invalid utf-8 after unescaping".to_string(),
+			ParseErrorKind::DuplicateKey(k) => format!("duplicate key {:?}", k),
+		};
+		write!(f, "{} at offset {}", description, self.offset)
+	}
+}
+
+pub fn escape(inp: &[u8]) -> Vec<u8> {
+	let mut res = vec![];
+	for c in inp.iter() {
+		match ESCAPED.contains(c) {
+			true => res.extend_from_slice(format!("%{:02X}", c).as_bytes()),
+			false => res.push(*c),
+		}
+	}
+	res
+}
+
+// offset is where inp starts in the whole cookie, for error reporting
+pub fn unescape(inp: &[u8], offset: usize) -> Result<String, ParseError> {
+	let mut res = vec![];
+	let mut i = 0;
+	while i < inp.len() {
+		if inp[i] == b'%' {
+			let byte = inp.get(i + 1..i + 3)
+				.and_then(|h| std::str::from_utf8(h).ok())
+				.filter(|h| h.bytes().all(|c| c.is_ascii_hexdigit()))
+				.and_then(|h| u8::from_str_radix(h, 16).ok());
+			match byte {
+				Some(b) => res.push(b),
+				None => return Err(ParseError{offset: offset + i, kind: ParseErrorKind::BadEscape}),
+			}
+			i += 3;
+		} else {
+			res.push(inp[i]);
+			i += 1;
+		}
+	}
+	String::from_utf8(res).map_err(|_| ParseError{offset: offset, kind: ParseErrorKind::InvalidUtf8})
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Cookie {
+	pairs: Vec<(String, String)>,
+}
+
+impl Cookie {
+	pub fn new() -> Cookie {
+		Cookie{pairs: vec![]}
+	}
+
+	pub fn push(&mut self, key: &str, value: &str) {
+		self.pairs.push((key.to_string(), value.to_string()));
+	}
+
+	// first value for key
+	pub fn get(&self, key: &str) -> Option<&str> {
+		self.pairs.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str())
+	}
+
+	pub fn encode(&self, format: Format) -> String {
+		let mut res: Vec<u8> = vec![];
+		for (i, (k, v)) in self.pairs.iter().enumerate() {
+			if i != 0 {
+				res.push(format.separator());
+			}
+			res.append(&mut escape(k.as_bytes()));
+			res.push(b'=');
+			res.append(&mut escape(v.as_bytes()));
+		}
+		// only ascii bytes were added to what was already utf-8
+		String::from_utf8(res).expect("escaping produced invalid utf-8")
+	}
+
+	pub fn parse(inp: &str, format: Format, policy: DuplicatePolicy) -> Result<Cookie, ParseError> {
+		let mut pairs: Vec<(String, String, usize)> = vec![];
+		if inp.is_empty() {
+			return Ok(Cookie::new());
+		}
+
+		let bytes = inp.as_bytes();
+		let mut start = 0;
+		for segment in bytes.split(|c| *c == format.separator()) {
+			if segment.is_empty() {
+				return Err(ParseError{offset: start, kind: ParseErrorKind::EmptyPair});
+			}
+			let mut parts = segment.splitn(2, |c| *c == b'=');
+			let (raw_key, raw_value) = match (parts.next(), parts.next()) {
+				(Some(k), Some(v)) => (k, v),
+				_ => return Err(ParseError{offset: start + segment.len(), kind: ParseErrorKind::MissingEquals}),
+			};
+			if raw_key.is_empty() {
+				return Err(ParseError{offset: start, kind: ParseErrorKind::EmptyKey});
+			}
+			let value_start = start + raw_key.len() + 1;
+			if let Some(i) = raw_value.iter().position(|c| *c == b'=') {
+				return Err(ParseError{offset: value_start + i, kind: ParseErrorKind::ExtraEquals});
+			}
+			let key = unescape(raw_key, start)?;
+			let value = unescape(raw_value, value_start)?;
+
+			match (policy, pairs.iter().position(|(k, _, _)| *k == key)) {
+				(DuplicatePolicy::Reject, Some(_)) => {
+					return Err(ParseError{offset: start, kind: ParseErrorKind::DuplicateKey(key)});
+				},
+				(DuplicatePolicy::KeepLast, Some(i)) => {
+					pairs.remove(i);
+					pairs.push((key, value, start));
+				},
+				_ => pairs.push((key, value, start)),
+			}
+			start += segment.len() + 1;
+		}
+		Ok(Cookie{pairs: pairs.into_iter().map(|(k, v, _)| (k, v)).collect()})
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Role {
+	User,
+	Admin,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct UserProfile {
+	pub email: String,
+	pub uid: u32,
+	pub role: Role,
+}
+
+impl UserProfile {
+	pub fn to_cookie(&self) -> Cookie {
+		let mut cookie = Cookie::new();
+		cookie.push("email", &self.email);
+		cookie.push("uid", &self.uid.to_string());
+		cookie.push("role", match self.role {
+			Role::User => "user",
+			Role::Admin => "admin",
+		});
+		cookie
+	}
+
+	pub fn from_cookie(cookie: &Cookie) -> Result<UserProfile, String> {
+		let field = |name: &str| cookie.get(name).ok_or(format!("missing field {}", name));
+		let uid = field("uid")?;
+		Ok(UserProfile {
+			email: field("email")?.to_string(),
+			uid: uid.parse().map_err(|_| format!("invalid uid {:?}", uid))?,
+			role: match field("role")? {
+				"user" => Role::User,
+				"admin" => Role::Admin,
+				r => return Err(format!("unknown role {:?}", r)),
+			},
+		})
+	}
+
+	pub fn encode(&self, format: Format) -> String {
+		self.to_cookie().encode(format)
+	}
+
+	// duplicates are rejected here: "which role= wins" is exactly the question an attacker wants to be ambiguous
+	pub fn decode(inp: &str, format: Format) -> Result<UserProfile, String> {
+		let cookie = Cookie::parse(inp, format, DuplicatePolicy::Reject).map_err(|e| e.to_string())?;
+		UserProfile::from_cookie(&cookie)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::cookie::*;
+
+	#[test]
+	fn test_escaping_round_trip() {
+		let mut cookie = Cookie::new();
+		cookie.push("email", "foo@bar.com&role=admin");
+		cookie.push("comment", "100%; sure");
+		cookie.push("a=b", "");
+		for format in [Format::Ampersand, Format::Semicolon].iter() {
+			let encoded = cookie.encode(*format);
+			assert!(!encoded.contains("&role=admin"));
+			assert_eq!(Cookie::parse(&encoded, *format, DuplicatePolicy::Reject).unwrap(), cookie);
+		}
+		assert_eq!(cookie.encode(Format::Ampersand), "email=foo@bar.com%26role%3Dadmin&comment=100%25%3B sure&a%3Db=");
+	}
+
+	#[test]
+	fn test_order_and_duplicates() {
+		let unique = "zap=1&foo=2&bar=4";
+		assert_eq!(Cookie::parse(unique, Format::Ampersand, DuplicatePolicy::Reject).unwrap().encode(Format::Ampersand), unique);
+		let inp = "zap=1&foo=2&zap=3&bar=4";
+		let last = Cookie::parse(inp, Format::Ampersand, DuplicatePolicy::KeepLast).unwrap();
+		assert_eq!(last.encode(Format::Ampersand), "foo=2&zap=3&bar=4");
+		assert_eq!(last.get("zap"), Some("3"));
+		let rejected = Cookie::parse(inp, Format::Ampersand, DuplicatePolicy::Reject).unwrap_err();
+		assert_eq!(rejected, ParseError{offset: 12, kind: ParseErrorKind::DuplicateKey("zap".to_string())});
+	}
+
+	#[test]
+	fn test_parse_errors() {
+		let parse = |inp| Cookie::parse(inp, Format::Semicolon, DuplicatePolicy::Reject).unwrap_err();
+		assert_eq!(parse("a=1;;b=2"), ParseError{offset: 4, kind: ParseErrorKind::EmptyPair});
+		assert_eq!(parse("a=1;b"), ParseError{offset: 5, kind: ParseErrorKind::MissingEquals});
+		assert_eq!(parse("a=1;=2"), ParseError{offset: 4, kind: ParseErrorKind::EmptyKey});
+		assert_eq!(parse("a=1;b=2=3"), ParseError{offset: 7, kind: ParseErrorKind::ExtraEquals});
+		assert_eq!(parse("a=1;b=%4"), ParseError{offset: 6, kind: ParseErrorKind::BadEscape});
+		assert_eq!(parse("a=%zz"), ParseError{offset: 2, kind: ParseErrorKind::BadEscape});
+		assert_eq!(parse("a=%ff"), ParseError{offset: 2, kind: ParseErrorKind::InvalidUtf8});
+	}
+
+	#[test]
+	fn test_user_profile_round_trip() {
+		let profile = UserProfile{email: "foo@bar.com&role=admin".to_string(), uid: 10, role: Role::User};
+		let encoded = profile.encode(Format::Ampersand);
+		assert_eq!(encoded, "email=foo@bar.com%26role%3Dadmin&uid=10&role=user");
+		assert_eq!(UserProfile::decode(&encoded, Format::Ampersand).unwrap(), profile);
+		assert!(UserProfile::decode("email=a&uid=10&role=user&role=admin", Format::Ampersand).is_err());
+		assert!(UserProfile::decode("email=a&uid=ten&role=user", Format::Ampersand).is_err());
+	}
+}
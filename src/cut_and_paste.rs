@@ -0,0 +1,204 @@
+/*
+
+Generic ECB cut-and-paste
+
+ex13 forges role=admin by hand: count out "email=" so "admin" plus padding lands in its own block,
+count out "email=...&uid=10&role=" so "user" lands in the last block, swap. That only works for the
+exact layout it was written against.
+
+ECB encrypts every block on its own, so any token we can describe block by block is forgeable as
+long as each of its blocks shows up, block-aligned, in some ciphertext the oracle will give us. We
+know the plaintext the oracle builds around our input (that's the template), so we can keep a
+catalog of plaintext block -> ciphertext block from everything it hands back. Blocks of fixed
+template text turn up by shifting our input one byte at a time; blocks we need that aren't in the
+template get submitted directly, lined up inside the field we control, as long as the encoder
+passes them through unchanged. Then it's just a matter of finding a length for our own field that
+makes every block of the desired token one we have.
+
+The block right after the new value is a problem if the target isn't the last field: it starts with
+the rest of our value and then a separator, which we can't submit ourselves if the encoder escapes
+or strips separators. So a value in the middle of the token has to come out to a whole number of blocks.
+
+*/
+
+use crate::analysis;
+use crate::cookie;
+use crate::oracle::Oracle;
+use crate::set2::ex9;
+use std::collections::HashMap;
+
+pub struct TokenTemplate {
+	pub fields: Vec<(Vec<u8>, Vec<u8>)>,	// in encoded order. the controlled field's value is ignored
+	pub controlled: usize,					// index into fields of the one the attacker sets
+	pub pair_separator: u8,
+	pub kv_separator: u8,
+	pub value_encoder: fn(&[u8]) -> Vec<u8>,	// what the encoder does to the controlled value (escape, strip...)
+}
+
+impl TokenTemplate {
+	// email=<controlled>&uid=10&role=user, as built by ex13::profile_for
+	pub fn ex13_profile() -> TokenTemplate {
+		TokenTemplate {
+			fields: vec![
+				(b"email".to_vec(), vec![]),
+				(b"uid".to_vec(), b"10".to_vec()),
+				(b"role".to_vec(), b"user".to_vec()),
+			],
+			controlled: 0,
+			pair_separator: b'&',
+			kv_separator: b'=',
+			value_encoder: cookie::escape,
+		}
+	}
+
+	// what the oracle will encrypt (minus padding) for a given controlled value, with optional field overrides
+	pub fn encode(&self, controlled_value: &[u8], overrides: &[(&[u8], &[u8])]) -> Vec<u8> {
+		let mut res = vec![];
+		for (i, (key, value)) in self.fields.iter().enumerate() {
+			if i != 0 {
+				res.push(self.pair_separator);
+			}
+			res.extend_from_slice(key);
+			res.push(self.kv_separator);
+			if i == self.controlled {
+				res.append(&mut (self.value_encoder)(controlled_value));
+			} else {
+				match overrides.iter().find(|(k, _)| *k == &key[..]) {
+					Some((_, v)) => res.extend_from_slice(v),
+					None => res.extend_from_slice(value),
+				}
+			}
+		}
+		res
+	}
+
+	// where the controlled value starts in the encoded token
+	pub fn controlled_offset(&self) -> usize {
+		// key, kv separator, value, pair separator for each field before it, then its own key and kv separator
+		self.fields[..self.controlled].iter().map(|(k, v)| k.len() + v.len() + 2).sum::<usize>()
+			+ self.fields[self.controlled].0.len() + 1
+	}
+}
+
+// returns the forged ciphertext, which decrypts to the template with target_field set to target_value
+pub fn forge_token<O: Oracle>(template: &TokenTemplate, target_field: &[u8], target_value: &[u8], oracle: &O) -> Result<Vec<u8>, String> {
+	if !template.fields.iter().any(|(k, _)| k == target_field) {
+		return Err(format!("template has no field {}", String::from_utf8_lossy(target_field)));
+	}
+	// we can already set that one, and encode would ignore the override for it anyway
+	if template.fields[template.controlled].0 == target_field {
+		return Err(format!("{} is the field we control, just submit the value", String::from_utf8_lossy(target_field)));
+	}
+	let profile = analysis::analyse(oracle)?;
+	if profile.mode != analysis::ModeGuess::Ecb {
+		return Err(format!("cut and paste needs ecb, oracle looks like {:?}", profile.mode));
+	}
+	let block_size = profile.block_size;
+	let offset = template.controlled_offset();
+	if profile.prefix_len != Some(offset) {
+		return Err(format!("template puts controlled value at {}, oracle at {:?}", offset, profile.prefix_len));
+	}
+
+	let mut catalog: HashMap<Vec<u8>, Vec<u8>> = HashMap::new();
+	let submit = |controlled_value: &[u8], catalog: &mut HashMap<Vec<u8>, Vec<u8>>| -> Result<(), String> {
+		let plaintext = ex9::pkcs7_padding(&template.encode(controlled_value, &[]), block_size);
+		let ciphertext = oracle.query(controlled_value)?;
+		for (p, c) in plaintext.chunks(block_size).zip(ciphertext.chunks(block_size)) {
+			catalog.entry(p.to_vec()).or_insert(c.to_vec());
+		}
+		Ok(())
+	};
+
+	// every alignment of the fixed template text
+	for shift in 0..block_size {
+		submit(&vec![b'A'; shift], &mut catalog)?;
+	}
+
+	let align = (block_size - offset % block_size) % block_size;
+	for filler_len in 0..block_size {
+		let filler = vec![b'A'; filler_len];
+		let desired = ex9::pkcs7_padding(&template.encode(&filler, &[(target_field, target_value)]), block_size);
+
+		// whatever's missing, try to get it by lining it up inside our own field
+		let mut usable = true;
+		for block in desired.chunks(block_size) {
+			if catalog.contains_key(block) {
+				continue;
+			}
+			if (template.value_encoder)(block) != block {
+				usable = false;
+				break;
+			}
+			let mut chosen = vec![b'A'; align];
+			chosen.extend_from_slice(block);
+			submit(&chosen, &mut catalog)?;
+		}
+		if !usable {
+			continue;
+		}
+
+		return Ok(desired.chunks(block_size).flat_map(|block| catalog[block].clone()).collect());
+	}
+	Err("no alignment lets every block of the forged token be produced".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::cut_and_paste::{forge_token, TokenTemplate};
+	use crate::set2::{ex9, ex10, ex11, ex15};
+	use crate::set1::ex7;
+
+	fn ecb_oracle_for<'a>(template: &'a TokenTemplate, key: &'a [u8; 16]) -> impl Fn(&[u8]) -> Vec<u8> + 'a {
+		move |inp: &[u8]| {
+			let mut padded = ex9::pkcs7_padding(&template.encode(inp, &[]), 16);
+			ex10::encrypt_aes128ecb(&mut padded, key).unwrap();
+			padded
+		}
+	}
+
+	fn decrypt(ciphertext: &[u8], key: &[u8; 16]) -> Vec<u8> {
+		let mut decrypted = ciphertext.to_vec();
+		ex7::decrypt_aes128ecb(&mut decrypted, key).unwrap();
+		ex15::strip_padding(&decrypted).unwrap()
+	}
+
+	#[test]
+	fn test_ex13_layout() {
+		let key = ex11::gen_aes128_key();
+		let template = TokenTemplate::ex13_profile();
+		let forged = forge_token(&template, b"role", b"admin", &ecb_oracle_for(&template, &key)).unwrap();
+		let token = String::from_utf8(decrypt(&forged, &key)).unwrap();
+		assert!(token.starts_with("email="));
+		assert!(token.ends_with("&uid=10&role=admin"));
+
+		assert!(forge_token(&template, b"email", b"admin@example.com", &ecb_oracle_for(&template, &key)).is_err());
+		assert!(forge_token(&template, b"nope", b"admin", &ecb_oracle_for(&template, &key)).is_err());
+	}
+
+	#[test]
+	fn test_other_layouts() {
+		let key = ex11::gen_aes128_key();
+		// different separators, controlled field not first
+		let template = TokenTemplate {
+			fields: vec![
+				(b"session".to_vec(), b"3f9a0c".to_vec()),
+				(b"user".to_vec(), vec![]),
+				(b"expires".to_vec(), b"1546300800".to_vec()),
+				(b"admin".to_vec(), b"false".to_vec()),
+			],
+			controlled: 1,
+			pair_separator: b';',
+			kv_separator: b':',
+			value_encoder: |v| v.iter().filter(|c| **c != b';' && **c != b':').cloned().collect(),
+		};
+		let forged = forge_token(&template, b"admin", b"true", &ecb_oracle_for(&template, &key)).unwrap();
+		let token = String::from_utf8(decrypt(&forged, &key)).unwrap();
+		assert!(token.starts_with("session:3f9a0c;user:"));
+		assert!(token.ends_with(";expires:1546300800;admin:true"));
+
+		// target in the middle works when the new value fills whole blocks
+		let forged = forge_token(&template, b"expires", b"9999999999999999", &ecb_oracle_for(&template, &key)).unwrap();
+		let token = String::from_utf8(decrypt(&forged, &key)).unwrap();
+		assert!(token.ends_with(";expires:9999999999999999;admin:false"));
+	}
+}
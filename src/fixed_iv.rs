@@ -0,0 +1,111 @@
+/*
+
+Byte-at-a-time decryption of CBC with a fixed IV
+
+ex16 encrypts everything under iv = 0. CBC is supposed to make identical plaintexts encrypt
+differently, but that's the IV's job: with the same key and IV every time, two plaintexts that share
+their first n blocks also share their first n ciphertext blocks. The chaining only matters from the
+first block where they differ.
+
+That's all the ex12 attack needs. Line the secret up so its next unknown byte is the last byte of
+block j, then try all 256 values in our own input: the right guess makes the plaintexts identical
+through block j, so the ciphertexts first differ somewhere after it. Same number of queries as
+against ECB. The only thing it doesn't give away is repeated blocks further along.
+
+A random IV per message stops it dead: no two ciphertexts share anything.
+
+*/
+
+use crate::analysis;
+use crate::oracle::{InstrumentedOracle, Oracle};
+use crate::set2::{ex9, ex10, ex11, ex12};
+use rand::{Rng, thread_rng};
+
+// E(prefix || input || secret) under aes-128-cbc
+pub struct CbcSecretEncrypter {
+	key: [u8; 16],
+	iv: Option<[u8; 16]>,	// None for a fresh random iv per message, sent in front of the ciphertext
+	prefix: Vec<u8>,
+	secret: Vec<u8>,
+}
+
+impl CbcSecretEncrypter {
+	pub fn new(prefix: &[u8], secret: &[u8], iv: Option<[u8; 16]>) -> CbcSecretEncrypter {
+		CbcSecretEncrypter{key: ex11::gen_aes128_key(), iv: iv, prefix: prefix.to_vec(), secret: secret.to_vec()}
+	}
+}
+
+impl Oracle for CbcSecretEncrypter {
+	fn query(&self, inp: &[u8]) -> Result<Vec<u8>, String> {
+		let mut plaintext = self.prefix.clone();
+		plaintext.extend_from_slice(inp);
+		plaintext.extend_from_slice(&self.secret);
+		let padded = ex9::pkcs7_padding(&plaintext, 16);
+		Ok(match self.iv {
+			Some(iv) => ex10::encrypt_aes128cbc(&padded, &self.key, &iv),
+			None => {
+				let mut iv = [0u8; 16];
+				thread_rng().fill(&mut iv);
+				let mut res = iv.to_vec();
+				res.append(&mut ex10::encrypt_aes128cbc(&padded, &self.key, &iv));
+				res
+			},
+		})
+	}
+}
+
+pub fn decrypt_secret_with_fixed_iv<O: Oracle>(oracle: &O) -> Result<Vec<u8>, String> {
+	let profile = analysis::analyse(oracle)?;
+	if !profile.deterministic || profile.length_preserving {
+		return Err("oracle isn't a deterministic block cipher, the iv must be changing".to_string());
+	}
+	let prefix_len = profile.prefix_len.ok_or("could not determine prefix length")?;
+	let secret_len = profile.suffix_len.ok_or("could not determine secret length")?;
+	// from here it's ex12's loop unchanged, a ciphertext that matches through the guessed byte's block
+	ex12::byte_at_a_time(oracle, profile.block_size, prefix_len, secret_len)
+}
+
+// the same secret through ecb, cbc with ex16's zero iv, and cbc with a random iv
+pub fn demonstrate() {
+	let prefix = b"comment1=cooking%20MCs;userdata=";
+	let secret = ex12::suffix();
+
+	let ske = ex12::SingleKeyEncrypter::new();
+	let ecb = InstrumentedOracle::new(|inp: &[u8]| {
+		let mut plaintext = prefix.to_vec();
+		plaintext.extend_from_slice(inp);
+		ske.encrypt_aes128ecb(&mut plaintext, Some(secret.clone()))
+	}, None);
+	let fixed = InstrumentedOracle::new(CbcSecretEncrypter::new(prefix, &secret, Some([0; 16])), None);
+	let random = InstrumentedOracle::new(CbcSecretEncrypter::new(prefix, &secret, None), None);
+
+	let report = |name: &str, result: Result<Vec<u8>, String>, queries: usize| match result {
+		Ok(recovered) => println!("{}: recovered secret: {} ({} queries)", name, recovered == secret, queries),
+		Err(e) => println!("{}: attack failed: {} ({} queries)", name, e, queries),
+	};
+	report("ecb", ex12::decrypt_suffix_with_oracle(&ecb), ecb.stats().queries);
+	report("cbc, fixed iv", decrypt_secret_with_fixed_iv(&fixed), fixed.stats().queries);
+	report("cbc, random iv", decrypt_secret_with_fixed_iv(&random), random.stats().queries);
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::fixed_iv::{decrypt_secret_with_fixed_iv, CbcSecretEncrypter};
+
+	#[test]
+	fn test_fixed_iv() {
+		let secret = b"session=4f1c8a2be9d07735; admin=false";
+		for prefix in [&b""[..], b"comment1=cooking%20MCs;userdata=", b"seventeen bytes!!"].iter() {
+			let oracle = CbcSecretEncrypter::new(prefix, secret, Some([0; 16]));
+			assert_eq!(decrypt_secret_with_fixed_iv(&oracle).unwrap(), secret.to_vec());
+			let oracle = CbcSecretEncrypter::new(prefix, secret, Some([0x5a; 16]));
+			assert_eq!(decrypt_secret_with_fixed_iv(&oracle).unwrap(), secret.to_vec());
+		}
+	}
+
+	#[test]
+	fn test_random_iv() {
+		let oracle = CbcSecretEncrypter::new(b"", b"session=4f1c8a2be9d07735", None);
+		assert!(decrypt_secret_with_fixed_iv(&oracle).is_err());
+	}
+}
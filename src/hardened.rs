@@ -0,0 +1,198 @@
+/*
+
+Hardened oracles
+
+Every oracle in the sets is broken on purpose. Each one takes a config with the fixes available as
+switches, defaulting to the exercise as written, so every attack can be run against the vulnerable
+version and the fixed one side by side:
+
+ex12::SingleKeyEncrypter, ex14::SKEWithPrefix	EncrypterConfig: random_iv, cbc with a fresh iv instead of ecb
+ex16::CookieService								CookieConfig: random_iv, encrypt_then_mac
+ex25::CtrEncrypter								EditConfig: per_message_nonce
+ex27::Receiver									ReceiverConfig: random_iv, generic_errors, encrypt_then_mac
+ex28::Sha1KeyedMac								MacConfig: hmac, constant_time_compare
+
+Not every switch stops every attack: a random iv does nothing for ex16, which only needs to flip bits
+in a ciphertext block it can already see. The tests below are the matrix.
+
+MacConfig's compare_delay is slept for every byte the comparison looks at, like ex31's insecure_compare.
+Here the sleeping is done on a simulated clock, so the timing rows are exact and don't take any real
+time; the constant-time compare is charged for every byte too, which is the point: it never stops early.
+The real thing, over a network, is ex31/ex32.
+
+*/
+
+use crate::bitflip;
+use crate::key_iv;
+use crate::set2::{ex11, ex12, ex14, ex16};
+use crate::set4::{ex25, ex27, ex28, ex29, ex31};
+use std::cell::Cell;
+use std::time::Duration;
+
+// per byte compared, in the timing rows
+const COMPARE_DELAY: Duration = Duration::from_millis(1);
+
+// how long authenticate takes, on a clock that only moves when the comparison sleeps
+fn time_authenticate(mac: &ex28::Sha1KeyedMac, message: &[u8], tag: &[u8]) -> Duration {
+	let clock = Cell::new(Duration::from_secs(0));
+	mac.authenticate_with_sleep(&tag.to_vec(), &message.to_vec(), |d| clock.set(clock.get() + d));
+	clock.get()
+}
+
+fn outcome<T>(attack: Result<T, String>, succeeded: impl Fn(&T) -> bool) -> &'static str {
+	match attack {
+		Ok(ref res) if succeeded(res) => "broken",
+		_ => "held",
+	}
+}
+
+// every attack against its oracle, unprotected and then with all the switches on
+pub fn demonstrate() {
+	let secret = b"session=4f1c8a2be9d07735; admin=false";
+	for config in [ex12::EncrypterConfig::default(), ex12::EncrypterConfig{random_iv: true}].iter() {
+		let skewp = ex14::SKEWithPrefix::with_config(*config);
+		let oracle = |inp: &[u8]| skewp.encrypt(&mut inp.to_vec(), Some(secret.to_vec()));
+		let res = ex12::decrypt_suffix_with_oracle(&oracle);
+		println!("suffix encrypter, {:?}: {}", config, outcome(res, |r| r[..] == secret[..]));
+	}
+
+	for config in [ex16::CookieConfig::default(), ex16::CookieConfig{random_iv: true, encrypt_then_mac: true}].iter() {
+		let service = ex16::CookieService::new(*config);
+		let oracle = |inp: &[u8]| service.encrypt(inp);
+		let verify = |ct: &[u8]| service.is_admin(ct).unwrap_or(false);
+		let prefix_len = if config.random_iv { 48 } else { 32 };
+		let res = bitflip::inject(&oracle, &verify, b";admin=true", Some(bitflip::FlipMode::Cbc), Some(prefix_len));
+		println!("cookie service, {:?}: {}", config, outcome(res, |_| true));
+	}
+
+	for config in [ex25::EditConfig::default(), ex25::EditConfig{per_message_nonce: true}].iter() {
+		let plaintext = b"attack at dawn, bring snacks".to_vec();
+		let encrypter = ex25::CtrEncrypter::with_config(&ex11::gen_aes128_key().to_vec(), &ex11::gen_aes128_key()[..8].to_vec(), *config);
+		let encrypted = encrypter.encrypt(&plaintext);
+		let res = ex25::recover_plaintext_with_edit_oracle(&encrypted, &ex25::EditOracle{encrypter: &encrypter, encrypted: &encrypted});
+		println!("edit service, {:?}: {}", config, outcome(res, |r| *r == plaintext));
+	}
+
+	for config in [ex27::ReceiverConfig::default(), ex27::ReceiverConfig{random_iv: true, generic_errors: true, encrypt_then_mac: true}].iter() {
+		let receiver = ex27::Receiver::new(*config);
+		let captured = receiver.seal(b"foo");
+		let res = key_iv::recover_key(&receiver, &ex27::extract_leak, &key_iv::decrypt_aes128cbc_key_as_iv, &captured, 16);
+		println!("key-as-iv receiver, {:?}: {}", config, outcome(res, |r| r.key[..] == receiver.key()[..]));
+	}
+
+	let message = b"user=bob".to_vec();
+	let unprotected = ex28::MacConfig{compare_delay: COMPARE_DELAY, ..ex28::MacConfig::default()};
+	for config in [unprotected, ex28::MacConfig{hmac: true, constant_time_compare: true, ..unprotected}].iter() {
+		let mac = ex28::Sha1KeyedMac::with_config(&b"YELLOW SUBMARINE".to_vec(), *config);
+		let tag = mac.gen(&message);
+		let recovered = ex31::recover_tag_by_timing(|t| Ok(time_authenticate(&mac, &message, t)), tag.len(), tag.len());
+		println!("mac timing, {:?}: {}", config, outcome(recovered, |r| *r == tag));
+		let forged = (0..32).any(|key_len| {
+			let (forgery, forged_tag) = ex29::extend_sha1_mac(&message, &tag, b"&admin=true", key_len);
+			mac.authenticate(&forged_tag, &forgery)
+		});
+		println!("mac length extension, {:?}: {}", config, outcome(Ok(forged), |f| *f));
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::bitflip;
+	use crate::hardened::*;
+	use crate::key_iv;
+	use crate::set2::{ex11, ex12, ex14, ex16};
+	use crate::set4::{ex25, ex27, ex28, ex29, ex31};
+
+	#[test]
+	fn test_suffix_encrypter() {
+		let secret = b"session=4f1c8a2be9d07735";
+		let attack = |config: ex12::EncrypterConfig| {
+			let ske = ex12::SingleKeyEncrypter::with_config(config);
+			let with_prefix = ex14::SKEWithPrefix::with_config(config);
+			let res = ex12::decrypt_suffix_with_oracle(&|inp: &[u8]| ske.encrypt(&mut inp.to_vec(), Some(secret.to_vec())));
+			let res_with_prefix = ex12::decrypt_suffix_with_oracle(&|inp: &[u8]| with_prefix.encrypt(&mut inp.to_vec(), Some(secret.to_vec())));
+			(res, res_with_prefix)
+		};
+		assert_eq!(attack(ex12::EncrypterConfig::default()), (Ok(secret.to_vec()), Ok(secret.to_vec())));
+		let (res, res_with_prefix) = attack(ex12::EncrypterConfig{random_iv: true});
+		assert!(res.is_err() && res_with_prefix.is_err());
+	}
+
+	#[test]
+	fn test_cookie_service() {
+		let attack = |config: ex16::CookieConfig| {
+			let service = ex16::CookieService::new(config);
+			let oracle = |inp: &[u8]| service.encrypt(inp);
+			let verify = |ct: &[u8]| service.is_admin(ct).unwrap_or(false);
+			let prefix_len = if config.random_iv { 48 } else { 32 };
+			bitflip::inject(&oracle, &verify, b";admin=true", Some(bitflip::FlipMode::Cbc), Some(prefix_len)).is_ok()
+		};
+		assert!(attack(ex16::CookieConfig::default()));
+		// the iv isn't what's being flipped
+		assert!(attack(ex16::CookieConfig{random_iv: true, encrypt_then_mac: false}));
+		assert!(!attack(ex16::CookieConfig{random_iv: false, encrypt_then_mac: true}));
+		assert!(!attack(ex16::CookieConfig{random_iv: true, encrypt_then_mac: true}));
+	}
+
+	#[test]
+	fn test_edit_service() {
+		let plaintext = b"attack at dawn, bring snacks".to_vec();
+		let attack = |config: ex25::EditConfig| {
+			let encrypter = ex25::CtrEncrypter::with_config(&ex11::gen_aes128_key().to_vec(), &ex11::gen_aes128_key()[..8].to_vec(), config);
+			let encrypted = encrypter.encrypt(&plaintext);
+			ex25::recover_plaintext_with_edit_oracle(&encrypted, &ex25::EditOracle{encrypter: &encrypter, encrypted: &encrypted})
+		};
+		assert_eq!(attack(ex25::EditConfig::default()), Ok(plaintext.clone()));
+		// no two edits come back under the same keystream, so not even the first byte matches
+		assert_eq!(attack(ex25::EditConfig{per_message_nonce: true}), Err("no edit reproduced the reference ciphertext for byte 0".to_string()));
+	}
+
+	#[test]
+	fn test_key_iv_receiver() {
+		let attack = |config: ex27::ReceiverConfig| {
+			let receiver = ex27::Receiver::new(config);
+			let captured = receiver.seal(b"foo");
+			match key_iv::recover_key(&receiver, &ex27::extract_leak, &key_iv::decrypt_aes128cbc_key_as_iv, &captured, 16) {
+				Ok(recovered) => recovered.key[..] == receiver.key()[..],
+				Err(_) => false,
+			}
+		};
+		assert!(attack(ex27::ReceiverConfig::default()));
+		assert!(!attack(ex27::ReceiverConfig{random_iv: true, ..ex27::ReceiverConfig::default()}));
+		assert!(!attack(ex27::ReceiverConfig{generic_errors: true, ..ex27::ReceiverConfig::default()}));
+		assert!(!attack(ex27::ReceiverConfig{encrypt_then_mac: true, ..ex27::ReceiverConfig::default()}));
+	}
+
+	#[test]
+	fn test_mac_length_extension() {
+		let message = b"comment1=cooking%20MCs;userdata=foo;comment2=%20like%20a%20pound%20of%20bacon".to_vec();
+		let attack = |config: ex28::MacConfig| {
+			let mac = ex28::Sha1KeyedMac::with_config(&b"YELLOW SUBMARINE".to_vec(), config);
+			let tag = mac.gen(&message);
+			(0..32).any(|key_len| {
+				let (forgery, forged_tag) = ex29::extend_sha1_mac(&message, &tag, b";admin=true", key_len);
+				mac.authenticate(&forged_tag, &forgery)
+			})
+		};
+		assert!(attack(ex28::MacConfig::default()));
+		assert!(attack(ex28::MacConfig{constant_time_compare: true, ..ex28::MacConfig::default()}));
+		assert!(!attack(ex28::MacConfig{hmac: true, ..ex28::MacConfig::default()}));
+	}
+
+	#[test]
+	fn test_mac_timing() {
+		let message = b"user=bob".to_vec();
+		let attack = |config: ex28::MacConfig| {
+			let mac = ex28::Sha1KeyedMac::with_config(&b"YELLOW SUBMARINE".to_vec(), ex28::MacConfig{compare_delay: COMPARE_DELAY, ..config});
+			let tag = mac.gen(&message);
+			(ex31::recover_tag_by_timing(|t| Ok(time_authenticate(&mac, &message, t)), tag.len(), tag.len()).unwrap(), tag)
+		};
+		let (recovered, tag) = attack(ex28::MacConfig::default());
+		assert_eq!(recovered, tag);
+		let (recovered, tag) = attack(ex28::MacConfig{hmac: true, ..ex28::MacConfig::default()});
+		assert_eq!(recovered, tag);
+		// every guess takes exactly as long, so the first one tried wins every position
+		let (recovered, _) = attack(ex28::MacConfig{constant_time_compare: true, ..ex28::MacConfig::default()});
+		assert_eq!(recovered, vec![0; 20]);
+	}
+}
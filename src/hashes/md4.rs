@@ -0,0 +1,48 @@
+/*
+
+MD4, from RFC 1320
+
+*/
+
+use crate::hashes::{MdHash, read_words};
+
+pub enum Md4 {}
+
+// a round's function, constant, word order and shifts
+type Round<'a> = (&'a dyn Fn(u32, u32, u32) -> u32, u32, [usize; 16], [u32; 4]);
+
+impl MdHash for Md4 {
+	const NAME: &'static str = "md4";
+	const BLOCK_SIZE: usize = 64;
+	const WORD_BYTES: usize = 4;
+	const LENGTH_BYTES: usize = 8;
+	const BIG_ENDIAN: bool = false;
+	const INITIAL_STATE: &'static [u64] = &[0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476];
+
+	fn compress(state: &mut [u64], block: &[u8]) {
+		let x: Vec<u32> = read_words::<Md4>(block).into_iter().map(|w| w as u32).collect();
+		let (mut a, mut b, mut c, mut d) = (state[0] as u32, state[1] as u32, state[2] as u32, state[3] as u32);
+		let f = |x: u32, y: u32, z: u32| (x & y) | (!x & z);
+		let g = |x: u32, y: u32, z: u32| (x & y) | (x & z) | (y & z);
+		let h = |x: u32, y: u32, z: u32| x ^ y ^ z;
+
+		// each round goes a, d, c, b over the words in its own order, with its own shifts
+		let rounds: [Round; 3] = [
+			(&f, 0, [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15], [3, 7, 11, 19]),
+			(&g, 0x5A827999, [0, 4, 8, 12, 1, 5, 9, 13, 2, 6, 10, 14, 3, 7, 11, 15], [3, 5, 9, 13]),
+			(&h, 0x6ED9EBA1, [0, 8, 4, 12, 2, 10, 6, 14, 1, 9, 5, 13, 3, 11, 7, 15], [3, 9, 11, 15]),
+		];
+		for (func, k, order, shifts) in rounds.iter() {
+			for (i, &word) in order.iter().enumerate() {
+				let t = a.wrapping_add(func(b, c, d)).wrapping_add(x[word]).wrapping_add(*k).rotate_left(shifts[i % 4]);
+				a = d;
+				d = c;
+				c = b;
+				b = t;
+			}
+		}
+		for (s, v) in state.iter_mut().zip([a, b, c, d].iter()) {
+			*s = (*s as u32).wrapping_add(*v) as u64;
+		}
+	}
+}
@@ -0,0 +1,232 @@
+/*
+
+Merkle–Damgård hashes
+
+MD4, MD5, SHA-1, SHA-256 and SHA-512 all work the same way: pad the message with 0x80, zeroes and
+its length, split it into blocks, and run each block through a compression function that updates a
+few words of state. The digest is just the final state. They differ in the compression function,
+block size, word size, byte order, and how wide the length at the end is:
+
+			block	words			order	length
+	md4		64		4 x 32 bits		little	64 bits
+	md5		64		4 x 32 bits		little	64 bits
+	sha1	64		5 x 32 bits		big		64 bits
+	sha256	64		8 x 32 bits		big		64 bits
+	sha512	128		8 x 64 bits		big		128 bits
+
+MdHasher does the buffering and padding for all of them. The state is kept as u64s whatever the
+word size, and it can be set, which is what length extension needs.
+
+*/
+
+pub mod md4;
+pub mod md5;
+pub mod sha1;
+pub mod sha256;
+pub mod sha512;
+
+use std::marker::PhantomData;
+
+pub trait MdHash {
+	const NAME: &'static str;
+	const BLOCK_SIZE: usize;
+	const WORD_BYTES: usize;
+	const LENGTH_BYTES: usize;
+	const BIG_ENDIAN: bool;
+	const INITIAL_STATE: &'static [u64];
+
+	fn compress(state: &mut [u64], block: &[u8]);
+}
+
+// words of a block or digest in the hash's byte order
+pub fn read_words<H: MdHash>(bytes: &[u8]) -> Vec<u64> {
+	bytes.chunks(H::WORD_BYTES).map(|word| match H::BIG_ENDIAN {
+		true => word.iter().fold(0, |acc, &b| (acc << 8) | b as u64),
+		false => word.iter().rev().fold(0, |acc, &b| (acc << 8) | b as u64),
+	}).collect()
+}
+
+fn write_words<H: MdHash>(words: &[u64]) -> Vec<u8> {
+	words.iter().flat_map(|w| match H::BIG_ENDIAN {
+		true => w.to_be_bytes()[8 - H::WORD_BYTES..].to_vec(),
+		false => w.to_le_bytes()[..H::WORD_BYTES].to_vec(),
+	}).collect()
+}
+
+pub struct MdHasher<H: MdHash> {
+	state: Vec<u64>,
+	buffer: Vec<u8>,	// less than a block, waiting for the rest
+	length: u64,		// bytes hashed so far, including the buffer
+	hash: PhantomData<H>,
+}
+
+// not derived, since that would want H: Clone
+impl<H: MdHash> Clone for MdHasher<H> {
+	fn clone(&self) -> MdHasher<H> {
+		MdHasher{state: self.state.clone(), buffer: self.buffer.clone(), length: self.length, hash: PhantomData}
+	}
+}
+
+impl<H: MdHash> MdHasher<H> {
+	pub fn new() -> MdHasher<H> {
+		MdHasher{state: H::INITIAL_STATE.to_vec(), buffer: vec![], length: 0, hash: PhantomData}
+	}
+
+	// carries on as if processed bytes had already been hashed and left this state. the state only
+	// changes at block boundaries, so processed has to be a whole number of blocks
+	pub fn from_state(state: &[u64], processed: u64) -> Result<MdHasher<H>, String> {
+		if state.len() != H::INITIAL_STATE.len() {
+			return Err(format!("{} has {} words of state, not {}", H::NAME, H::INITIAL_STATE.len(), state.len()));
+		}
+		if processed % H::BLOCK_SIZE as u64 != 0 {
+			return Err(format!("{} bytes isn't a whole number of {} blocks", processed, H::NAME));
+		}
+		Ok(MdHasher{state: state.to_vec(), buffer: vec![], length: processed, hash: PhantomData})
+	}
+
+	// the state a digest came from, after hashing processed bytes (message and padding)
+	pub fn from_digest(digest: &[u8], processed: u64) -> Result<MdHasher<H>, String> {
+		if digest.len() != Self::digest_size() {
+			return Err(format!("a {} digest is {} bytes, not {}", H::NAME, Self::digest_size(), digest.len()));
+		}
+		MdHasher::from_state(&read_words::<H>(digest), processed)
+	}
+
+	pub fn digest_size() -> usize {
+		H::INITIAL_STATE.len() * H::WORD_BYTES
+	}
+
+	pub fn digest(data: &[u8]) -> Vec<u8> {
+		let mut hasher = MdHasher::<H>::new();
+		hasher.update(data);
+		hasher.finalize()
+	}
+
+	// what gets appended to a message of this many bytes: 0x80, zeroes, and the length in bits, up
+	// to a multiple of the block size
+	pub fn padding(message_len: u64) -> Vec<u8> {
+		let room = H::BLOCK_SIZE - 1 - H::LENGTH_BYTES;
+		let zeroes = (H::BLOCK_SIZE + room - (message_len as usize % H::BLOCK_SIZE)) % H::BLOCK_SIZE;
+		let mut res = vec![0x80];
+		res.resize(1 + zeroes, 0);
+		let bits = message_len as u128 * 8;
+		match H::BIG_ENDIAN {
+			true => res.extend_from_slice(&bits.to_be_bytes()[16 - H::LENGTH_BYTES..]),
+			false => res.extend_from_slice(&bits.to_le_bytes()[..H::LENGTH_BYTES]),
+		}
+		res
+	}
+
+	pub fn update(&mut self, data: &[u8]) {
+		self.length = self.length.wrapping_add(data.len() as u64);
+		let mut data = data;
+		if !self.buffer.is_empty() {
+			let wanted = (H::BLOCK_SIZE - self.buffer.len()).min(data.len());
+			self.buffer.extend_from_slice(&data[..wanted]);
+			data = &data[wanted..];
+			if self.buffer.len() < H::BLOCK_SIZE {
+				return;
+			}
+			let block = std::mem::take(&mut self.buffer);
+			H::compress(&mut self.state, &block);
+		}
+		let whole = data.len() - data.len() % H::BLOCK_SIZE;
+		for block in data[..whole].chunks(H::BLOCK_SIZE) {
+			H::compress(&mut self.state, block);
+		}
+		self.buffer.extend_from_slice(&data[whole..]);
+	}
+
+	pub fn finalize(mut self) -> Vec<u8> {
+		let length = self.length;
+		self.update(&Self::padding(length));
+		debug_assert!(self.buffer.is_empty());
+		write_words::<H>(&self.state)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::hashes::{md4::Md4, md5::Md5, sha1::Sha1, sha256::Sha256, sha512::Sha512};
+	use crate::hashes::{MdHash, MdHasher};
+	use crate::utils;
+
+	fn hex<H: MdHash>(data: &[u8]) -> String {
+		utils::bytes_to_hex_string(&MdHasher::<H>::digest(data))
+	}
+
+	// rfc 1320, rfc 1321, and the fips 180-4 examples
+	#[test]
+	fn test_vectors() {
+		let digits = b"12345678901234567890123456789012345678901234567890123456789012345678901234567890";
+		let two_blocks = b"abcdefghbcdefghicdefghijdefghijkefghijklfghijklmghijklmnhijklmnoijklmnopjklmnopqklmnopqrlmnopqrsmnopqrstnopqrstu";
+
+		assert_eq!(hex::<Md4>(b""), "31d6cfe0d16ae931b73c59d7e0c089c0");
+		assert_eq!(hex::<Md4>(b"abc"), "a448017aaf21d8525fc10ae87aa6729d");
+		assert_eq!(hex::<Md4>(b"message digest"), "d9130a8164549fe818874806e1c7014b");
+		assert_eq!(hex::<Md4>(digits), "e33b4ddc9c38f2199c3e7b164fcc0536");
+
+		assert_eq!(hex::<Md5>(b""), "d41d8cd98f00b204e9800998ecf8427e");
+		assert_eq!(hex::<Md5>(b"abc"), "900150983cd24fb0d6963f7d28e17f72");
+		assert_eq!(hex::<Md5>(b"message digest"), "f96b697d7cb7938d525a2f31aaf161d0");
+		assert_eq!(hex::<Md5>(digits), "57edf4a22be3c955ac49da2e2107b67a");
+
+		assert_eq!(hex::<Sha1>(two_blocks), "a49b2446a02c645bf419f995b67091253a04a259");
+
+		assert_eq!(hex::<Sha256>(b""), "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+		assert_eq!(hex::<Sha256>(b"abc"), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+		assert_eq!(hex::<Sha256>(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"), "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1");
+
+		assert_eq!(hex::<Sha512>(b""), "cf83e1357eefb8bdf1542850d66d8007d620e4050b5715dc83f4a921d36ce9ce47d0d13c5d85f2b0ff8318d2877eec2f63b931bd47417a81a538327af927da3e");
+		assert_eq!(hex::<Sha512>(b"abc"), "ddaf35a193617abacc417349ae20413112e6fa4e89a97ea20a9eeee64b55d39a2192992a274fc1a836ba3c23a3feebbd454d4423643ce80e2a9ac94fa54ca49f");
+		assert_eq!(hex::<Sha512>(two_blocks), "8e959b75dae313da8cf4f72814fc143f8f7779c6eb9f7fa17299aeadb6889018501d289e4900f7e4331b99dec4b5433ac7d329eeb6dd26545e96e55b874be909");
+
+		// and md4 against the md4 crate
+		use md4::Digest;
+		let message: Vec<u8> = (0..300).map(|i| (i * 7) as u8).collect();
+		assert_eq!(MdHasher::<Md4>::digest(&message), md4::Md4::digest(&message).to_vec());
+	}
+
+	fn check_padding<H: MdHash>() {
+		for len in [0u64, 1, 55, 56, 63, 64, 111, 112, 119, 120, 127, 128, 1000].iter() {
+			let padding = MdHasher::<H>::padding(*len);
+			assert_eq!((*len as usize + padding.len()) % H::BLOCK_SIZE, 0, "{} {}", H::NAME, len);
+			assert!(padding.len() > H::LENGTH_BYTES && padding.len() <= H::BLOCK_SIZE + H::LENGTH_BYTES);
+			let mut bits = (len * 8).to_le_bytes().to_vec();
+			bits.resize(H::LENGTH_BYTES, 0);
+			if H::BIG_ENDIAN {
+				bits.reverse();
+			}
+			assert_eq!(padding[padding.len() - H::LENGTH_BYTES..], bits[..]);
+		}
+	}
+
+	fn check_resume<H: MdHash>() {
+		let message: Vec<u8> = (0..3 * H::BLOCK_SIZE + 10).map(|i| i as u8).collect();
+		let mut hasher = MdHasher::<H>::new();
+		hasher.update(&message[..2 * H::BLOCK_SIZE]);
+		let mut resumed = MdHasher::<H>::from_state(&hasher.state, 2 * H::BLOCK_SIZE as u64).unwrap();
+		// a byte at a time across block boundaries
+		for b in message[2 * H::BLOCK_SIZE..].iter() {
+			resumed.update(&[*b]);
+		}
+		assert_eq!(resumed.finalize(), MdHasher::<H>::digest(&message));
+		assert!(MdHasher::<H>::from_state(&hasher.state, 1).is_err());
+		assert!(MdHasher::<H>::from_state(&hasher.state[1..], 0).is_err());
+		assert!(MdHasher::<H>::from_digest(&[0; 3], 0).is_err());
+	}
+
+	#[test]
+	fn test_padding_and_state() {
+		check_padding::<Md4>();
+		check_padding::<Md5>();
+		check_padding::<Sha1>();
+		check_padding::<Sha256>();
+		check_padding::<Sha512>();
+		check_resume::<Md4>();
+		check_resume::<Md5>();
+		check_resume::<Sha1>();
+		check_resume::<Sha256>();
+		check_resume::<Sha512>();
+	}
+}
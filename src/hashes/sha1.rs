@@ -0,0 +1,79 @@
+/*
+
+SHA-1, from FIPS 180-4
+
+*/
+
+use crate::hashes::{MdHash, read_words};
+
+pub enum Sha1 {}
+
+impl MdHash for Sha1 {
+	const NAME: &'static str = "sha-1";
+	const BLOCK_SIZE: usize = 64;
+	const WORD_BYTES: usize = 4;
+	const LENGTH_BYTES: usize = 8;
+	const BIG_ENDIAN: bool = true;
+	const INITIAL_STATE: &'static [u64] = &[0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+
+	fn compress(state: &mut [u64], block: &[u8]) {
+		let mut w = [0u32; 80];
+		for (i, word) in read_words::<Sha1>(block).into_iter().enumerate() {
+			w[i] = word as u32;
+		}
+		for i in 16..80 {
+			w[i] = (w[i-3] ^ w[i-8] ^ w[i-14] ^ w[i-16]).rotate_left(1);
+		}
+		let (mut a, mut b, mut c, mut d, mut e) = (state[0] as u32, state[1] as u32, state[2] as u32, state[3] as u32, state[4] as u32);
+		for i in 0..80 {
+			let (f, k) = match i {
+				0..=19 => ((b & c) | (!b & d), 0x5A827999),
+				20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+				40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+				_ => (b ^ c ^ d, 0xCA62C1D6),
+			};
+			let temp = a.rotate_left(5).wrapping_add(f).wrapping_add(e).wrapping_add(k).wrapping_add(w[i]);
+			e = d;
+			d = c;
+			c = b.rotate_left(30);
+			b = a;
+			a = temp;
+		}
+		for (x, y) in state.iter_mut().zip([a, b, c, d, e].iter()) {
+			*x = (*x as u32).wrapping_add(*y) as u64;
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::hashes::{MdHasher, sha1::Sha1};
+	use crate::utils;
+
+	fn hex(data: &[u8]) -> String {
+		utils::bytes_to_hex_string(&MdHasher::<Sha1>::digest(data))
+	}
+
+	// fips 180-4 examples and the nist cavs million a's
+	#[test]
+	fn test_vectors() {
+		assert_eq!(hex(b""), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+		assert_eq!(hex(b"abc"), "a9993e364706816aba3e25717850c26c9cd0d89d");
+		assert_eq!(hex(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"), "84983e441c3bd26ebaae4aa1f95129e5e54670f1");
+		assert_eq!(hex(&vec![b'a'; 1000000]), "34aa973cd4c4daa4f61eeb2bdbad27316534016f");
+	}
+
+	#[test]
+	fn test_from_digest() {
+		// the registers are the digest of the padded message
+		let mut padded = b"abc".to_vec();
+		padded.append(&mut MdHasher::<Sha1>::padding(3));
+		let digest = MdHasher::<Sha1>::digest(b"abc");
+		let mut extended = MdHasher::<Sha1>::from_digest(&digest, padded.len() as u64).unwrap();
+		extended.update(b"def");
+		padded.extend_from_slice(b"def");
+		assert_eq!(extended.finalize(), MdHasher::<Sha1>::digest(&padded));
+		assert!(MdHasher::<Sha1>::from_digest(&digest, 63).is_err());
+		assert!(MdHasher::<Sha1>::from_digest(&digest[1..], 64).is_err());
+	}
+}
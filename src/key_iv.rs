@@ -0,0 +1,159 @@
+/*
+
+Key-equals-IV recovery
+
+If a CBC receiver uses its key as the IV and tells you what it decrypted when something goes wrong
+("non ascii char: ..."), one modified ciphertext gives away the key. Send C_1, 0, C_1:
+
+P'_1 = D(C_1) XOR IV = D(C_1) XOR K
+P'_3 = D(C_1) XOR 0
+
+so P'_1 XOR P'_3 is the key. Nothing in that depends on AES or 16-byte blocks.
+
+The receiver is an Oracle taking ciphertext; its error string goes through an extractor supplied by
+the caller, since every server words its leak differently. The last two blocks of a captured ciphertext
+are tacked onto the end so the padding still checks out, for receivers that look at padding before they
+look at the plaintext. The recovered key is then checked by decrypting the captured ciphertext with it.
+
+*/
+
+use crate::oracle::Oracle;
+use crate::set2::{ex10, ex15};
+
+#[derive(Debug, Clone)]
+pub struct RecoveredKey {
+	pub key: Vec<u8>,
+	pub plaintext: Vec<u8>,	// the captured ciphertext, decrypted with the recovered key
+}
+
+fn forge_key_leak_ciphertext(captured: &[u8], block_size: usize) -> Result<Vec<u8>, String> {
+	if captured.len() < block_size || captured.len() % block_size != 0 {
+		return Err(format!("captured ciphertext is {} bytes, need whole blocks of {}", captured.len(), block_size));
+	}
+	let c1 = &captured[..block_size];
+	let mut modified = c1.to_vec();
+	modified.append(&mut vec![0; block_size]);
+	modified.extend_from_slice(c1);
+	// keep the padding valid. with a single block the one before it is the iv, which we don't have
+	if captured.len() >= 2 * block_size {
+		modified.extend_from_slice(&captured[captured.len() - 2 * block_size..]);
+	}
+	Ok(modified)
+}
+
+// decrypt is (ciphertext, key) -> plaintext with the key used as the iv, failing on bad padding
+pub fn recover_key<O, E, D>(oracle: &O, extract: &E, decrypt: &D, captured: &[u8], block_size: usize) -> Result<RecoveredKey, String>
+	where O: Oracle, E: Fn(&str) -> Option<Vec<u8>>, D: Fn(&[u8], &[u8]) -> Result<Vec<u8>, String> {
+	let modified = forge_key_leak_ciphertext(captured, block_size)?;
+	let leaked = match oracle.query(&modified) {
+		Ok(_) => return Err("receiver accepted the modified ciphertext without leaking anything".to_string()),
+		Err(e) => extract(&e).ok_or(format!("no plaintext in error {:?}", e))?,
+	};
+	if leaked.len() < 3 * block_size {
+		return Err(format!("leaked {} bytes, need at least {}", leaked.len(), 3 * block_size));
+	}
+
+	let key: Vec<u8> = leaked[..block_size].iter()
+		.zip(leaked[2 * block_size..3 * block_size].iter())
+		.map(|(a, b)| a ^ b)
+		.collect();
+	match decrypt(captured, &key) {
+		Ok(plaintext) => Ok(RecoveredKey{key: key, plaintext: plaintext}),
+		Err(e) => Err(format!("recovered key doesn't decrypt the captured ciphertext: {}", e)),
+	}
+}
+
+// the decrypt for recover_key when the receiver is aes-128-cbc with pkcs7
+pub fn decrypt_aes128cbc_key_as_iv(ciphertext: &[u8], key: &[u8]) -> Result<Vec<u8>, String> {
+	let mut decrypted = ciphertext.to_vec();
+	ex10::decrypt_aes128cbc(&mut decrypted, key, key).map_err(|e| format!("{:?}", e))?;
+	ex15::strip_padding(&decrypted).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::key_iv::{decrypt_aes128cbc_key_as_iv, recover_key};
+	use crate::oracle::Oracle;
+	use crate::set2::{ex9, ex10, ex11, ex15};
+	use crate::utils;
+
+	// toy 8-byte block cipher, just so there's something that isn't aes
+	fn toy_encrypt_block(block: &[u8], key: &[u8]) -> Vec<u8> {
+		block.iter().enumerate().map(|(i, b)| (b ^ key[i]).wrapping_add(key[(i + 1) % key.len()])).collect()
+	}
+
+	fn toy_decrypt_block(block: &[u8], key: &[u8]) -> Vec<u8> {
+		block.iter().enumerate().map(|(i, b)| b.wrapping_sub(key[(i + 1) % key.len()]) ^ key[i]).collect()
+	}
+
+	fn toy_cbc_encrypt(plaintext: &[u8], key: &[u8]) -> Vec<u8> {
+		let mut previous = key.to_vec();
+		let mut res = vec![];
+		for block in ex9::pkcs7_padding(plaintext, key.len()).chunks(key.len()) {
+			let x: Vec<u8> = block.iter().zip(previous.iter()).map(|(a, b)| a ^ b).collect();
+			previous = toy_encrypt_block(&x, key);
+			res.extend_from_slice(&previous);
+		}
+		res
+	}
+
+	fn toy_cbc_decrypt(ciphertext: &[u8], key: &[u8]) -> Result<Vec<u8>, String> {
+		let mut previous = key.to_vec();
+		let mut res = vec![];
+		for block in ciphertext.chunks(key.len()) {
+			res.extend(toy_decrypt_block(block, key).iter().zip(previous.iter()).map(|(a, b)| a ^ b));
+			previous = block.to_vec();
+		}
+		ex15::strip_padding(&res).map_err(|e| e.to_string())
+	}
+
+	// checks padding first, then complains about high ascii with the plaintext in hex
+	struct Receiver<'a> {
+		key: &'a [u8],
+		decrypt: fn(&[u8], &[u8]) -> Result<Vec<u8>, String>,
+	}
+
+	impl<'a> Oracle for Receiver<'a> {
+		fn query(&self, inp: &[u8]) -> Result<Vec<u8>, String> {
+			let plaintext = (self.decrypt)(inp, self.key)?;
+			match plaintext.iter().all(|c| c.is_ascii()) {
+				true => Ok(vec![]),
+				false => Err(format!("non ascii char: {}", utils::bytes_to_hex_string(&plaintext))),
+			}
+		}
+	}
+
+	fn extract(e: &str) -> Option<Vec<u8>> {
+		let prefix = "non ascii char: ";
+		match e.starts_with(prefix) {
+			true => Some(utils::hex_string_to_bytes(&e[prefix.len()..])),
+			false => None,
+		}
+	}
+
+	#[test]
+	fn test_aes_receiver() {
+		let key = ex11::gen_aes128_key();
+		let message = b"comment1=cooking%20MCs;userdata=hello;comment2=%20like%20a%20pound%20of%20bacon";
+		let captured = ex10::encrypt_aes128cbc(&ex9::pkcs7_padding(message, 16), &key, &key);
+		let receiver = Receiver{key: &key, decrypt: decrypt_aes128cbc_key_as_iv};
+		let recovered = recover_key(&receiver, &extract, &decrypt_aes128cbc_key_as_iv, &captured, 16).unwrap();
+		assert_eq!(recovered.key, key.to_vec());
+		assert_eq!(recovered.plaintext, message.to_vec());
+	}
+
+	#[test]
+	fn test_other_block_size() {
+		let key = b"8bytekey".to_vec();
+		let message = b"twenty-something bytes of text";
+		let captured = toy_cbc_encrypt(message, &key);
+		let receiver = Receiver{key: &key, decrypt: toy_cbc_decrypt};
+		let recovered = recover_key(&receiver, &extract, &toy_cbc_decrypt, &captured, 8).unwrap();
+		assert_eq!(recovered.key, key);
+		assert_eq!(recovered.plaintext, message.to_vec());
+
+		// a receiver that doesn't say what it decrypted gives nothing away
+		let quiet = |_: &str| None;
+		assert!(recover_key(&receiver, &quiet, &toy_cbc_decrypt, &captured, 8).is_err());
+	}
+}
@@ -0,0 +1,246 @@
+/*
+
+Keystream generators
+
+Everything in set 3 that encrypts is the same shape: something produces bytes, and they get XORed
+into the text. A KeystreamGenerator hands out the next block of bytes (16 for AES, one output's
+worth for a PRNG) and StreamCipher keeps the leftovers between calls, so any of them can be used
+as a stream cipher and the attacks only have to be written once.
+
+PRNG outputs become bytes by keeping the low bytes_per_output bytes of each output in the chosen
+order. ex24 takes all four bytes of each MT19937 output big-endian; glibc's rand() only has 31 bits
+so it's usually the low byte or three.
+
+*/
+
+use crate::set2::ex10;
+use crate::set3::ex21::MtPrng;
+use std::ops::Range;
+
+pub trait KeystreamGenerator {
+	// the next bytes of keystream, never empty
+	fn next_block(&mut self) -> Vec<u8>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ByteOrder {
+	BigEndian,
+	LittleEndian,
+}
+
+pub struct PrngKeystream<F: FnMut() -> u64> {
+	next_output: F,
+	bytes_per_output: usize,
+	order: ByteOrder,
+}
+
+impl<F: FnMut() -> u64> PrngKeystream<F> {
+	pub fn new(next_output: F, bytes_per_output: usize, order: ByteOrder) -> PrngKeystream<F> {
+		assert!(bytes_per_output > 0 && bytes_per_output <= 8, "between 1 and 8 bytes per output");
+		PrngKeystream{next_output: next_output, bytes_per_output: bytes_per_output, order: order}
+	}
+}
+
+impl<F: FnMut() -> u64> KeystreamGenerator for PrngKeystream<F> {
+	fn next_block(&mut self) -> Vec<u8> {
+		let output = (self.next_output)();
+		match self.order {
+			ByteOrder::BigEndian => output.to_be_bytes()[8 - self.bytes_per_output..].to_vec(),
+			ByteOrder::LittleEndian => output.to_le_bytes()[..self.bytes_per_output].to_vec(),
+		}
+	}
+}
+
+// ex24's cipher
+pub fn mt19937_keystream(seed: u32) -> PrngKeystream<impl FnMut() -> u64> {
+	let mut mt = MtPrng::new(seed);
+	PrngKeystream::new(move || mt.extract_number() as u64, 4, ByteOrder::BigEndian)
+}
+
+// ex18's layout: 8 byte nonce, then the block count as 8 bytes little endian
+pub struct CtrKeystream {
+	key: Vec<u8>,
+	nonce: Vec<u8>,
+	counter: u64,
+}
+
+impl CtrKeystream {
+	pub fn new(key: &[u8], nonce: &[u8]) -> CtrKeystream {
+		assert!(nonce.len() == 8, "ctr nonce is 8 bytes");
+		CtrKeystream{key: key.to_vec(), nonce: nonce.to_vec(), counter: 0}
+	}
+}
+
+impl KeystreamGenerator for CtrKeystream {
+	fn next_block(&mut self) -> Vec<u8> {
+		let mut block = self.nonce.clone();
+		block.extend_from_slice(&self.counter.to_le_bytes());
+		ex10::encrypt_aes128ecb(&mut block, &self.key).expect("couldn't encrypt with ecb");
+		self.counter += 1;
+		block
+	}
+}
+
+// each block is the encryption of the one before, starting from the iv
+pub struct OfbKeystream {
+	key: Vec<u8>,
+	state: Vec<u8>,
+}
+
+impl OfbKeystream {
+	pub fn new(key: &[u8], iv: &[u8]) -> OfbKeystream {
+		assert!(iv.len() == 16, "ofb iv is one block");
+		OfbKeystream{key: key.to_vec(), state: iv.to_vec()}
+	}
+}
+
+impl KeystreamGenerator for OfbKeystream {
+	fn next_block(&mut self) -> Vec<u8> {
+		ex10::encrypt_aes128ecb(&mut self.state, &self.key).expect("couldn't encrypt with ecb");
+		self.state.clone()
+	}
+}
+
+pub struct StreamCipher<G: KeystreamGenerator> {
+	generator: G,
+	buffered: Vec<u8>,
+}
+
+impl<G: KeystreamGenerator> StreamCipher<G> {
+	pub fn new(generator: G) -> StreamCipher<G> {
+		StreamCipher{generator: generator, buffered: vec![]}
+	}
+
+	pub fn keystream(&mut self, len: usize) -> Vec<u8> {
+		while self.buffered.len() < len {
+			let mut block = self.generator.next_block();
+			self.buffered.append(&mut block);
+		}
+		let rest = self.buffered.split_off(len);
+		std::mem::replace(&mut self.buffered, rest)
+	}
+
+	// encrypts and decrypts, carrying on from wherever the last call stopped
+	pub fn apply(&mut self, data: &mut [u8]) {
+		let keystream = self.keystream(data.len());
+		for (d, k) in data.iter_mut().zip(keystream) {
+			*d ^= k;
+		}
+	}
+}
+
+// a fresh cipher from make(seed) for every seed, until one turns the ciphertext into something
+// containing the known plaintext
+pub fn find_seed<G: KeystreamGenerator, F: Fn(u64) -> G>(make: F, seeds: Range<u64>, ciphertext: &[u8], known: &[u8]) -> Result<u64, String> {
+	if known.is_empty() || known.len() > ciphertext.len() {
+		return Err("known plaintext has to be non-empty and fit in the ciphertext".to_string());
+	}
+	for seed in seeds {
+		let mut plaintext = ciphertext.to_vec();
+		StreamCipher::new(make(seed)).apply(&mut plaintext);
+		if plaintext.windows(known.len()).any(|w| w == known) {
+			return Ok(seed);
+		}
+	}
+	Err("no seed in range gives the known plaintext".to_string())
+}
+
+// where the plaintext is known the keystream is too, and a keystream that has been used twice
+// decrypts the other ciphertext at the same positions
+pub fn recover_keystream(ciphertext: &[u8], known: &[u8], offset: usize) -> Result<Vec<u8>, String> {
+	if offset > ciphertext.len() {
+		return Err(format!("offset {} is past the end of a {} byte ciphertext", offset, ciphertext.len()));
+	}
+	// known plaintext running past the end only gives as much keystream as there's ciphertext
+	Ok(ciphertext[offset..].iter().zip(known.iter()).map(|(c, p)| c ^ p).collect())
+}
+
+pub fn demonstrate() {
+	use crate::weak_prng::{GlibcRand, XorShift128Plus};
+	use rand::{Rng, thread_rng};
+
+	let mut rng = thread_rng();
+	let mut plaintext: Vec<u8> = (0..rng.gen_range(0, 16)).map(|_| rng.gen()).collect();
+	plaintext.extend_from_slice(b"AAAAAAAAAAAAAA");
+
+	let glibc = |seed: u64| {
+		let mut g = GlibcRand::new(seed as u32);
+		PrngKeystream::new(move || g.rand() as u64, 1, ByteOrder::LittleEndian)
+	};
+	let xorshift = |seed: u64| {
+		let mut x = XorShift128Plus{state0: seed, state1: seed ^ 0x5555};
+		PrngKeystream::new(move || x.next_u64(), 8, ByteOrder::LittleEndian)
+	};
+	let seed = rng.gen_range(0, 1 << 16);
+
+	let mut ciphertext = plaintext.clone();
+	StreamCipher::new(glibc(seed)).apply(&mut ciphertext);
+	println!("16 bit seed from glibc rand() low bytes: {:?}", find_seed(glibc, 0..1 << 16, &ciphertext, b"AAAAAAAAAAAAAA").map(|s| s == seed));
+
+	let mut ciphertext = plaintext.clone();
+	StreamCipher::new(xorshift(seed)).apply(&mut ciphertext);
+	println!("16 bit seed from xorshift128+: {:?}", find_seed(xorshift, 0..1 << 16, &ciphertext, b"AAAAAAAAAAAAAA").map(|s| s == seed));
+
+	// ofb with the iv used twice: the known message gives the keystream, which decrypts the other one
+	let key: Vec<u8> = (0..16).map(|_| rng.gen()).collect();
+	let iv: Vec<u8> = (0..16).map(|_| rng.gen()).collect();
+	let known = b"Rollin' in my 5.0, with my rag-top down so my hair can blow".to_vec();
+	let secret = b"The girlies on standby waving just to say hi".to_vec();
+	let (mut c1, mut c2) = (known.clone(), secret.clone());
+	StreamCipher::new(OfbKeystream::new(&key, &iv)).apply(&mut c1);
+	StreamCipher::new(OfbKeystream::new(&key, &iv)).apply(&mut c2);
+	let recovered = recover_keystream(&c1, &known, 0).map(|k| c2.iter().zip(k).map(|(c, k)| c ^ k).collect::<Vec<u8>>());
+	println!("ofb with a reused iv: {:?}", recovered.map(|p| String::from_utf8_lossy(&p).to_string()));
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::keystream::*;
+	use crate::utils;
+
+	#[test]
+	fn test_block_ciphers() {
+		// nist sp 800-38a f.4.1
+		let key = utils::hex_string_to_bytes("2b7e151628aed2a6abf7158809cf4f3c");
+		let iv = utils::hex_string_to_bytes("000102030405060708090a0b0c0d0e0f");
+		let mut text = utils::hex_string_to_bytes("6bc1bee22e409f96e93d7e117393172aae2d8a571e03ac9c9eb76fac45af8e51");
+		let mut cipher = StreamCipher::new(OfbKeystream::new(&key, &iv));
+		// in uneven pieces to check the leftovers carry over
+		cipher.apply(&mut text[..5]);
+		cipher.apply(&mut text[5..21]);
+		cipher.apply(&mut text[21..]);
+		assert_eq!(utils::bytes_to_hex_string(&text), "3b3fd92eb72dad20333449f8e83cfb4a7789508d16918f03f53c52dac54ed825");
+
+		// ex18
+		let mut text = utils::base64_to_bytes(&"L77na/nrFsKvynd6HzOoG7GHTLXsTVu9qvY/2syLXzhPweyyMTJULu/6/kXX0KSvoOLSFQ==".to_string());
+		StreamCipher::new(CtrKeystream::new(b"YELLOW SUBMARINE", &[0; 8])).apply(&mut text);
+		assert_eq!(String::from_utf8_lossy(&text), "Yo, VIP Let's kick it Ice, Ice, baby Ice, Ice, baby ");
+	}
+
+	#[test]
+	fn test_prng_keystream() {
+		let mut cipher = StreamCipher::new(PrngKeystream::new(|| 0x0102030405060708, 3, ByteOrder::BigEndian));
+		assert_eq!(cipher.keystream(7), vec![6, 7, 8, 6, 7, 8, 6]);
+		assert_eq!(cipher.keystream(2), vec![7, 8]);
+		let mut cipher = StreamCipher::new(PrngKeystream::new(|| 0x0102030405060708, 2, ByteOrder::LittleEndian));
+		assert_eq!(cipher.keystream(3), vec![8, 7, 8]);
+		let mut mt = MtPrng::new(5489);
+		let first = mt.extract_number().to_be_bytes();
+		assert_eq!(StreamCipher::new(mt19937_keystream(5489)).keystream(4), first.to_vec());
+	}
+
+	#[test]
+	fn test_find_seed() {
+		let mut ciphertext = b"xyzAAAAAAAAAAAAAAxyz".to_vec();
+		StreamCipher::new(mt19937_keystream(4321)).apply(&mut ciphertext);
+		assert_eq!(find_seed(|s| mt19937_keystream(s as u32), 4000..5000, &ciphertext, b"AAAAAAAAAAAAAA"), Ok(4321));
+		assert!(find_seed(|s| mt19937_keystream(s as u32), 0..100, &ciphertext, b"AAAAAAAAAAAAAA").is_err());
+		assert!(find_seed(|s| mt19937_keystream(s as u32), 0..100, b"short", b"longer than that").is_err());
+
+		let keystream = recover_keystream(&ciphertext, b"AAAA", 3).unwrap();
+		assert_eq!(keystream, StreamCipher::new(mt19937_keystream(4321)).keystream(7)[3..].to_vec());
+		assert_eq!(recover_keystream(&ciphertext, b"AAAA", 18).unwrap().len(), 2);
+		assert_eq!(recover_keystream(&ciphertext, b"AAAA", 20), Ok(vec![]));
+		assert!(recover_keystream(&ciphertext, b"AAAA", 21).is_err());
+	}
+}
@@ -0,0 +1,111 @@
+/*
+
+Length extension
+
+ex29 and ex30 for any Merkle–Damgård hash. A secret-prefix MAC H(key || message) is the hash state
+after key || message || padding, so anyone with the MAC can carry on hashing from there. For a guessed
+key length:
+
+	forged message	message || glue padding || extension
+	forged mac		the hasher resumed from the mac, told key_len + len(message || glue) bytes went
+					through already, fed the extension
+
+The glue padding is just the hash's own padding for key_len + len(message) bytes, so the only thing
+we need to guess is the key length. find_forgery tries a range of them against something that says
+whether a (message, mac) pair checks out.
+
+*/
+
+use crate::hashes::{MdHash, MdHasher};
+use crate::mac::{self, SecretPrefixMac};
+use std::ops::Range;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Forgery {
+	pub key_len: usize,
+	pub message: Vec<u8>,
+	pub mac: Vec<u8>,
+}
+
+pub fn forge<H: MdHash>(message: &[u8], mac: &[u8], extension: &[u8], key_len: usize) -> Result<Forgery, String> {
+	let glue = MdHasher::<H>::padding((key_len + message.len()) as u64);
+	let processed = (key_len + message.len() + glue.len()) as u64;
+	let mut hasher = MdHasher::<H>::from_digest(mac, processed)?;
+	hasher.update(extension);
+	let mut forged = message.to_vec();
+	forged.extend_from_slice(&glue);
+	forged.extend_from_slice(extension);
+	Ok(Forgery{key_len: key_len, message: forged, mac: hasher.finalize()})
+}
+
+// one forgery per key length
+pub fn forge_range<H: MdHash>(message: &[u8], mac: &[u8], extension: &[u8], key_lens: Range<usize>) -> Result<Vec<Forgery>, String> {
+	key_lens.map(|key_len| forge::<H>(message, mac, extension, key_len)).collect()
+}
+
+// the first forgery verify accepts
+pub fn find_forgery<H: MdHash, V: Fn(&[u8], &[u8]) -> bool>(verify: V, message: &[u8], mac: &[u8], extension: &[u8], key_lens: Range<usize>)
+	-> Result<Forgery, String> {
+	for forgery in forge_range::<H>(message, mac, extension, key_lens)? {
+		if verify(&forgery.message, &forgery.mac) {
+			return Ok(forgery);
+		}
+	}
+	Err(format!("no {} forgery was accepted, is the key length outside the range?", H::NAME))
+}
+
+// H(key || message)
+pub fn secret_prefix_mac<H: MdHash>(key: &[u8], message: &[u8]) -> Vec<u8> {
+	mac::mac::<SecretPrefixMac<H>>(key, message)
+}
+
+pub fn demonstrate() {
+	use crate::hashes::{md4::Md4, md5::Md5, sha1::Sha1, sha256::Sha256, sha512::Sha512};
+	use rand::{Rng, thread_rng};
+
+	fn attack<H: MdHash>() {
+		let key: Vec<u8> = (0..thread_rng().gen_range(1, 64)).map(|_| thread_rng().gen()).collect();
+		let message = b"comment1=cooking%20MCs;userdata=foo;comment2=%20like%20a%20pound%20of%20bacon";
+		let mac = secret_prefix_mac::<H>(&key, message);
+		let verify = |m: &[u8], t: &[u8]| secret_prefix_mac::<H>(&key, m) == t;
+		match find_forgery::<H, _>(verify, message, &mac, b";admin=true", 0..64) {
+			Ok(forgery) => println!("{}: forged with key length {}", H::NAME, forgery.key_len),
+			Err(e) => println!("{}: {}", H::NAME, e),
+		}
+	}
+	attack::<Md4>();
+	attack::<Md5>();
+	attack::<Sha1>();
+	attack::<Sha256>();
+	attack::<Sha512>();
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::hashes::{md4::Md4, md5::Md5, sha1::Sha1, sha256::Sha256, sha512::Sha512};
+	use crate::hashes::MdHash;
+	use crate::length_extension::*;
+
+	fn check<H: MdHash>() {
+		let message = b"comment1=cooking%20MCs;userdata=foo;comment2=%20like%20a%20pound%20of%20bacon";
+		for key_len in [0, 1, 16, 50, 111, 200].iter() {
+			let key: Vec<u8> = (0..*key_len).map(|i| (i * 31) as u8).collect();
+			let mac = secret_prefix_mac::<H>(&key, message);
+			let verify = |m: &[u8], t: &[u8]| secret_prefix_mac::<H>(&key, m) == t;
+			let forgery = find_forgery::<H, _>(verify, message, &mac, b";admin=true", 0..256).unwrap();
+			assert_eq!(forgery.key_len, *key_len, "{}", H::NAME);
+			assert!(forgery.message.starts_with(message) && forgery.message.ends_with(b";admin=true"));
+			assert!(find_forgery::<H, _>(verify, message, &mac, b";admin=true", 0..*key_len).is_err());
+		}
+		assert!(forge::<H>(message, &[0; 3], b"x", 0).is_err());
+	}
+
+	#[test]
+	fn test_every_hash() {
+		check::<Md4>();
+		check::<Md5>();
+		check::<Sha1>();
+		check::<Sha256>();
+		check::<Sha512>();
+	}
+}
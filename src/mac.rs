@@ -0,0 +1,205 @@
+/*
+
+MACs
+
+A Mac is made from a key, fed the message in as many pieces as you like, and finalized into a tag.
+Each one is good for exactly one message: finalize takes it by value, so there's no way to carry a
+half-used hasher over into the next message (which is what ex28's and ex30's MACs used to do, so
+their second tag covered both messages). mac is the one-shot version.
+
+	SecretPrefixMac<H>	H(key || message), what ex28 and ex30 build and ex29/ex30 break
+	Hmac<H>				H((key ^ opad) || H((key ^ ipad) || message)), rfc 2104
+
+Both work with any of the hashes in crate::hashes.
+
+Also here: hmac_sha1, which the encrypt-then-mac formats all use, check_tag for splitting it back off,
+and constant_time_eq.
+
+*/
+
+use crate::hashes::{MdHash, MdHasher};
+use crate::hashes::sha1::Sha1;
+
+pub trait Mac: Sized {
+	fn new(key: &[u8]) -> Self;
+	fn update(&mut self, data: &[u8]);
+	fn finalize(self) -> Vec<u8>;
+}
+
+pub fn mac<M: Mac>(key: &[u8], message: &[u8]) -> Vec<u8> {
+	let mut m = M::new(key);
+	m.update(message);
+	m.finalize()
+}
+
+pub struct SecretPrefixMac<H: MdHash> {
+	hasher: MdHasher<H>,
+}
+
+impl<H: MdHash> Mac for SecretPrefixMac<H> {
+	fn new(key: &[u8]) -> SecretPrefixMac<H> {
+		let mut hasher = MdHasher::new();
+		hasher.update(key);
+		SecretPrefixMac{hasher: hasher}
+	}
+
+	fn update(&mut self, data: &[u8]) {
+		self.hasher.update(data);
+	}
+
+	fn finalize(self) -> Vec<u8> {
+		self.hasher.finalize()
+	}
+}
+
+pub struct Hmac<H: MdHash> {
+	inner: MdHasher<H>,
+	outer: MdHasher<H>,
+}
+
+impl<H: MdHash> Mac for Hmac<H> {
+	// keys longer than a block are hashed first, shorter ones padded out with zeroes
+	fn new(key: &[u8]) -> Hmac<H> {
+		let mut key = match key.len() > H::BLOCK_SIZE {
+			true => MdHasher::<H>::digest(key),
+			false => key.to_vec(),
+		};
+		key.resize(H::BLOCK_SIZE, 0);
+		let mut inner = MdHasher::new();
+		inner.update(&key.iter().map(|b| b ^ 0x36).collect::<Vec<u8>>());
+		let mut outer = MdHasher::new();
+		outer.update(&key.iter().map(|b| b ^ 0x5c).collect::<Vec<u8>>());
+		Hmac{inner: inner, outer: outer}
+	}
+
+	fn update(&mut self, data: &[u8]) {
+		self.inner.update(data);
+	}
+
+	fn finalize(self) -> Vec<u8> {
+		let mut outer = self.outer;
+		outer.update(&self.inner.finalize());
+		outer.finalize()
+	}
+}
+
+pub fn hmac_sha1(key: &[u8], message: &[u8]) -> Vec<u8> {
+	mac::<Hmac<Sha1>>(key, message)
+}
+
+// looks at every byte whatever happens
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+	constant_time_eq_with(a, b, || ())
+}
+
+// on_byte runs for every byte looked at, which is all of them. somewhere to hang a delay, the way
+// ex31's insecure_compare sleeps for every byte that matched
+pub fn constant_time_eq_with<F: FnMut()>(a: &[u8], b: &[u8], mut on_byte: F) -> bool {
+	if a.len() != b.len() {
+		return false;
+	}
+	a.iter().zip(b.iter()).fold(0, |acc, (x, y)| {
+		on_byte();
+		acc | (x ^ y)
+	}) == 0
+}
+
+// splits off and checks the tag on the end of an encrypt-then-mac message
+pub fn check_tag(mac_key: &[u8], message: &[u8]) -> Result<Vec<u8>, String> {
+	let tag_len = 20;
+	if message.len() < tag_len {
+		return Err("message too short".to_string());
+	}
+	let (body, tag) = message.split_at(message.len() - tag_len);
+	match constant_time_eq(&hmac_sha1(mac_key, body), tag) {
+		true => Ok(body.to_vec()),
+		false => Err("invalid message".to_string()),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::hashes::{MdHash, md4::Md4, md5::Md5, sha1::Sha1, sha256::Sha256, sha512::Sha512};
+	use crate::mac::*;
+	use crate::set4::{ex28, ex30};
+	use crate::utils;
+
+	fn hex<H: MdHash>(key: &[u8], message: &[u8]) -> String {
+		utils::bytes_to_hex_string(&mac::<Hmac<H>>(key, message))
+	}
+
+	// rfc 2202
+	#[test]
+	fn test_hmac_md5_sha1() {
+		assert_eq!(hex::<Md5>(&[0x0b; 16], b"Hi There"), "9294727a3638bb1c13f48ef8158bfc9d");
+		assert_eq!(hex::<Md5>(b"Jefe", b"what do ya want for nothing?"), "750c783e6ab0b503eaa86e310a5db738");
+		assert_eq!(hex::<Md5>(&[0xaa; 16], &[0xdd; 50]), "56be34521d144c88dbb8c733f0e8b3f6");
+		assert_eq!(hex::<Md5>(&[0xaa; 80], b"Test Using Larger Than Block-Size Key - Hash Key First"), "6b1ab7fe4bd7bf8f0b62e6ce61b9d0cd");
+
+		assert_eq!(hex::<Sha1>(&[0x0b; 20], b"Hi There"), "b617318655057264e28bc0b6fb378c8ef146be00");
+		assert_eq!(hex::<Sha1>(b"Jefe", b"what do ya want for nothing?"), "effcdf6ae5eb2fa2d27416d5f184df9c259a7c79");
+		assert_eq!(hex::<Sha1>(&[0xaa; 20], &[0xdd; 50]), "125d7342b9ac11cd91a39af48aa17b4f63f175d3");
+		assert_eq!(hex::<Sha1>(&[0xaa; 80], b"Test Using Larger Than Block-Size Key and Larger Than One Block-Size Data"), "e8e99d0f45237d786d6bbaa7965c7808bbff1a91");
+	}
+
+	// rfc 4231 test cases 1, 2, 6 and 7
+	#[test]
+	fn test_hmac_sha2() {
+		let large_key = "Test Using Larger Than Block-Size Key - Hash Key First";
+		let large_both = b"This is a test using a larger than block-size key and a larger than block-size data. The key needs to be hashed before being used by the HMAC algorithm.";
+
+		assert_eq!(hex::<Sha256>(&[0x0b; 20], b"Hi There"), "b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7");
+		assert_eq!(hex::<Sha256>(b"Jefe", b"what do ya want for nothing?"), "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843");
+		assert_eq!(hex::<Sha256>(&[0xaa; 131], large_key.as_bytes()), "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54");
+		assert_eq!(hex::<Sha256>(&[0xaa; 131], large_both), "9b09ffa71b942fcb27635fbcd5b0e944bfdc63644f0713938a7f51535c3a35e2");
+
+		assert_eq!(hex::<Sha512>(&[0x0b; 20], b"Hi There"), "87aa7cdea5ef619d4ff0b4241a1d6cb02379f4e2ce4ec2787ad0b30545e17cdedaa833b7d6b8a702038b274eaea3f4e4be9d914eeb61f1702e696c203a126854");
+		assert_eq!(hex::<Sha512>(b"Jefe", b"what do ya want for nothing?"), "164b7a7bfcf819e2e395fbe73b56e0a387bd64222e831fd610270cd7ea2505549758bf75c05a994a6d034f65f8f0e6fdcaeab1a34d4a6b4b636e070a38bce737");
+		assert_eq!(hex::<Sha512>(&[0xaa; 131], large_key.as_bytes()), "80b24263c7c1a3ebb71493c1dd7be8b49b46d1f41b4aeec1121b013783f8f3526b56d037e05f2598bd0fd2215d6a1e5295e64f73f63f0aec8b915a985d786598");
+		assert_eq!(hex::<Sha512>(&[0xaa; 131], large_both), "e37b6a775dc87dbaa4dfa9f96e5e3ffddebd71f8867289865df5a32d20cdc944b6022cac3c4982b10d5eeb55c3e4de15134676fb6de0446065c97440fa8c6a58");
+	}
+
+	#[test]
+	fn test_one_message_per_mac() {
+		let key = b"YELLOW SUBMARINE";
+		let tag = mac::<Hmac<Sha1>>(key, b"hello world");
+		// in pieces or all at once
+		let mut m = Hmac::<Sha1>::new(key);
+		m.update(b"hello");
+		m.update(b" world");
+		assert_eq!(m.finalize(), tag);
+		assert!(mac::<Hmac<Sha1>>(key, b"hello worle") != tag);
+		assert!(mac::<Hmac<Sha1>>(b"YELLOW SUBMARINF", b"hello world") != tag);
+
+		let mut prefix = key.to_vec();
+		prefix.extend_from_slice(b"hello world");
+		assert_eq!(mac::<SecretPrefixMac<Md4>>(key, b"hello world"), MdHasher::<Md4>::digest(&prefix));
+
+		// the same message twice gets the same tag
+		let s1km = ex28::Sha1KeyedMac::new(&key.to_vec());
+		let first = s1km.gen(&b"hello world".to_vec());
+		assert_eq!(s1km.gen(&b"hello world".to_vec()), first);
+		assert!(s1km.authenticate(&first, &b"hello world".to_vec()));
+		let md4km = ex30::Md4KeyedMac::new(&key.to_vec());
+		assert_eq!(md4km.gen(&b"hello world".to_vec()), md4km.gen(&b"hello world".to_vec()));
+	}
+
+	#[test]
+	fn test_constant_time_eq() {
+		assert!(constant_time_eq(b"abc", b"abc"));
+		assert!(!constant_time_eq(b"abc", b"abd"));
+		assert!(!constant_time_eq(b"abc", b"ab"));
+		// every byte gets looked at, not just the ones before the first difference
+		let mut looked_at = 0;
+		assert!(!constant_time_eq_with(b"xbcdefghij", b"abcdefghij", || looked_at += 1));
+		assert_eq!(looked_at, 10);
+
+		let message = b"attack at dawn".to_vec();
+		let mut sealed = message.clone();
+		sealed.append(&mut hmac_sha1(b"YELLOW SUBMARINE", &message));
+		assert_eq!(check_tag(b"YELLOW SUBMARINE", &sealed), Ok(message));
+		sealed[0] ^= 1;
+		assert!(check_tag(b"YELLOW SUBMARINE", &sealed).is_err());
+		assert!(check_tag(b"YELLOW SUBMARINE", &sealed[..19]).is_err());
+	}
+}
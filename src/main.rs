@@ -1,4 +1,25 @@
 mod utils;
+mod oracle;
+mod cookie;
+mod analysis;
+mod cut_and_paste;
+mod bitflip;
+mod key_iv;
+mod predictable_iv;
+mod fixed_iv;
+mod hardened;
+mod container;
+mod blob_store;
+mod mt_recovery;
+mod py_random;
+mod weak_prng;
+mod randomness;
+mod token_seed;
+mod seed_table;
+mod keystream;
+mod hashes;
+mod length_extension;
+mod mac;
 
 // set 1
 mod set1;
@@ -23,6 +44,21 @@ extern crate rand;
 use std::iter::FromIterator;
 
 fn main() {
+	let args: Vec<String> = std::env::args().collect();
+	if args.len() > 1 && ["container", "randomness", "tokens", "seedtable", "hmactiming"].contains(&args[1].as_str()) {
+		let res = match args[1].as_str() {
+			"container" => container::run_cli(&args[2..]),
+			"randomness" => randomness::run_cli(&args[2..]),
+			"tokens" => token_seed::run_cli(&args[2..]),
+			"seedtable" => seed_table::run_cli(&args[2..]),
+			_ => ex32::run_cli(&args[2..]),
+		};
+		if let Err(e) = res {
+			eprintln!("{}", e);
+			std::process::exit(1);
+		}
+		return;
+	}
 	for a in std::env::args() {
 		match a.as_str() {
 			"all" => { set_one(); set_two(); set_three(); set_four(); return; },
@@ -145,12 +181,25 @@ fn set_two() {
 
 	// ex12
 	println!("\nex12:\n{}", ex12::decrypt_suffix());
+	let ske = ex12::SingleKeyEncrypter::new();
+	let ex12_oracle = oracle::InstrumentedOracle::new(|inp: &[u8]| {
+		ske.encrypt_aes128ecb(&mut inp.to_vec(), Some(ex12::suffix()))
+	}, None);
+	let recovered = ex12::decrypt_suffix_with_oracle(&ex12_oracle).expect("could not decrypt suffix");
+	println!("recovered through oracle: {}", recovered == ex12::suffix());
+	ex12_oracle.stats().print();
+	let transcript_file = std::env::temp_dir().join("ex12_transcript.txt");
+	let transcript_file = transcript_file.to_str().expect("invalid temp path");
+	ex12_oracle.save_transcript(transcript_file).expect("could not save transcript");
+	let replay = oracle::ReplayOracle::load(transcript_file).expect("could not load transcript");
+	let replayed = ex12::decrypt_suffix_with_oracle(&replay).expect("could not replay transcript");
+	println!("replayed from {} without key: {} ({} queries left over)", transcript_file, replayed == recovered, replay.remaining());
 
 	// ex13
 	println!("\nex13:");
 	let map = ex13::kv_parse("foo=bar&baz=qux&zap=zazzle");
 	println!("MAP: {:?}", map);
-	// println!("string: {:02x?}", ex13::profile_for(&mut "foobar@ham&sand=wich.org".as_bytes().to_vec()));
+	println!("profile_for: {:?}", ex13::profile_for(b"foo@bar.com&role=admin").map(|p| String::from_utf8_lossy(&p).to_string()));
 	println!("ECB cut and paste: {:?}", ex13::ecb_cut_and_paste());
 
 	// ex14
@@ -170,6 +219,14 @@ fn set_two() {
 	println!("\nex16:");
 	ex16::bitflipping_attack();
 
+	// not an exercise: predictable (chained) cbc ivs
+	println!("\npredictable ivs:");
+	predictable_iv::demonstrate();
+
+	// not an exercise: ex12 against cbc with ex16's fixed iv
+	println!("\nfixed iv:");
+	fixed_iv::demonstrate();
+
 }
 
 fn set_three() {
@@ -201,12 +258,37 @@ fn set_three() {
 	// ex23
 	println!("\nex23:");
 	ex23::clone_mt19947_prng();
+	ex23::rewind_mt19937_prng();
+
+	// not an exercise: cloning from partial outputs
+	println!("\ncloning from partial outputs:");
+	mt_recovery::demonstrate();
+
+	// not an exercise: python's random module
+	println!("\npython's random:");
+	py_random::demonstrate();
+
+	// not an exercise: other weak prngs
+	println!("\nweak prngs:");
+	weak_prng::demonstrate();
 
 	// ex24
 	println!("\nex24:");
 	ex24::crack_mt19937_stream_cipher_with_16_bit_seed();
 	ex24::test_is_from_mt19937_with_timestamp_seed();
 
+	// not an exercise: time seeded tokens
+	println!("\ntime seeded tokens:");
+	token_seed::demonstrate();
+
+	// not an exercise: seed lookup tables
+	println!("\nseed lookup tables:");
+	seed_table::demonstrate();
+
+	// not an exercise: other prngs as stream ciphers, and ofb
+	println!("\nother stream ciphers:");
+	keystream::demonstrate();
+
 }
 
 fn set_four() {
@@ -229,10 +311,30 @@ fn set_four() {
 	// ex29
 	println!("\nex29:");
 	ex29::break_sha1_keyed_mac();
-}
 
-fn current_exercise() {
 	// ex30
 	println!("\nex30:");
-	ex30::md4_keyed_mac();
+	ex30::break_md4_keyed_mac();
+
+	// ex31, ex32: the attacks take minutes, so just the server here. "hmactiming" runs them
+	println!("\nex31:");
+	ex31::break_hmac_with_timing_leak(0);
+
+	// not an exercise: length extension against md5, sha-256 and sha-512 too
+	println!("\nlength extension:");
+	length_extension::demonstrate();
+
+	// not an exercise: the oracles above with their mitigations switched on
+	println!("\nhardened oracles:");
+	hardened::demonstrate();
+
+	// not an exercise: what ex25's edit should have been
+	println!("\nencrypted blob store:");
+	blob_store::demonstrate();
+}
+
+fn current_exercise() {
+	// ex31 without the attack, which is "hmactiming"
+	println!("\nex31:");
+	ex31::break_hmac_with_timing_leak(0);
 }
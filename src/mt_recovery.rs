@@ -0,0 +1,360 @@
+/*
+
+Cloning a Mersenne Twister from partial outputs
+
+ex23 needs n whole outputs in a row. Real leaks are usually worse: rand() % 256, the top byte,
+doubles from genrand_res53 (which throw away the bottom 5 and 6 bits of two outputs), with gaps
+where someone else used the generator.
+
+It doesn't matter, because the whole generator is linear over GF(2). Tempering and twisting are
+shifts, ands with constants and xors, so every output bit is the xor of some fixed set of state
+bits. Track each state bit symbolically as that set, write down one equation per output bit we get
+to see, and solve the system by gaussian elimination. Any bits we see will do, as long as there are
+enough independent ones.
+
+The unknowns are the untempered words behind outputs 0..n-1 (what ex23 untempers). For mt19937
+that's 19968 bits, but only the top bit of the first word ever feeds into the twist. If output 0
+wasn't observed, the other 31 are undetermined, and they don't matter for anything after it.
+
+*/
+
+use crate::set3::ex21::{self, MersenneTwister, MtParams, MtWord};
+use rand::{Rng, thread_rng};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Observation {
+	pub index: usize,	// which output, counting from the first one after seeding
+	pub bit: u32,		// 0 is the least significant
+	pub value: bool,
+}
+
+// the bits of output index picked out by mask
+pub fn observe_bits<W: MtWord>(index: usize, mask: W, value: W) -> Vec<Observation> {
+	let one = W::from_u64(1);
+	(0..W::BITS)
+		.filter(|&bit| (mask >> bit) & one == one)
+		.map(|bit| Observation{index: index, bit: bit, value: (value >> bit) & one == one})
+		.collect()
+}
+
+// a genrand_res53 double made from outputs index and index + 1
+pub fn observe_res53(index: usize, x: f64) -> Vec<Observation> {
+	let bits = (x * 9007199254740992.0) as u64;
+	let mut res = observe_bits(index, 0xFFFFFFE0u32, ((bits >> 26) << 5) as u32);
+	res.append(&mut observe_bits(index + 1, 0xFFFFFFC0u32, ((bits & 0x3FFFFFF) << 6) as u32));
+	res
+}
+
+// an xor of unknowns, one bit per unknown
+#[derive(Clone)]
+struct BitRow(Vec<u64>);
+
+impl BitRow {
+	fn zero(len: usize) -> BitRow {
+		BitRow(vec![0; (len + 63) / 64])
+	}
+
+	fn unit(len: usize, i: usize) -> BitRow {
+		let mut row = BitRow::zero(len);
+		row.set(i);
+		row
+	}
+
+	fn get(&self, i: usize) -> bool {
+		self.0[i / 64] >> (i % 64) & 1 == 1
+	}
+
+	fn set(&mut self, i: usize) {
+		self.0[i / 64] |= 1 << (i % 64);
+	}
+
+	// every bit below from_word * 64 is zero in other
+	fn xor_from(&mut self, other: &BitRow, from_word: usize) {
+		for i in from_word..self.0.len() {
+			self.0[i] ^= other.0[i];
+		}
+	}
+
+	fn lowest_set(&self, from_word: usize) -> Option<usize> {
+		(from_word..self.0.len())
+			.find(|&i| self.0[i] != 0)
+			.map(|i| i * 64 + self.0[i].trailing_zeros() as usize)
+	}
+
+	fn dot(&self, other: &BitRow) -> bool {
+		self.0.iter().zip(other.0.iter()).fold(0, |acc, (a, b)| acc ^ (a & b).count_ones()) & 1 == 1
+	}
+}
+
+// row echelon form, one row per pivot column, built up an equation at a time
+struct Eliminator {
+	unknowns: usize,
+	pivots: Vec<Option<(BitRow, bool)>>,
+	rank: usize,
+}
+
+impl Eliminator {
+	fn new(unknowns: usize) -> Eliminator {
+		Eliminator{unknowns: unknowns, pivots: vec![None; unknowns], rank: 0}
+	}
+
+	fn add(&mut self, mut row: BitRow, mut rhs: bool) -> Result<(), String> {
+		let mut from_word = 0;
+		while let Some(col) = row.lowest_set(from_word) {
+			from_word = col / 64;
+			match &self.pivots[col] {
+				Some((pivot, pivot_rhs)) => {
+					row.xor_from(pivot, from_word);
+					rhs ^= pivot_rhs;
+				},
+				None => {
+					self.pivots[col] = Some((row, rhs));
+					self.rank += 1;
+					return Ok(());
+				},
+			}
+		}
+		// nothing new, but it had better agree with what we have
+		match rhs {
+			true => Err("observations are inconsistent".to_string()),
+			false => Ok(()),
+		}
+	}
+
+	// undetermined unknowns come out as 0
+	fn solve(&self) -> BitRow {
+		let mut values = BitRow::zero(self.unknowns);
+		for col in (0..self.unknowns).rev() {
+			if let Some((row, rhs)) = &self.pivots[col] {
+				if row.dot(&values) != *rhs {
+					values.set(col);
+				}
+			}
+		}
+		values
+	}
+}
+
+// the state as bit rows: state[word][bit]
+fn symbolic_twist<W: MtWord>(params: &MtParams<W>, state: &mut Vec<Vec<BitRow>>) {
+	let (n, m, r, w) = (params.n, params.m, params.r as usize, params.w as usize);
+	let one = W::from_u64(1);
+	for i in 0..n {
+		let mut new = state[(i + m) % n].clone();
+		{
+			// x = (mt[i] & upper_mask) | (mt[i + 1] & lower_mask)
+			let x = |k: usize| match k >= r {
+				true => &state[i][k],
+				false => &state[(i + 1) % n][k],
+			};
+			// mt[i + m] ^ (x >> 1) ^ (a if x is odd)
+			for k in 0..w {
+				if k + 1 < w {
+					new[k].xor_from(x(k + 1), 0);
+				}
+				if (params.a >> k as u32) & one == one {
+					new[k].xor_from(x(0), 0);
+				}
+			}
+		}
+		state[i] = new;
+	}
+}
+
+pub struct Recovered<W: MtWord> {
+	pub params: MtParams<W>,
+	pub state: Vec<W>,		// untempered words behind outputs 0..n-1
+	pub rank: usize,
+	pub unknowns: usize,
+	pub undetermined: Vec<usize>,	// the unknowns nothing pinned down, word * w + bit
+}
+
+impl<W: MtWord> Recovered<W> {
+	pub fn rank_deficiency(&self) -> usize {
+		self.unknowns - self.rank
+	}
+
+	// a generator whose next output is output index. with a rank deficiency this is only right if
+	// the undetermined bits don't reach that far
+	pub fn clone_at(&self, index: usize) -> MersenneTwister<W> {
+		let mut clone = MersenneTwister::from_state_at(self.params, &self.state, 0).expect("recovered state is the wrong size");
+		for _ in 0..index {
+			clone.extract_number();
+		}
+		clone
+	}
+}
+
+pub fn recover_state<W: MtWord>(params: MtParams<W>, observations: &[Observation]) -> Result<Recovered<W>, String> {
+	let (n, w) = (params.n, params.w as usize);
+	let unknowns = n * w;
+	if let Some(o) = observations.iter().find(|o| o.bit >= params.w) {
+		return Err(format!("bit {} of output {} doesn't exist", o.bit, o.index));
+	}
+	let mut observations = observations.to_vec();
+	observations.sort_by_key(|o| o.index);
+
+	// temper is linear too: output bit j is the xor of the state bits k where temper(1 << k) has bit j set
+	let one = W::from_u64(1);
+	let temper_cols: Vec<W> = (0..w).map(|k| params.temper(one << k as u32)).collect();
+
+	let mut state: Vec<Vec<BitRow>> = (0..n)
+		.map(|i| (0..w).map(|k| BitRow::unit(unknowns, i * w + k)).collect())
+		.collect();
+	let mut period = 0;
+	let mut eliminator = Eliminator::new(unknowns);
+	for o in observations.iter() {
+		if eliminator.rank == unknowns {
+			break;
+		}
+		while o.index >= (period + 1) * n {
+			symbolic_twist(&params, &mut state);
+			period += 1;
+		}
+		let word = &state[o.index - period * n];
+		let mut row = BitRow::zero(unknowns);
+		for k in 0..w {
+			if (temper_cols[k] >> o.bit) & one == one {
+				row.xor_from(&word[k], 0);
+			}
+		}
+		eliminator.add(row, o.value)?;
+	}
+
+	let values = eliminator.solve();
+	let undetermined = (0..unknowns).filter(|&i| eliminator.pivots[i].is_none()).collect();
+	let state = (0..n)
+		.map(|i| (0..w).filter(|&k| values.get(i * w + k)).fold(W::from_u64(0), |acc, k| acc | (one << k as u32)))
+		.collect();
+	Ok(Recovered{params: params, state: state, rank: eliminator.rank, unknowns: unknowns, undetermined: undetermined})
+}
+
+fn report(name: &str, observations: &[Observation], target: &mut ex21::MtPrng, next_index: usize) {
+	match recover_state(ex21::MT19937, observations) {
+		Ok(recovered) => {
+			let mut clone = recovered.clone_at(next_index);
+			let synced = (0..1000).all(|_| clone.extract_number() == target.extract_number());
+			println!("{}: {} equations, rank {} of {} ({} undetermined), clone predicts the next 1000 outputs: {}",
+				name, observations.len(), recovered.rank, recovered.unknowns, recovered.rank_deficiency(), synced);
+		},
+		Err(e) => println!("{}: could not recover state: {}", name, e),
+	}
+}
+
+pub fn demonstrate() {
+	// rand() % 256 after someone else took the first 1000 outputs
+	let mut target = ex21::MtPrng::new(thread_rng().gen());
+	let mut observations = vec![];
+	for i in 0..5000 {
+		let y = target.extract_number();
+		if i >= 1000 {
+			observations.append(&mut observe_bits(i, 0xFF, y & 0xFF));
+		}
+	}
+	report("low bytes", &observations, &mut target, 5000);
+
+	// every other genrand_res53 double
+	let mut target = ex21::MtPrng::new(thread_rng().gen());
+	let mut observations = vec![];
+	for i in 0..1600 {
+		let x = target.genrand_res53();
+		if i % 2 == 0 {
+			observations.append(&mut observe_res53(2 * i, x));
+		}
+	}
+	report("every other double", &observations, &mut target, 3200);
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::mt_recovery::{observe_bits, observe_res53, recover_state, Observation};
+	use crate::set3::ex21::{self, MersenneTwister, MtParams};
+
+	// small enough to solve quickly in a debug build, the algebra is the same
+	const TOY: MtParams<u32> = MtParams {
+		w: 32, n: 13, m: 7, r: 5,
+		a: 0xCCAB8EE7,
+		u: 11, d: 0xFFFFFFFF,
+		s: 7, b: 0x31B6AB00,
+		t: 15, c: 0xFFE50000,
+		l: 17,
+		f: 1812433253,
+		array_f1: 1664525,
+		array_f2: 1566083941,
+	};
+
+	#[test]
+	fn test_top_bits_with_gaps() {
+		let mut target = MersenneTwister::with_params(TOY, 0xdeadbeef);
+		let mut observations = vec![];
+		for i in 0..400 {
+			let y = target.extract_number();
+			if i % 3 != 1 {
+				observations.append(&mut observe_bits(i, 0xF0000000, y));
+			}
+		}
+		// the low r bits of the first word only show up in output 0, and we only saw 4 bits of that
+		let recovered = recover_state(TOY, &observations).unwrap();
+		assert!(recovered.rank_deficiency() <= TOY.r as usize);
+		let mut clone = recovered.clone_at(400);
+		for _ in 0..100 {
+			assert_eq!(clone.extract_number(), target.extract_number());
+		}
+		// and back at the start too
+		let mut fresh = MersenneTwister::with_params(TOY, 0xdeadbeef);
+		let mut clone = recovered.clone_at(0);
+		assert_eq!(clone.extract_number() >> 28, fresh.extract_number() >> 28);
+		for _ in 0..100 {
+			assert_eq!(clone.extract_number(), fresh.extract_number());
+		}
+	}
+
+	#[test]
+	fn test_rank_deficiency() {
+		// skipping output 0 leaves the low r bits of the first word undetermined
+		let mut target = MersenneTwister::with_params(TOY, 1);
+		let mut observations = vec![];
+		for i in 0..200 {
+			let y = target.extract_number();
+			if i > 0 {
+				observations.append(&mut observe_bits(i, 0xFFFFFFFF, y));
+			}
+		}
+		let recovered = recover_state(TOY, &observations).unwrap();
+		assert_eq!(recovered.rank_deficiency(), TOY.r as usize);
+		assert_eq!(recovered.undetermined, (0..TOY.r as usize).collect::<Vec<usize>>());
+		let mut clone = recovered.clone_at(1);
+		let mut fresh = MersenneTwister::with_params(TOY, 1);
+		fresh.extract_number();
+		for _ in 0..100 {
+			assert_eq!(clone.extract_number(), fresh.extract_number());
+		}
+
+		// not enough to go on at all
+		let recovered = recover_state(TOY, &observe_bits(3, 0xFFu32, 0x12)).unwrap();
+		assert_eq!(recovered.rank, 8);
+	}
+
+	#[test]
+	fn test_inconsistent() {
+		let mut observations = observe_bits(0, 0xFFFFFFFFu32, 0x12345678);
+		observations.push(Observation{index: 0, bit: 0, value: true});
+		assert!(recover_state(TOY, &observations).is_err());
+		assert!(recover_state(TOY, &[Observation{index: 0, bit: 32, value: true}]).is_err());
+	}
+
+	#[test]
+	fn test_mt19937_doubles() {
+		let mut target = ex21::MtPrng::new(5489);
+		let observations: Vec<Observation> = (0..700)
+			.flat_map(|i| observe_res53(2 * i, target.genrand_res53()))
+			.collect();
+		// 27 bits of output 0 for the 31 low bits of the first word that nothing else depends on
+		let recovered = recover_state(ex21::MT19937, &observations).unwrap();
+		assert_eq!(recovered.rank_deficiency(), 4);
+		let mut clone = recovered.clone_at(1400);
+		for _ in 0..1000 {
+			assert_eq!(clone.extract_number(), target.extract_number());
+		}
+	}
+}
@@ -0,0 +1,280 @@
+/*
+
+Oracle instrumentation
+
+Most of the attacks in these sets talk to some "server" function that we pretend we can't see inside of:
+an encryption oracle, an edit API, a padding check. This wraps any of them so we can see how hard
+we're hammering it: number of queries, bytes in and out, time spent waiting on it, and an optional
+budget after which it refuses to answer.
+
+Every request/response pair is also kept in a transcript, which can be written to a file and loaded
+back as a ReplayOracle. The replay answers the same queries in the same order without needing the
+key, so an attack run can be reproduced exactly (in a test, say) after the fact. Errors count as
+answers, both against the budget and in the transcript: for a padding oracle, or ex27's receiver,
+the error is the whole point.
+
+Transcript format is one exchange per line: hex request, a space, then either the hex response or
+a ! followed by the error message in hex.
+
+*/
+
+use crate::utils;
+use std::cell::{Cell, RefCell};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Write};
+use std::time::{Duration, Instant};
+
+pub trait Oracle {
+	fn query(&self, inp: &[u8]) -> Result<Vec<u8>, String>;
+}
+
+// lets plain closures act as oracles, e.g. |inp| ske.encrypt_aes128ecb(&mut inp.to_vec(), None)
+impl<F> Oracle for F where F: Fn(&[u8]) -> Vec<u8> {
+	fn query(&self, inp: &[u8]) -> Result<Vec<u8>, String> {
+		Ok(self(inp))
+	}
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct OracleStats {
+	pub queries: usize,
+	pub bytes_sent: usize,
+	pub bytes_received: usize,
+	pub total_latency: Duration,
+	pub max_latency: Duration,
+}
+
+impl OracleStats {
+	pub fn mean_latency(&self) -> Duration {
+		match self.queries {
+			0 => Duration::from_secs(0),
+			n => self.total_latency / n as u32,
+		}
+	}
+
+	pub fn print(&self) {
+		println!("oracle queries: {}, bytes sent: {}, bytes received: {}, mean latency: {:?}, max latency: {:?}",
+			self.queries, self.bytes_sent, self.bytes_received, self.mean_latency(), self.max_latency
+		);
+	}
+}
+
+// each query with what came back, errors included
+pub type Transcript = Vec<(Vec<u8>, Result<Vec<u8>, String>)>;
+
+pub struct InstrumentedOracle<O: Oracle> {
+	inner: O,
+	budget: Option<usize>,
+	stats: RefCell<OracleStats>,
+	transcript: RefCell<Transcript>,
+}
+
+impl<O: Oracle> InstrumentedOracle<O> {
+	// budget of None means unlimited queries
+	pub fn new(inner: O, budget: Option<usize>) -> InstrumentedOracle<O> {
+		InstrumentedOracle {
+			inner: inner,
+			budget: budget,
+			stats: RefCell::new(OracleStats::default()),
+			transcript: RefCell::new(vec![]),
+		}
+	}
+
+	pub fn stats(&self) -> OracleStats {
+		self.stats.borrow().clone()
+	}
+
+	pub fn save_transcript(&self, filename: &str) -> Result<(), String> {
+		let mut file = File::create(filename).map_err(|e| format!("could not create {}: {}", filename, e))?;
+		for (request, response) in self.transcript.borrow().iter() {
+			let response = match response {
+				Ok(r) => utils::bytes_to_hex_string(r),
+				Err(e) => format!("!{}", utils::bytes_to_hex_string(e.as_bytes())),
+			};
+			writeln!(file, "{} {}", utils::bytes_to_hex_string(request), response)
+				.map_err(|e| format!("could not write to {}: {}", filename, e))?;
+		}
+		Ok(())
+	}
+}
+
+impl<O: Oracle> Oracle for InstrumentedOracle<O> {
+	fn query(&self, inp: &[u8]) -> Result<Vec<u8>, String> {
+		if let Some(budget) = self.budget {
+			if self.stats.borrow().queries >= budget {
+				return Err(format!("oracle query budget of {} exhausted", budget));
+			}
+		}
+		let start = Instant::now();
+		let response = self.inner.query(inp);
+		let elapsed = start.elapsed();
+
+		let mut stats = self.stats.borrow_mut();
+		stats.queries += 1;
+		stats.bytes_sent += inp.len();
+		if let Ok(r) = &response {
+			stats.bytes_received += r.len();
+		}
+		stats.total_latency += elapsed;
+		if elapsed > stats.max_latency {
+			stats.max_latency = elapsed;
+		}
+		self.transcript.borrow_mut().push((inp.to_vec(), response.clone()));
+		response
+	}
+}
+
+// answers queries from a recorded transcript, in order. any deviation from the recorded run is an error.
+pub struct ReplayOracle {
+	transcript: Transcript,
+	position: Cell<usize>,
+}
+
+impl ReplayOracle {
+	pub fn new(transcript: Transcript) -> ReplayOracle {
+		ReplayOracle{transcript: transcript, position: Cell::new(0)}
+	}
+
+	pub fn load(filename: &str) -> Result<ReplayOracle, String> {
+		let file = File::open(filename).map_err(|e| format!("could not open {}: {}", filename, e))?;
+		let mut transcript = vec![];
+		for (i, line) in BufReader::new(file).lines().enumerate() {
+			let line = line.map_err(|e| format!("could not read {}: {}", filename, e))?;
+			let mut fields = line.split(' ');
+			match (fields.next(), fields.next(), fields.next()) {
+				(Some(request), Some(response), None) => {
+					let is_hex = |s: &str| s.len() % 2 == 0 && s.chars().all(|c| c.is_ascii_hexdigit());
+					let (is_error, response) = match response.starts_with('!') {
+						true => (true, &response[1..]),
+						false => (false, response),
+					};
+					if !is_hex(request) || !is_hex(response) {
+						return Err(format!("invalid hex on line {}", i + 1));
+					}
+					let response = utils::hex_string_to_bytes(response);
+					let response = match is_error {
+						true => Err(String::from_utf8(response).map_err(|_| format!("invalid error message on line {}", i + 1))?),
+						false => Ok(response),
+					};
+					transcript.push((utils::hex_string_to_bytes(request), response));
+				},
+				_ => return Err(format!("malformed transcript line {}", i + 1)),
+			}
+		}
+		Ok(ReplayOracle::new(transcript))
+	}
+
+	pub fn remaining(&self) -> usize {
+		self.transcript.len() - self.position.get()
+	}
+}
+
+impl Oracle for ReplayOracle {
+	fn query(&self, inp: &[u8]) -> Result<Vec<u8>, String> {
+		let i = self.position.get();
+		match self.transcript.get(i) {
+			Some((request, response)) => {
+				if request[..] != inp[..] {
+					return Err(format!("query {} does not match transcript", i));
+				}
+				self.position.set(i + 1);
+				response.clone()
+			},
+			None => Err(format!("transcript exhausted after {} queries", i)),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::oracle::{InstrumentedOracle, Oracle, ReplayOracle};
+	use crate::set2::ex12;
+
+	#[test]
+	fn test_budget() {
+		let oracle = InstrumentedOracle::new(|inp: &[u8]| inp.to_vec(), Some(3));
+		for _ in 0..3 {
+			assert!(oracle.query(b"abc").is_ok());
+		}
+		assert!(oracle.query(b"abc").is_err());
+		let stats = oracle.stats();
+		assert_eq!(stats.queries, 3);
+		assert_eq!(stats.bytes_sent, 9);
+		assert_eq!(stats.bytes_received, 9);
+
+		// errors are answers too
+		let failing = |inp: &[u8]| -> Result<Vec<u8>, String> {
+			match inp.is_empty() {
+				true => Err("empty".to_string()),
+				false => Ok(inp.to_vec()),
+			}
+		};
+		let oracle = InstrumentedOracle::new(FnOracle(failing), Some(2));
+		assert_eq!(oracle.query(b""), Err("empty".to_string()));
+		assert_eq!(oracle.query(b"ab"), Ok(b"ab".to_vec()));
+		assert_eq!(oracle.query(b"ab"), Err("oracle query budget of 2 exhausted".to_string()));
+		assert_eq!((oracle.stats().queries, oracle.stats().bytes_sent, oracle.stats().bytes_received), (2, 2, 2));
+	}
+
+	struct FnOracle<F: Fn(&[u8]) -> Result<Vec<u8>, String>>(F);
+
+	impl<F: Fn(&[u8]) -> Result<Vec<u8>, String>> Oracle for FnOracle<F> {
+		fn query(&self, inp: &[u8]) -> Result<Vec<u8>, String> {
+			(self.0)(inp)
+		}
+	}
+
+	#[test]
+	fn test_replay_errors() {
+		let leaky = |inp: &[u8]| -> Result<Vec<u8>, String> {
+			match inp.first() {
+				Some(0) => Err("non ascii char: ff00".to_string()),
+				_ => Ok(vec![1]),
+			}
+		};
+		let live = InstrumentedOracle::new(FnOracle(leaky), None);
+		let answers = vec![live.query(&[1, 2]), live.query(&[0, 3]), live.query(&[])];
+
+		let filename = std::env::temp_dir().join("rustpals_error_transcript.txt");
+		let filename = filename.to_str().unwrap();
+		live.save_transcript(filename).unwrap();
+		let replay = ReplayOracle::load(filename).unwrap();
+		assert_eq!(vec![replay.query(&[1, 2]), replay.query(&[0, 3]), replay.query(&[])], answers);
+		assert_eq!(replay.remaining(), 0);
+		std::fs::remove_file(filename).unwrap();
+	}
+
+	#[test]
+	fn test_record_and_replay() {
+		let ske = ex12::SingleKeyEncrypter::new();
+		let live = InstrumentedOracle::new(|inp: &[u8]| {
+			ske.encrypt_aes128ecb(&mut inp.to_vec(), Some(ex12::suffix()))
+		}, None);
+		let recovered = ex12::decrypt_suffix_with_oracle(&live).unwrap();
+		assert_eq!(recovered, ex12::suffix());
+
+		let filename = std::env::temp_dir().join("rustpals_ex12_transcript.txt");
+		let filename = filename.to_str().unwrap();
+		live.save_transcript(filename).unwrap();
+
+		// no key from here on
+		let replay = ReplayOracle::load(filename).unwrap();
+		assert_eq!(ex12::decrypt_suffix_with_oracle(&replay).unwrap(), recovered);
+		assert_eq!(replay.remaining(), 0);
+		assert!(replay.query(b"one more").is_err());
+		std::fs::remove_file(filename).unwrap();
+	}
+
+	#[test]
+	fn test_corrupted_transcript() {
+		let filename = std::env::temp_dir().join("rustpals_corrupted_transcript.txt");
+		let filename = filename.to_str().unwrap();
+		for contents in ["00ff 0102\n0g 00\n", "00ff 0102\n00 012\n", "00ff\n", "00 !0g\n", "00 !ff\n"].iter() {
+			std::fs::write(filename, contents).unwrap();
+			assert!(ReplayOracle::load(filename).is_err(), "{:?}", contents);
+		}
+		std::fs::write(filename, "00ff 0102\n").unwrap();
+		assert_eq!(ReplayOracle::load(filename).unwrap().query(&[0, 0xff]), Ok(vec![1, 2]));
+		std::fs::remove_file(filename).unwrap();
+	}
+}
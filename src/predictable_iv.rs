@@ -0,0 +1,177 @@
+/*
+
+CBC with predictable IVs (BEAST)
+
+SSL 3.0 and TLS 1.0 didn't send a fresh IV with each record, they just carried on the CBC chain: the
+IV for a record is the last ciphertext block of the one before. Anyone watching the wire knows it
+before the record is encrypted.
+
+That's enough for a chosen-plaintext attack on a secret that's sent after our data (a cookie after
+a path we control). Line the secret up so the byte we want is the last one in block j, with the
+previous 15 bytes known:
+
+C_j = E(C_{j-1} XOR P_j)
+
+To test a guess G for P_j, start the next record with G XOR IV_next XOR C_{j-1}. Its first block is
+E(IV_next XOR G XOR IV_next XOR C_{j-1}) = E(G XOR C_{j-1}), which is C_j if the guess was right.
+256 guesses per byte, worst case.
+
+The fixes: a random IV per record (TLS 1.1), or 1/n-1 record splitting (what browsers shipped),
+which puts the first byte of each message in a record of its own. The second record's IV is then
+the first record's ciphertext, which we only get to see after our chosen block has been encrypted.
+
+*/
+
+use crate::analysis;
+use crate::oracle::Oracle;
+use crate::set2::{ex9, ex10, ex11};
+use rand::{Rng, thread_rng};
+use std::cell::RefCell;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum IvPolicy {
+	Chained,		// iv is the last block of the previous record
+	Random,			// fresh random iv per record, sent in front of it
+	SplitRecords,	// chained, but the first byte goes in a record of its own
+}
+
+// encrypts (our data || secret) as one tls-ish record per query
+pub struct RecordOracle {
+	key: [u8; 16],
+	secret: Vec<u8>,
+	policy: IvPolicy,
+	last_block: RefCell<Vec<u8>>,
+}
+
+impl RecordOracle {
+	pub fn new(secret: &[u8], policy: IvPolicy) -> RecordOracle {
+		let mut iv = vec![0u8; 16];
+		thread_rng().fill(&mut iv[..]);
+		RecordOracle{key: ex11::gen_aes128_key(), secret: secret.to_vec(), policy: policy, last_block: RefCell::new(iv)}
+	}
+
+	// carries on the chain from the last record
+	fn encrypt_record(&self, plaintext: &[u8]) -> Vec<u8> {
+		let iv = self.last_block.borrow().clone();
+		let ciphertext = ex10::encrypt_aes128cbc(&ex9::pkcs7_padding(plaintext, 16), &self.key, &iv);
+		*self.last_block.borrow_mut() = ciphertext[ciphertext.len() - 16..].to_vec();
+		ciphertext
+	}
+}
+
+impl Oracle for RecordOracle {
+	fn query(&self, inp: &[u8]) -> Result<Vec<u8>, String> {
+		let mut plaintext = inp.to_vec();
+		plaintext.extend_from_slice(&self.secret);
+		Ok(match self.policy {
+			IvPolicy::Chained => self.encrypt_record(&plaintext),
+			IvPolicy::Random => {
+				let mut iv = vec![0u8; 16];
+				thread_rng().fill(&mut iv[..]);
+				let mut res = iv.clone();
+				res.append(&mut ex10::encrypt_aes128cbc(&ex9::pkcs7_padding(&plaintext, 16), &self.key, &iv));
+				res
+			},
+			IvPolicy::SplitRecords => {
+				let mut res = self.encrypt_record(&plaintext[..1]);
+				res.append(&mut self.encrypt_record(&plaintext[1..]));
+				res
+			},
+		})
+	}
+}
+
+// the attacker's view of the oracle: remembers the last ciphertext block, which should be the next iv
+struct ChainWatcher<'a, O: Oracle> {
+	oracle: &'a O,
+	next_iv: Vec<u8>,
+}
+
+impl<'a, O: Oracle> ChainWatcher<'a, O> {
+	fn query(&mut self, inp: &[u8]) -> Result<(Vec<u8>, Vec<u8>), String> {
+		let iv = self.next_iv.clone();
+		let ciphertext = self.oracle.query(inp)?;
+		if ciphertext.len() < iv.len() {
+			return Err("oracle returned less than a block".to_string());
+		}
+		self.next_iv = ciphertext[ciphertext.len() - iv.len()..].to_vec();
+		Ok((iv, ciphertext))
+	}
+}
+
+pub fn recover_secret<O: Oracle>(oracle: &O) -> Result<Vec<u8>, String> {
+	let (block_size, jump_filler_len) = analysis::find_block_size(oracle)?;
+	// at the jump, filler + secret is a whole number of blocks and padding adds one more
+	let jump_len = oracle.query(&vec![b'A'; jump_filler_len])?.len();
+	let secret_len = jump_len - block_size - jump_filler_len;
+
+	let mut watcher = ChainWatcher{oracle: oracle, next_iv: vec![0; block_size]};
+	watcher.query(b"")?;
+
+	let mut known: Vec<u8> = vec![];
+	for i in 0..secret_len {
+		let filler = vec![b'A'; block_size - 1 - (i % block_size)];
+		let j = i / block_size;
+		let (iv, ciphertext) = watcher.query(&filler)?;
+		let target = ciphertext[j * block_size..(j + 1) * block_size].to_vec();
+		let previous = match j {
+			0 => iv,
+			_ => ciphertext[(j - 1) * block_size..j * block_size].to_vec(),
+		};
+
+		// the 15 bytes in front of the one we want
+		let mut window = filler.clone();
+		window.extend_from_slice(&known);
+		let mut guess_block = window[window.len() - (block_size - 1)..].to_vec();
+		guess_block.push(0);
+
+		let mut found = None;
+		for g in 0..=255 {
+			guess_block[block_size - 1] = g;
+			let chosen: Vec<u8> = guess_block.iter().zip(watcher.next_iv.iter()).zip(previous.iter())
+				.map(|((a, b), c)| a ^ b ^ c)
+				.collect();
+			let (_, ciphertext) = watcher.query(&chosen)?;
+			if ciphertext[..block_size] == target[..] {
+				found = Some(g);
+				break;
+			}
+		}
+		match found {
+			Some(g) => known.push(g),
+			None => return Err(format!("no guess matched secret byte {}, iv doesn't look predictable", i)),
+		}
+	}
+	Ok(known)
+}
+
+pub fn demonstrate() {
+	let secret = b"Cookie: session=7f3b9e0c51a24d68";
+	for policy in [IvPolicy::Chained, IvPolicy::Random, IvPolicy::SplitRecords].iter() {
+		let oracle = RecordOracle::new(secret, *policy);
+		match recover_secret(&oracle) {
+			Ok(recovered) => println!("{:?}: recovered {}", policy, String::from_utf8_lossy(&recovered)),
+			Err(e) => println!("{:?}: attack failed: {}", policy, e),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::predictable_iv::{recover_secret, IvPolicy, RecordOracle};
+
+	#[test]
+	fn test_chained_ivs() {
+		let secret = b"Cookie: session=0123456789abcdef; path=/";
+		let oracle = RecordOracle::new(secret, IvPolicy::Chained);
+		assert_eq!(recover_secret(&oracle).unwrap(), secret.to_vec());
+	}
+
+	#[test]
+	fn test_hardened_ivs() {
+		let secret = b"Cookie: session=0123456789abcdef";
+		for policy in [IvPolicy::Random, IvPolicy::SplitRecords].iter() {
+			assert!(recover_secret(&RecordOracle::new(secret, *policy)).is_err());
+		}
+	}
+}
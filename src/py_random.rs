@@ -0,0 +1,286 @@
+/*
+
+Python's random module
+
+CPython's random is MT19937 too, seeded with init_by_array on the 32 bit words of abs(seed), least
+significant first (MtPrng::seed_from_u64 already does that). Everything else is built out of whole
+outputs:
+
+	getrandbits(k)	the top k bits of an output for k <= 32, otherwise whole outputs from the least
+					significant word up, with the last one shifted down to what's left
+	random()		genrand_res53, 27 bits of one output and 26 of the next
+	randrange		getrandbits(bit length of n) until it's below n, so an unknown number of outputs
+
+So anything that leaks getrandbits(32) or random() values leaks the state. 624 getrandbits(32) can
+go straight through ex23, random() needs the GF(2) solver since 11 bits of each pair are missing.
+Either way we model the outputs from the first one we saw: the untempered outputs follow the
+twist's recurrence whether or not a twist happened there.
+
+*/
+
+use crate::mt_recovery::{self, Observation};
+use crate::set3::ex21::{self, MtPrng};
+use rand::{SeedableRng, Rng, thread_rng};
+
+pub struct PyRandom {
+	mt: MtPrng,
+}
+
+impl PyRandom {
+	// random.seed(n) for n >= 0
+	pub fn new(seed: u64) -> PyRandom {
+		PyRandom{mt: MtPrng::seed_from_u64(seed)}
+	}
+
+	pub fn from_twister(mt: MtPrng) -> PyRandom {
+		PyRandom{mt: mt}
+	}
+
+	// python takes any k, we stop at 64
+	pub fn getrandbits(&mut self, k: u32) -> u64 {
+		assert!(k <= 64, "getrandbits only goes up to 64 bits here");
+		match k {
+			0 => 0,
+			1..=32 => (self.mt.extract_number() >> (32 - k)) as u64,
+			_ => {
+				let low = self.mt.extract_number() as u64;
+				let high = (self.mt.extract_number() >> (64 - k)) as u64;
+				(high << 32) | low
+			},
+		}
+	}
+
+	pub fn random(&mut self) -> f64 {
+		self.mt.genrand_res53()
+	}
+
+	// _randbelow_with_getrandbits
+	pub fn randbelow(&mut self, n: u64) -> u64 {
+		assert!(n > 0, "randbelow needs n > 0");
+		let k = 64 - n.leading_zeros();
+		let mut r = self.getrandbits(k);
+		while r >= n {
+			r = self.getrandbits(k);
+		}
+		r
+	}
+
+	pub fn randrange(&mut self, start: i64, stop: i64, step: i64) -> Result<i64, String> {
+		self.randrange_wide(start as i128, stop as i128, step as i128)
+	}
+
+	// in i128 so randint's stop can be one past i64::MAX
+	fn randrange_wide(&mut self, start: i128, stop: i128, step: i128) -> Result<i64, String> {
+		let width = stop - start;
+		// python floors these, but they only differ when the result is negative anyway
+		let n = match step {
+			0 => return Err("zero step for randrange()".to_string()),
+			s if s > 0 => (width + s - 1) / s,
+			s => (width + s + 1) / s,
+		};
+		if n <= 0 {
+			return Err("empty range for randrange()".to_string());
+		}
+		if n > u64::MAX as i128 {
+			return Err("randrange() over more than 2^64 values isn't supported here".to_string());
+		}
+		Ok((start + step * self.randbelow(n as u64) as i128) as i64)
+	}
+
+	pub fn randint(&mut self, a: i64, b: i64) -> Result<i64, String> {
+		self.randrange_wide(a as i128, b as i128 + 1, 1)
+	}
+}
+
+// everything the target's random module gave out after some point, in order
+pub struct Predictor {
+	observations: Vec<Observation>,
+	next_index: usize,	// outputs used so far
+}
+
+impl Predictor {
+	pub fn new() -> Predictor {
+		Predictor{observations: vec![], next_index: 0}
+	}
+
+	pub fn observe_getrandbits32(&mut self, x: u32) {
+		self.observations.append(&mut mt_recovery::observe_bits(self.next_index, 0xFFFFFFFFu32, x));
+		self.next_index += 1;
+	}
+
+	pub fn observe_random(&mut self, x: f64) {
+		self.observations.append(&mut mt_recovery::observe_res53(self.next_index, x));
+		self.next_index += 2;
+	}
+
+	// outputs the target used that we didn't get to see
+	pub fn skip(&mut self, outputs: usize) {
+		self.next_index += outputs;
+	}
+
+	// a PyRandom that carries on where the target is now
+	pub fn predict(&self) -> Result<PyRandom, String> {
+		let recovered = mt_recovery::recover_state(ex21::MT19937, &self.observations)?;
+		// the low r bits of the first word only matter for output 0, which is behind us. anything
+		// else left undetermined would make the predictions wrong
+		if let Some(i) = recovered.undetermined.iter().find(|&&i| i >= ex21::MT19937.r as usize) {
+			return Err(format!("not enough observations, only {} of {} state bits pinned down (bit {} of word {} isn't)",
+				recovered.rank, recovered.unknowns, i % 32, i / 32));
+		}
+		Ok(PyRandom::from_twister(recovered.clone_at(self.next_index)))
+	}
+}
+
+pub fn demonstrate() {
+	// session ids from random(), starting some way into the target's life
+	let mut target = PyRandom::new(thread_rng().gen());
+	for _ in 0..thread_rng().gen_range(0, 2000) {
+		target.random();
+	}
+	let mut predictor = Predictor::new();
+	for _ in 0..800 {
+		predictor.observe_random(target.random());
+	}
+	match predictor.predict() {
+		Ok(mut predicted) => {
+			let right = (0..10).all(|_| predicted.randint(1, 1000000) == target.randint(1, 1000000));
+			println!("after 800 random() calls, next 10 randint(1, 1000000) predicted: {}", right);
+		},
+		Err(e) => println!("could not predict: {}", e),
+	}
+
+	// 32 bit tokens from getrandbits, except every tenth went to someone else
+	let mut predictor = Predictor::new();
+	for i in 0..1300 {
+		let token = target.getrandbits(32);
+		match i % 10 {
+			9 => predictor.skip(1),
+			_ => predictor.observe_getrandbits32(token as u32),
+		}
+	}
+	match predictor.predict() {
+		Ok(mut predicted) => {
+			let right = (0..10).all(|_| predicted.randrange(0, 1000000, 5) == target.randrange(0, 1000000, 5));
+			println!("after 1170 of 1300 getrandbits(32) tokens, next 10 randrange(0, 1000000, 5) predicted: {}", right);
+		},
+		Err(e) => println!("could not predict: {}", e),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::mt_recovery;
+	use crate::py_random::{PyRandom, Predictor};
+
+	// from cpython 3.11
+	#[test]
+	fn test_cpython_vectors() {
+		let vectors: [(u64, [u64; 3], [u64; 4], [f64; 3], [i64; 5], [i64; 5], [i64; 6]); 3] = [
+			(0, [3626764237, 1654615998, 3255389356], [113, 44756014165, 17809683713383489082, 1],
+				[0.48592769656281265, 0.9182343317851318, 0.8298529036589914],
+				[991, 488, 366, 597, 913], [-23, 14, -33, -14, -33], [87, 10, 66, 16, 72, 44]),
+			(42, [2746317213, 478163327, 107420369], [94, 267469214295, 2574020394472462046, 1],
+				[0.1024951761715075, 0.7406677446676758, 0.5453665337483498],
+				[604, 432, 32, 30, 95], [-23, -21, 14, 27, -47], [59, 24, 80, 30, 23, 44]),
+			((1 << 40) + 5, [2166296868, 2220160828, 1153647273], [84, 34040994016, 13990771555713216969, 1],
+				[0.8342667355137507, 0.9561507918590162, 0.7606690666939874],
+				[47, 169, 81, 815, 835], [-33, -20, 33, 37, -33], [24, 73, 87, 58, 23, 93]),
+		];
+		for (seed, bits32, bits, floats, below, ints, stepped) in vectors.iter() {
+			let mut r = PyRandom::new(*seed);
+			for x in bits32.iter() {
+				assert_eq!(r.getrandbits(32), *x);
+			}
+			for (k, x) in [7, 40, 64, 1].iter().zip(bits.iter()) {
+				assert_eq!(r.getrandbits(*k), *x);
+			}
+			for x in floats.iter() {
+				assert_eq!(r.random(), *x);
+			}
+			for x in below.iter() {
+				assert_eq!(r.randrange(0, 1000, 1).unwrap(), *x);
+			}
+			for x in ints.iter() {
+				assert_eq!(r.randint(-50, 50).unwrap(), *x);
+			}
+			for x in stepped[..3].iter() {
+				assert_eq!(r.randrange(3, 100, 7).unwrap(), *x);
+			}
+			for x in stepped[3..].iter() {
+				assert_eq!(r.randrange(100, 3, -7).unwrap(), *x);
+			}
+		}
+		let mut r = PyRandom::new(0);
+		assert!(r.randrange(5, 5, 1).is_err());
+		assert!(r.randrange(0, 10, 0).is_err());
+		assert!(r.randrange(0, 10, -1).is_err());
+
+		// the top of the range is allowed, python's ints don't overflow
+		let mut r = PyRandom::new(5);
+		for x in [9223372036854775807, 9223372036854775802, 9223372036854775803, 9223372036854775806, 9223372036854775798].iter() {
+			assert_eq!(r.randint(i64::MAX - 9, i64::MAX).unwrap(), *x);
+		}
+		assert_eq!(r.randint(i64::MAX, i64::MAX), Ok(i64::MAX));
+		assert!(r.randint(5, 4).is_err());
+		assert!(r.randint(i64::MIN, i64::MAX).is_err());
+	}
+
+	// random.Random(31337), and what it gave next in cpython
+	#[test]
+	fn test_predict_getrandbits() {
+		let mut target = PyRandom::new(31337);
+		let mut predictor = Predictor::new();
+		for _ in 0..624 {
+			predictor.observe_getrandbits32(target.getrandbits(32) as u32);
+		}
+		let mut predicted = predictor.predict().unwrap();
+		assert_eq!([predicted.getrandbits(32), predicted.getrandbits(32), predicted.getrandbits(32)], [3564471986, 181800629, 994881700]);
+		assert_eq!(predicted.random(), 0.5639558127308845);
+		assert_eq!(predicted.randint(1, 6).unwrap(), 4);
+	}
+
+	#[test]
+	fn test_predict_random() {
+		let mut target = PyRandom::new(31337);
+		let mut predictor = Predictor::new();
+		for _ in 0..800 {
+			predictor.observe_random(target.random());
+		}
+		let mut predicted = predictor.predict().unwrap();
+		assert_eq!([predicted.getrandbits(32), predicted.getrandbits(32), predicted.getrandbits(32)], [2984663204, 445023686, 2926860857]);
+		assert_eq!(predicted.random(), 0.35558516042225885);
+		assert_eq!(predicted.randint(1, 6).unwrap(), 4);
+
+		// with a gap, and starting mid generation
+		let mut target = PyRandom::new(7);
+		for _ in 0..100 {
+			target.getrandbits(32);
+		}
+		let mut predictor = Predictor::new();
+		for i in 0..900 {
+			match i % 10 {
+				0 => { target.random(); predictor.skip(2) },
+				_ => predictor.observe_random(target.random()),
+			}
+		}
+		let mut predicted = predictor.predict().unwrap();
+		for _ in 0..100 {
+			assert_eq!(predicted.random(), target.random());
+		}
+
+		assert!(Predictor::new().predict().is_err());
+
+		// 4 bits short, but of output 100 rather than output 0, so no prediction
+		let mut target = PyRandom::new(7);
+		let mut predictor = Predictor::new();
+		for i in 0..624 {
+			let x = target.getrandbits(32) as u32;
+			match i {
+				100 => predictor.observations.append(&mut mt_recovery::observe_bits(i, 0xFFFFFFF0u32, x)),
+				_ => predictor.observations.append(&mut mt_recovery::observe_bits(i, 0xFFFFFFFFu32, x)),
+			}
+		}
+		predictor.next_index = 624;
+		assert!(predictor.predict().is_err());
+	}
+}
@@ -0,0 +1,458 @@
+/*
+
+Statistical randomness tests
+
+Part of NIST SP 800-22: monobit, block frequency, runs, longest run of ones, serial, approximate
+entropy and cumulative sums, plus a chi-squared test on the byte histogram. Each gives a p-value,
+the probability of something at least this far from what a random sequence does, and anything
+under 0.01 is a fail.
+
+None of this says a generator is secure. MT19937 passes all of it (and ex23 clones it from 624
+outputs). What it catches is the badly broken: counters, short periods, biased bits, a keystream
+that isn't being generated the way we think it is.
+
+*/
+
+use crate::py_random::PyRandom;
+use crate::set3::{ex18, ex21, ex24};
+use crate::weak_prng::{GlibcRand, JavaRandom, MsvcRand, Pcg32, XorShift128Plus};
+use rand::{RngCore, SeedableRng, thread_rng};
+
+pub const ALPHA: f64 = 0.01;
+
+// erfc from numerical recipes, good to about 1.2e-7
+fn erfc(x: f64) -> f64 {
+	let z = x.abs();
+	let t = 1.0 / (1.0 + 0.5 * z);
+	let ans = t * (-z * z - 1.26551223 + t * (1.00002368 + t * (0.37409196 + t * (0.09678418 +
+		t * (-0.18628806 + t * (0.27886807 + t * (-1.13520398 + t * (1.48851587 +
+		t * (-0.82215223 + t * 0.17087277))))))))).exp();
+	match x >= 0.0 {
+		true => ans,
+		false => 2.0 - ans,
+	}
+}
+
+fn normal_cdf(x: f64) -> f64 {
+	0.5 * erfc(-x / 2f64.sqrt())
+}
+
+fn ln_gamma(x: f64) -> f64 {
+	let coefficients = [76.18009172947146, -86.50532032941677, 24.01409824083091,
+		-1.231739572450155, 0.1208650973866179e-2, -0.5395239384953e-5];
+	let mut tmp = x + 5.5;
+	tmp -= (x + 0.5) * tmp.ln();
+	let mut y = x;
+	let mut ser = 1.000000000190015;
+	for c in coefficients.iter() {
+		y += 1.0;
+		ser += c / y;
+	}
+	-tmp + (2.5066282746310005 * ser / x).ln()
+}
+
+// the regularised upper incomplete gamma function Q(a, x), as a series below a + 1 and a continued
+// fraction above
+fn igamc(a: f64, x: f64) -> f64 {
+	let (eps, tiny) = (1e-15, 1e-300);
+	if x <= 0.0 {
+		return 1.0;
+	}
+	let front = (-x + a * x.ln() - ln_gamma(a)).exp();
+	if x < a + 1.0 {
+		let (mut ap, mut del) = (a, 1.0 / a);
+		let mut sum = del;
+		while del.abs() > sum.abs() * eps {
+			ap += 1.0;
+			del *= x / ap;
+			sum += del;
+		}
+		return 1.0 - sum * front;
+	}
+	let mut b = x + 1.0 - a;
+	let mut c = 1.0 / tiny;
+	let mut d = 1.0 / b;
+	let mut h = d;
+	for i in 1..10000 {
+		let an = -(i as f64) * (i as f64 - a);
+		b += 2.0;
+		d = an * d + b;
+		if d.abs() < tiny {
+			d = tiny;
+		}
+		c = b + an / c;
+		if c.abs() < tiny {
+			c = tiny;
+		}
+		d = 1.0 / d;
+		let del = d * c;
+		h *= del;
+		if (del - 1.0).abs() < eps {
+			break;
+		}
+	}
+	front * h
+}
+
+// most significant bit first, one 0 or 1 per byte
+pub fn bits_of(bytes: &[u8]) -> Vec<u8> {
+	bytes.iter().flat_map(|b| (0..8).rev().map(move |i| (b >> i) & 1)).collect()
+}
+
+pub fn monobit(bits: &[u8]) -> f64 {
+	let n = bits.len() as f64;
+	let sum: i64 = bits.iter().map(|&b| 2 * b as i64 - 1).sum();
+	erfc(sum.abs() as f64 / n.sqrt() / 2f64.sqrt())
+}
+
+pub fn block_frequency(bits: &[u8], m: usize) -> Result<f64, String> {
+	if m == 0 || m > bits.len() {
+		return Err(format!("block frequency needs a block size from 1 to {}, not {}", bits.len(), m));
+	}
+	let blocks = bits.len() / m;
+	let chi_squared: f64 = bits.chunks(m).take(blocks)
+		.map(|block| block.iter().map(|&b| b as f64).sum::<f64>() / m as f64 - 0.5)
+		.map(|d| 4.0 * m as f64 * d * d)
+		.sum();
+	Ok(igamc(blocks as f64 / 2.0, chi_squared / 2.0))
+}
+
+pub fn runs(bits: &[u8]) -> f64 {
+	let n = bits.len() as f64;
+	let pi = bits.iter().map(|&b| b as f64).sum::<f64>() / n;
+	// no point counting runs if monobit already failed badly
+	if (pi - 0.5).abs() >= 2.0 / n.sqrt() {
+		return 0.0;
+	}
+	let v = 1 + bits.windows(2).filter(|w| w[0] != w[1]).count();
+	let expected = 2.0 * n * pi * (1.0 - pi);
+	erfc((v as f64 - expected).abs() / (2.0 * (2.0 * n).sqrt() * pi * (1.0 - pi)))
+}
+
+pub fn longest_run(bits: &[u8]) -> Result<f64, String> {
+	// block size, the smallest run length class, and the class probabilities
+	let (m, v0, pi): (usize, usize, &[f64]) = match bits.len() {
+		n if n >= 750000 => (10000, 10, &[0.0882, 0.2092, 0.2483, 0.1933, 0.1208, 0.0675, 0.0727]),
+		n if n >= 6272 => (128, 4, &[0.1174, 0.2430, 0.2493, 0.1752, 0.1027, 0.1124]),
+		n if n >= 128 => (8, 1, &[0.2148, 0.3672, 0.2305, 0.1875]),
+		_ => return Err("longest run needs at least 128 bits".to_string()),
+	};
+	let blocks = bits.len() / m;
+	let mut counts = vec![0usize; pi.len()];
+	for block in bits.chunks(m).take(blocks) {
+		let (mut longest, mut current) = (0, 0);
+		for &b in block.iter() {
+			current = match b {
+				1 => current + 1,
+				_ => 0,
+			};
+			longest = longest.max(current);
+		}
+		let class = longest.max(v0).min(v0 + pi.len() - 1) - v0;
+		counts[class] += 1;
+	}
+	let chi_squared: f64 = counts.iter().zip(pi.iter())
+		.map(|(&c, &p)| (c as f64 - blocks as f64 * p).powi(2) / (blocks as f64 * p))
+		.sum();
+	Ok(igamc((pi.len() - 1) as f64 / 2.0, chi_squared / 2.0))
+}
+
+// how often each overlapping m bit pattern turns up, wrapping round at the end
+fn pattern_counts(bits: &[u8], m: usize) -> Vec<usize> {
+	let mut counts = vec![0; 1 << m];
+	let n = bits.len();
+	for i in 0..n {
+		let pattern = (0..m).fold(0, |acc, j| (acc << 1) | bits[(i + j) % n] as usize);
+		counts[pattern] += 1;
+	}
+	counts
+}
+
+fn psi_squared(bits: &[u8], m: usize) -> f64 {
+	if m == 0 {
+		return 0.0;
+	}
+	let n = bits.len() as f64;
+	let sum: f64 = pattern_counts(bits, m).iter().map(|&c| (c * c) as f64).sum();
+	(1 << m) as f64 / n * sum - n
+}
+
+// two p-values, for the m bit patterns and for the difference from m - 1 to m
+pub fn serial(bits: &[u8], m: usize) -> Result<(f64, f64), String> {
+	if bits.is_empty() {
+		return Err("serial needs some bits".to_string());
+	}
+	// one count for each of the 2^m patterns
+	if m == 0 || m > 24 {
+		return Err(format!("serial needs a pattern length from 1 to 24, not {}", m));
+	}
+	let (psi_m, psi_m1, psi_m2) = (psi_squared(bits, m), psi_squared(bits, m - 1), psi_squared(bits, m.saturating_sub(2)));
+	let del1 = psi_m - psi_m1;
+	let del2 = psi_m - 2.0 * psi_m1 + psi_m2;
+	Ok((igamc((1 << (m - 1)) as f64 / 2.0, del1 / 2.0), igamc((1 << (m - 1)) as f64 / 4.0, del2 / 2.0)))
+}
+
+fn phi(bits: &[u8], m: usize) -> f64 {
+	if m == 0 {
+		return 0.0;
+	}
+	let n = bits.len() as f64;
+	pattern_counts(bits, m).iter()
+		.filter(|&&c| c > 0)
+		.map(|&c| c as f64 / n * (c as f64 / n).ln())
+		.sum()
+}
+
+pub fn approximate_entropy(bits: &[u8], m: usize) -> f64 {
+	let n = bits.len() as f64;
+	let ap_en = phi(bits, m) - phi(bits, m + 1);
+	let chi_squared = 2.0 * n * (2f64.ln() - ap_en);
+	igamc((1 << m) as f64 / 2.0, chi_squared / 2.0)
+}
+
+// the biggest excursion of the random walk, from the front or from the back
+pub fn cumulative_sums(bits: &[u8], forward: bool) -> Result<f64, String> {
+	if bits.is_empty() {
+		return Err("cumulative sums needs some bits".to_string());
+	}
+	let steps: Vec<i64> = bits.iter().map(|&b| 2 * b as i64 - 1).collect();
+	let mut sum = 0i64;
+	let mut z = 0i64;
+	let mut step = |s: &i64| {
+		sum += s;
+		z = z.max(sum.abs());
+	};
+	match forward {
+		true => steps.iter().for_each(&mut step),
+		false => steps.iter().rev().for_each(&mut step),
+	}
+	let (n, z) = (bits.len() as f64, z as f64);
+	// truncating like the reference code does
+	let mut sum1 = 0.0;
+	for k in ((-n / z + 1.0) / 4.0) as i64..=((n / z - 1.0) / 4.0) as i64 {
+		let k = k as f64;
+		sum1 += normal_cdf((4.0 * k + 1.0) * z / n.sqrt()) - normal_cdf((4.0 * k - 1.0) * z / n.sqrt());
+	}
+	let mut sum2 = 0.0;
+	for k in ((-n / z - 3.0) / 4.0) as i64..=((n / z - 1.0) / 4.0) as i64 {
+		let k = k as f64;
+		sum2 += normal_cdf((4.0 * k + 3.0) * z / n.sqrt()) - normal_cdf((4.0 * k + 1.0) * z / n.sqrt());
+	}
+	Ok(1.0 - sum1 + sum2)
+}
+
+pub fn byte_chi_squared(bytes: &[u8]) -> f64 {
+	let mut counts = [0usize; 256];
+	for &b in bytes.iter() {
+		counts[b as usize] += 1;
+	}
+	let expected = bytes.len() as f64 / 256.0;
+	let chi_squared: f64 = counts.iter().map(|&c| (c as f64 - expected).powi(2) / expected).sum();
+	igamc(255.0 / 2.0, chi_squared / 2.0)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TestResult {
+	pub name: String,
+	pub p_value: f64,
+}
+
+impl TestResult {
+	pub fn passed(&self) -> bool {
+		self.p_value >= ALPHA
+	}
+}
+
+// everything, with nist's suggested parameters scaled down for short inputs
+pub fn run_battery(bytes: &[u8]) -> Result<Vec<TestResult>, String> {
+	if bytes.len() < 16 {
+		return Err("need at least 16 bytes".to_string());
+	}
+	let bits = bits_of(bytes);
+	let log_n = (bits.len() as f64).log2() as usize;
+	let serial_m = log_n.saturating_sub(3).clamp(2, 16);
+	let entropy_m = log_n.saturating_sub(7).clamp(1, 10);
+	let (serial1, serial2) = serial(&bits, serial_m)?;
+	let result = |name: String, p_value: f64| TestResult{name: name, p_value: p_value};
+	Ok(vec![
+		result("monobit".to_string(), monobit(&bits)),
+		result("block frequency (M = 128)".to_string(), block_frequency(&bits, 128.min(bits.len()))?),
+		result("runs".to_string(), runs(&bits)),
+		result("longest run of ones".to_string(), longest_run(&bits)?),
+		result(format!("serial (m = {})", serial_m), serial1),
+		result(format!("serial difference (m = {})", serial_m), serial2),
+		result(format!("approximate entropy (m = {})", entropy_m), approximate_entropy(&bits, entropy_m)),
+		result("cumulative sums forward".to_string(), cumulative_sums(&bits, true)?),
+		result("cumulative sums reverse".to_string(), cumulative_sums(&bits, false)?),
+		result("byte chi-squared".to_string(), byte_chi_squared(bytes)),
+	])
+}
+
+pub fn print_report(results: &[TestResult]) {
+	for r in results.iter() {
+		println!("{:<36} p = {:.6}  {}", r.name, r.p_value, match r.passed() {
+			true => "pass",
+			false => "FAIL",
+		});
+	}
+	println!("{} of {} passed at alpha = {}", results.iter().filter(|r| r.passed()).count(), results.len(), ALPHA);
+}
+
+pub const GENERATORS: &[&str] = &["mt19937", "mt19937-64", "mt11213", "python", "glibc", "msvc", "java",
+	"xorshift128+", "pcg32", "mt19937-stream", "ctr", "thread", "counter"];
+
+// bytes from every call, little endian, keeping only as many as the generator fills
+fn collect_words<F: FnMut() -> u64>(mut next: F, bytes_per_word: usize, len: usize) -> Vec<u8> {
+	let mut res = Vec::with_capacity(len + 8);
+	while res.len() < len {
+		res.extend_from_slice(&next().to_le_bytes()[..bytes_per_word]);
+	}
+	res.truncate(len);
+	res
+}
+
+pub fn generator_bytes(name: &str, seed: u64, len: usize) -> Result<Vec<u8>, String> {
+	Ok(match name {
+		"mt19937" => {
+			let mut res = vec![0; len];
+			ex21::MtPrng::seed_from_u64(seed).fill_bytes(&mut res);
+			res
+		},
+		"mt19937-64" => {
+			let mut res = vec![0; len];
+			ex21::MtPrng64::seed_from_u64(seed).fill_bytes(&mut res);
+			res
+		},
+		"mt11213" => {
+			let mut rng = ex21::MersenneTwister::with_params(ex21::MT11213, seed as u32);
+			collect_words(|| rng.extract_number() as u64, 4, len)
+		},
+		"python" => {
+			let mut rng = PyRandom::new(seed);
+			collect_words(|| rng.getrandbits(32), 4, len)
+		},
+		// 31 bits and 15 bits per call
+		"glibc" => {
+			let mut rng = GlibcRand::new(seed as u32);
+			collect_words(|| rng.rand() as u64, 3, len)
+		},
+		"msvc" => {
+			let mut rng = MsvcRand::new(seed as u32);
+			collect_words(|| rng.rand() as u64, 1, len)
+		},
+		"java" => {
+			let mut rng = JavaRandom::new(seed as i64);
+			collect_words(|| rng.next_int() as u32 as u64, 4, len)
+		},
+		"xorshift128+" => {
+			let mut rng = XorShift128Plus{state0: seed, state1: !seed};
+			collect_words(|| rng.next_u64(), 8, len)
+		},
+		"pcg32" => {
+			let mut rng = Pcg32::new(seed, 54);
+			collect_words(|| rng.next_u32() as u64, 4, len)
+		},
+		// keystreams: encrypt zeros
+		"mt19937-stream" => {
+			let mut res = vec![0; len];
+			ex24::encrypt_mt19937_stream_cipher(&mut res, &(seed as u16 as u32));
+			res
+		},
+		"ctr" => {
+			let mut key = seed.to_le_bytes().to_vec();
+			key.extend_from_slice(&seed.to_be_bytes());
+			ex18::encrypt_ctr(&vec![0; len], &key, &vec![0; 8])
+		},
+		"thread" => {
+			let mut res = vec![0; len];
+			thread_rng().fill_bytes(&mut res);
+			res
+		},
+		// obviously broken, as a sanity check for the tests
+		"counter" => (0..len).map(|i| (i as u64).wrapping_add(seed) as u8).collect(),
+		_ => return Err(format!("unknown generator {}, try one of: {}", name, GENERATORS.join(", "))),
+	})
+}
+
+pub fn run_cli(args: &[String]) -> Result<(), String> {
+	let usage = "usage: randomness file <path> | randomness generator <name> [bytes] [seed]".to_string();
+	let bytes = match args {
+		[command, path] if command == "file" => std::fs::read(path).map_err(|e| format!("could not read {}: {}", path, e))?,
+		[command, name, rest @ ..] if command == "generator" && rest.len() <= 2 => {
+			let len = match rest.first() {
+				Some(len) => len.parse::<usize>().map_err(|_| usage.clone())?,
+				None => 125000,
+			};
+			let seed = match rest.get(1) {
+				Some(seed) => seed.parse::<u64>().map_err(|_| usage.clone())?,
+				None => 5489,
+			};
+			generator_bytes(name, seed, len)?
+		},
+		_ => return Err(usage),
+	};
+	println!("{} bytes", bytes.len());
+	print_report(&run_battery(&bytes)?);
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::randomness::*;
+
+	fn bits_from_str(s: &str) -> Vec<u8> {
+		s.bytes().filter(|b| !b.is_ascii_whitespace()).map(|b| b - b'0').collect()
+	}
+
+	fn assert_p(p: f64, expected: f64) {
+		assert!((p - expected).abs() < 1e-5, "p = {}, expected {}", p, expected);
+	}
+
+	// the worked examples in sp 800-22 rev 1a
+	#[test]
+	fn test_nist_examples() {
+		let pi_bits = bits_from_str("11001001000011111101101010100010001000010110100011\
+			00001000110100110001001100011001100010100010111000");
+		assert_p(monobit(&bits_from_str("1011010101")), 0.527089);
+		assert_p(monobit(&pi_bits), 0.109599);
+		assert_p(block_frequency(&bits_from_str("0110011010"), 3).unwrap(), 0.801252);
+		assert_p(block_frequency(&pi_bits, 10).unwrap(), 0.706438);
+		assert_p(runs(&bits_from_str("1001101011")), 0.147232);
+		assert_p(runs(&pi_bits), 0.500798);
+		let longest = bits_from_str("11001100000101010110110001001100111000000000001001\
+			00110101010001000100111101011010000000110101111100\
+			1100111001101101100010110010");
+		// nist rounds chi-squared before the last step
+		assert!((longest_run(&longest).unwrap() - 0.180609).abs() < 2e-5);
+		let (p1, p2) = serial(&bits_from_str("0011011101"), 3).unwrap();
+		assert_p(p1, 0.808792);
+		assert_p(p2, 0.670320);
+		assert_p(approximate_entropy(&bits_from_str("0100110101"), 3), 0.261961);
+		assert_p(approximate_entropy(&pi_bits, 2), 0.235301);
+		assert_p(cumulative_sums(&bits_from_str("1011010111"), true).unwrap(), 0.4116588);
+		assert_p(cumulative_sums(&pi_bits, true).unwrap(), 0.219194);
+		assert_p(cumulative_sums(&pi_bits, false).unwrap(), 0.114866);
+		assert!(longest_run(&pi_bits).is_err());
+
+		// parameters that don't make sense are errors, not panics or nan
+		assert!(block_frequency(&pi_bits, 0).is_err());
+		assert!(block_frequency(&pi_bits, 101).is_err());
+		assert!(serial(&pi_bits, 0).is_err());
+		assert!(serial(&pi_bits, 25).is_err());
+		assert!(serial(&[], 2).is_err());
+		assert!(cumulative_sums(&[], true).is_err());
+	}
+
+	#[test]
+	fn test_generators() {
+		// fixed seeds, so these don't fail one run in a hundred
+		for name in ["mt19937", "python", "xorshift128+", "pcg32", "mt19937-stream", "ctr"].iter() {
+			let results = run_battery(&generator_bytes(name, 3, 20000).unwrap()).unwrap();
+			assert!(results.iter().all(|r| r.passed()), "{} failed: {:?}", name, results);
+		}
+		let results = run_battery(&generator_bytes("counter", 0, 20000).unwrap()).unwrap();
+		assert!(results.iter().filter(|r| !r.passed()).count() >= 5);
+		assert!(generator_bytes("dice", 0, 10).is_err());
+		assert!(run_battery(b"short").is_err());
+	}
+}
@@ -0,0 +1,360 @@
+/*
+
+Seed lookup tables for MT19937
+
+ex22 and ex24 find a seed by seeding and twisting once per candidate. For 32 bit seeds there are
+only 2^32 first outputs, so we can work them all out once, sort them, and then finding the seeds
+for an output is a binary search: 32 GiB on disk for the whole range, microseconds per lookup.
+
+The table is split into 2^shard_bits files by the top bits of the output, each one sorted so it can
+be memory-mapped and searched in place. Every shard covers the same seed range, and lookups only say
+which seeds in that range give the output (nearly always zero or one, sometimes two).
+
+Shard file layout (integers big-endian):
+
+	header, 28 bytes:
+		magic			4	"RPST"
+		version			1	1
+		generator		1	1 = MT19937 first output, seeded with init_genrand
+		shard bits		1
+		reserved		1	0
+		shard index		4
+		seed start		8	inclusive
+		seed end		8	exclusive
+
+	then entries, sorted by output then seed:
+		output			4
+		seed			4
+
+Building goes through every seed once, appending entries to unsorted shards, then sorts each shard
+on its own, so the most memory it needs is one shard.
+
+*/
+
+use crate::set3::ex21::{MtPrng, MT19937};
+use memmap2::Mmap;
+use std::fs::{self, File};
+use std::io::{BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Instant;
+
+const MAGIC: &[u8; 4] = b"RPST";
+const VERSION: u8 = 1;
+const GENERATOR_MT19937_FIRST_OUTPUT: u8 = 1;
+const HEADER_LEN: usize = 28;
+const ENTRY_LEN: usize = 8;
+const MAX_SHARD_BITS: u8 = 16;
+const BATCH: u64 = 1 << 20;
+
+// MtPrng::new(seed).extract_number() without the rest of the state: the first output only needs
+// words 0, 1 and 397
+pub fn first_output(seed: u32) -> u32 {
+	let mut word = seed;
+	let mut second = 0;
+	for i in 1..=MT19937.m {
+		word = MT19937.f.wrapping_mul(word ^ (word >> 30)).wrapping_add(i as u32);
+		if i == 1 {
+			second = word;
+		}
+	}
+	let x = (seed & 0x80000000) | (second & 0x7fffffff);
+	let x_a = match x & 1 {
+		0 => x >> 1,
+		_ => (x >> 1) ^ MT19937.a,
+	};
+	MT19937.temper(word ^ x_a)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Header {
+	shard_bits: u8,
+	shard: u32,
+	seed_start: u64,
+	seed_end: u64,
+}
+
+impl Header {
+	fn to_bytes(self) -> Vec<u8> {
+		let mut res = MAGIC.to_vec();
+		res.extend_from_slice(&[VERSION, GENERATOR_MT19937_FIRST_OUTPUT, self.shard_bits, 0]);
+		res.extend_from_slice(&self.shard.to_be_bytes());
+		res.extend_from_slice(&self.seed_start.to_be_bytes());
+		res.extend_from_slice(&self.seed_end.to_be_bytes());
+		res
+	}
+
+	fn from_bytes(bytes: &[u8]) -> Result<Header, String> {
+		if bytes.len() < HEADER_LEN || &bytes[..4] != MAGIC {
+			return Err("not a seed table shard".to_string());
+		}
+		if bytes[4] != VERSION || bytes[5] != GENERATOR_MT19937_FIRST_OUTPUT || bytes[7] != 0 {
+			return Err(format!("unsupported seed table version {} / generator {}", bytes[4], bytes[5]));
+		}
+		let mut u32_bytes = [0u8; 4];
+		let (mut start, mut end) = ([0u8; 8], [0u8; 8]);
+		u32_bytes.copy_from_slice(&bytes[8..12]);
+		start.copy_from_slice(&bytes[12..20]);
+		end.copy_from_slice(&bytes[20..28]);
+		Ok(Header{shard_bits: bytes[6], shard: u32::from_be_bytes(u32_bytes), seed_start: u64::from_be_bytes(start), seed_end: u64::from_be_bytes(end)})
+	}
+}
+
+fn shard_path(dir: &Path, shard: u32) -> PathBuf {
+	dir.join(format!("shard-{:05}.bin", shard))
+}
+
+fn shard_of(output: u32, shard_bits: u8) -> u32 {
+	match shard_bits {
+		0 => 0,
+		bits => output >> (32 - bits as u32),
+	}
+}
+
+fn check_range(seed_start: u64, seed_end: u64, shard_bits: u8) -> Result<(), String> {
+	if seed_start >= seed_end || seed_end > 1 << 32 {
+		return Err(format!("bad seed range {}..{}, seeds are 32 bits", seed_start, seed_end));
+	}
+	if shard_bits > MAX_SHARD_BITS {
+		return Err(format!("at most {} shard bits", MAX_SHARD_BITS));
+	}
+	Ok(())
+}
+
+// first outputs for seed_start..seed_end, written out as 2^shard_bits sorted shards in dir
+pub fn build(dir: &Path, seed_start: u64, seed_end: u64, shard_bits: u8) -> Result<(), String> {
+	check_range(seed_start, seed_end, shard_bits)?;
+	fs::create_dir_all(dir).map_err(|e| format!("could not create {}: {}", dir.display(), e))?;
+	let shards = 1u32 << shard_bits;
+	let mut writers = (0..shards).map(|shard| {
+		let path = shard_path(dir, shard);
+		File::create(&path).map(BufWriter::new).map_err(|e| format!("could not create {}: {}", path.display(), e))
+	}).collect::<Result<Vec<BufWriter<File>>, String>>()?;
+
+	// worker threads take batches of seeds, this thread writes them out
+	let threads = thread::available_parallelism().map(|n| n.get()).unwrap_or(1) as u64;
+	let (sender, receiver) = mpsc::sync_channel::<Vec<(u32, u32)>>(2 * threads as usize);
+	let workers: Vec<thread::JoinHandle<()>> = (0..threads).map(|t| {
+		let sender = sender.clone();
+		thread::spawn(move || {
+			let mut batch_start = seed_start + t * BATCH;
+			while batch_start < seed_end {
+				let batch = (batch_start..seed_end.min(batch_start + BATCH)).map(|s| (first_output(s as u32), s as u32)).collect();
+				if sender.send(batch).is_err() {
+					return;
+				}
+				batch_start += threads * BATCH;
+			}
+		})
+	}).collect();
+	drop(sender);
+	for batch in receiver.iter() {
+		for (output, seed) in batch {
+			let writer = &mut writers[shard_of(output, shard_bits) as usize];
+			writer.write_all(&output.to_be_bytes()).and_then(|_| writer.write_all(&seed.to_be_bytes()))
+				.map_err(|e| format!("could not write shard: {}", e))?;
+		}
+	}
+	for worker in workers {
+		worker.join().map_err(|_| "seed table worker panicked".to_string())?;
+	}
+	for writer in writers.iter_mut() {
+		writer.flush().map_err(|e| format!("could not write shard: {}", e))?;
+	}
+	drop(writers);
+
+	// now sort each one and put the header on
+	for shard in 0..shards {
+		let path = shard_path(dir, shard);
+		let mut raw = vec![];
+		File::open(&path).and_then(|mut f| f.read_to_end(&mut raw)).map_err(|e| format!("could not read {}: {}", path.display(), e))?;
+		let mut entries: Vec<u64> = raw.chunks(ENTRY_LEN).map(|e| {
+			let mut bytes = [0u8; 8];
+			bytes.copy_from_slice(e);
+			u64::from_be_bytes(bytes)
+		}).collect();
+		drop(raw);
+		entries.sort_unstable();
+		let mut out = BufWriter::new(File::create(&path).map_err(|e| format!("could not create {}: {}", path.display(), e))?);
+		let header = Header{shard_bits: shard_bits, shard: shard, seed_start: seed_start, seed_end: seed_end};
+		out.write_all(&header.to_bytes()).map_err(|e| format!("could not write {}: {}", path.display(), e))?;
+		for entry in entries.iter() {
+			out.write_all(&entry.to_be_bytes()).map_err(|e| format!("could not write {}: {}", path.display(), e))?;
+		}
+		out.flush().map_err(|e| format!("could not write {}: {}", path.display(), e))?;
+	}
+	Ok(())
+}
+
+pub struct SeedTable {
+	pub shard_bits: u8,
+	pub seed_start: u64,
+	pub seed_end: u64,
+	shards: Vec<Mmap>,
+}
+
+fn entry_at(shard: &[u8], i: usize) -> (u32, u32) {
+	let e = &shard[HEADER_LEN + i * ENTRY_LEN..HEADER_LEN + (i + 1) * ENTRY_LEN];
+	(u32::from_be_bytes([e[0], e[1], e[2], e[3]]), u32::from_be_bytes([e[4], e[5], e[6], e[7]]))
+}
+
+impl SeedTable {
+	pub fn open(dir: &Path) -> Result<SeedTable, String> {
+		let mut header = [0u8; HEADER_LEN];
+		File::open(shard_path(dir, 0)).and_then(|mut f| f.read_exact(&mut header))
+			.map_err(|e| format!("could not open seed table in {}: {}", dir.display(), e))?;
+		let first = Header::from_bytes(&header)?;
+		check_range(first.seed_start, first.seed_end, first.shard_bits)?;
+		let mut shards = vec![];
+		for shard in 0..1u32 << first.shard_bits {
+			let path = shard_path(dir, shard);
+			let file = File::open(&path).map_err(|e| format!("could not open {}: {}", path.display(), e))?;
+			// safe as long as nobody changes the file while it's mapped, which is on whoever runs build
+			let map = unsafe { Mmap::map(&file) }.map_err(|e| format!("could not map {}: {}", path.display(), e))?;
+			let header = Header::from_bytes(&map)?;
+			if header != (Header{shard: shard, ..first}) || (map.len() - HEADER_LEN) % ENTRY_LEN != 0 {
+				return Err(format!("{} doesn't belong with the rest of the table", path.display()));
+			}
+			shards.push(map);
+		}
+		Ok(SeedTable{shard_bits: first.shard_bits, seed_start: first.seed_start, seed_end: first.seed_end, shards: shards})
+	}
+
+	pub fn len(&self) -> usize {
+		self.shards.iter().map(|s| (s.len() - HEADER_LEN) / ENTRY_LEN).sum()
+	}
+
+	// every seed in the table's range whose first output is this
+	pub fn lookup(&self, output: u32) -> Vec<u32> {
+		let shard = &self.shards[shard_of(output, self.shard_bits) as usize];
+		let (mut lo, mut hi) = (0, (shard.len() - HEADER_LEN) / ENTRY_LEN);
+		while lo < hi {
+			let mid = (lo + hi) / 2;
+			match entry_at(shard, mid).0 < output {
+				true => lo = mid + 1,
+				false => hi = mid,
+			}
+		}
+		(lo..(shard.len() - HEADER_LEN) / ENTRY_LEN).map(|i| entry_at(shard, i))
+			.take_while(|&(o, _)| o == output)
+			.map(|(_, seed)| seed)
+			.collect()
+	}
+}
+
+// what ex22 does, for comparison
+pub fn linear_scan(output: u32, seed_start: u64, seed_end: u64) -> Vec<u32> {
+	(seed_start..seed_end).map(|s| s as u32).filter(|&s| MtPrng::new(s).extract_number() == output).collect()
+}
+
+// time both on the first outputs of a few seeds from the table's range
+pub fn benchmark(table: &SeedTable, samples: u64) -> Result<(), String> {
+	let width = table.seed_end - table.seed_start;
+	let seeds: Vec<u32> = (0..samples).map(|i| (table.seed_start + (i * 2654435761) % width) as u32).collect();
+	let outputs: Vec<u32> = seeds.iter().map(|&s| first_output(s)).collect();
+
+	let start = Instant::now();
+	for (seed, output) in seeds.iter().zip(outputs.iter()) {
+		if !table.lookup(*output).contains(seed) {
+			return Err(format!("table doesn't have seed {}", seed));
+		}
+	}
+	let table_time = start.elapsed() / samples as u32;
+
+	// the whole range might take hours, so time part of a scan and scale it up
+	let scanned = width.min(1 << 16);
+	let start = Instant::now();
+	linear_scan(outputs[0], table.seed_start, table.seed_start + scanned);
+	let scan_time = start.elapsed().as_secs_f64() * width as f64 / scanned as f64;
+
+	println!("{} seeds in {} shards", table.len(), table.shards.len());
+	println!("table lookup: {:?} per output", table_time);
+	println!("linear scan over {} seeds: about {:.3}s per output", width, scan_time);
+	Ok(())
+}
+
+pub fn run_cli(args: &[String]) -> Result<(), String> {
+	let usage = "usage: seedtable build <dir> <seed start> <seed end> [shard bits] | seedtable lookup <dir> <output> | seedtable bench <dir>".to_string();
+	let number = |s: &str| s.parse::<u64>().map_err(|_| format!("{} is not a number", s));
+	match args {
+		[command, dir, start, end, rest @ ..] if command == "build" && rest.len() <= 1 => {
+			let shard_bits = match rest.first() {
+				Some(bits) => number(bits)?.min(u8::MAX as u64) as u8,
+				None => 8,
+			};
+			build(Path::new(dir), number(start)?, number(end)?, shard_bits)
+		},
+		[command, dir, output] if command == "lookup" => {
+			let table = SeedTable::open(Path::new(dir))?;
+			let output = number(output)?;
+			if output > u32::MAX as u64 {
+				return Err(format!("{} is more than 32 bits", output));
+			}
+			let start = Instant::now();
+			let seeds = table.lookup(output as u32);
+			println!("seeds {:?} ({:?})", seeds, start.elapsed());
+			Ok(())
+		},
+		[command, dir] if command == "bench" => benchmark(&SeedTable::open(Path::new(dir))?, 10000),
+		_ => Err(usage),
+	}
+}
+
+pub fn demonstrate() {
+	let dir = std::env::temp_dir().join(format!("rustpals-seed-table-{}", std::process::id()));
+	// a day either side of 2023-11-14
+	let res = build(&dir, 1700000000 - 86400, 1700000000 + 86400, 4)
+		.and_then(|_| SeedTable::open(&dir))
+		.and_then(|table| {
+			println!("seed for first output {}: {:?}", first_output(1700012345), table.lookup(first_output(1700012345)));
+			benchmark(&table, 1000)
+		});
+	if let Err(e) = res {
+		println!("seed table failed: {}", e);
+	}
+	let _ = fs::remove_dir_all(&dir);
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::seed_table::*;
+	use crate::set3::ex21::MtPrng;
+	use std::fs;
+
+	fn temp_dir(name: &str) -> std::path::PathBuf {
+		std::env::temp_dir().join(format!("rustpals-test-{}-{}", name, std::process::id()))
+	}
+
+	#[test]
+	fn test_first_output() {
+		for &seed in [0u32, 1, 5489, 1700000000, u32::MAX].iter() {
+			assert_eq!(first_output(seed), MtPrng::new(seed).extract_number());
+		}
+	}
+
+	#[test]
+	fn test_build_and_lookup() {
+		let dir = temp_dir("seed-table");
+		build(&dir, 1000, 51000, 3).unwrap();
+		let table = SeedTable::open(&dir).unwrap();
+		assert_eq!(table.len(), 50000);
+		for seed in (1000..51000).step_by(997) {
+			assert!(table.lookup(first_output(seed)).contains(&seed));
+		}
+		assert_eq!(table.lookup(first_output(12345)), linear_scan(first_output(12345), 1000, 51000));
+		assert!(table.lookup(first_output(999)).is_empty());
+
+		// shards have to agree with each other
+		let mut shard = fs::read(dir.join("shard-00005.bin")).unwrap();
+		shard[15] ^= 1;
+		fs::write(dir.join("shard-00005.bin"), &shard).unwrap();
+		assert!(SeedTable::open(&dir).is_err());
+		fs::remove_dir_all(&dir).unwrap();
+
+		assert!(build(&dir, 10, 10, 3).is_err());
+		assert!(build(&dir, 0, (1 << 32) + 1, 3).is_err());
+		assert!(build(&dir, 0, 10, 17).is_err());
+		assert!(SeedTable::open(&temp_dir("nothing-here")).is_err());
+	}
+}
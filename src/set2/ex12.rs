@@ -38,13 +38,23 @@ This is the first challenge we've given you whose solution will break real crypt
 
 */
 
+use crate::analysis;
+use crate::oracle::Oracle;
 use crate::set1::ex8;
 use crate::set2::{ex9, ex10, ex11};
 use crate::utils;
+use rand::{thread_rng, Rng};
 use std::collections::HashMap;
 
+// the default is the exercise as written
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct EncrypterConfig {
+	pub random_iv: bool,	// cbc under a fresh iv, sent in front of the ciphertext, instead of ecb
+}
+
 pub struct SingleKeyEncrypter {
-	pub key: [u8; 16]
+	pub key: [u8; 16],
+	pub config: EncrypterConfig,
 }
 
 // pub trait EncryptAes128Ecb {
@@ -66,23 +76,37 @@ impl /*EncryptAes128Ecb for*/ SingleKeyEncrypter {
 		res
 	}
 
+	// encrypt_aes128ecb, unless the config says cbc
+	pub fn encrypt(&self, inp: &mut Vec<u8>, suffix: Option<Vec<u8>>) -> Vec<u8> {
+		match self.config.random_iv {
+			true => {
+				if let Some(mut s) = suffix {
+					inp.append(&mut s);
+				}
+				let padded = ex9::pkcs7_padding(inp.as_slice(), 16);
+				let mut iv = [0u8; 16];
+				thread_rng().fill(&mut iv);
+				let mut res = iv.to_vec();
+				res.append(&mut ex10::encrypt_aes128cbc(&padded, &self.key, &iv));
+				res
+			},
+			false => self.encrypt_aes128ecb(inp, suffix),
+		}
+	}
+
 	pub fn new() -> SingleKeyEncrypter {
+		SingleKeyEncrypter::with_config(EncrypterConfig::default())
+	}
+
+	pub fn with_config(config: EncrypterConfig) -> SingleKeyEncrypter {
 		let random_key = ex11::gen_aes128_key();
-		SingleKeyEncrypter{key: random_key}
+		SingleKeyEncrypter{key: random_key, config: config}
 	}
 }
 
 pub fn discover_key_size(ske: &SingleKeyEncrypter) -> usize {
-	let mut inp_string = "A".to_string();
-	let initial_size = ske.encrypt_aes128ecb(&mut inp_string.clone().into_bytes(), Some(suffix())).len();
-	loop {
-		inp_string.push_str("A");
-		let ciphertext = ske.encrypt_aes128ecb(&mut inp_string.clone().into_bytes(), Some(suffix()));
-		let current_size = ciphertext.len();
-		if current_size != initial_size {
-			return current_size - initial_size
-		}
-	}
+	let oracle = |inp: &[u8]| ske.encrypt_aes128ecb(&mut inp.to_vec(), Some(suffix()));
+	analysis::find_block_size(&oracle).expect("could not find block size").0
 }
 
 pub fn make_dictionary(inp: &str, ske: &SingleKeyEncrypter, suffix: &mut Vec<u8>) -> HashMap<u8, Vec<u8>> {
@@ -124,6 +148,55 @@ pub fn decrypt_suffix() -> String {
 	res
 }
 
+// same attack, but against an oracle that appends the secret itself, like the real thing would.
+// instead of slicing the suffix, shift it with a shrinking run of filler bytes. a fixed prefix (ex14) is
+// handled by padding it out to a block boundary first and skipping its blocks.
+pub fn decrypt_suffix_with_oracle<O: Oracle>(oracle: &O) -> Result<Vec<u8>, String> {
+	let profile = analysis::analyse(oracle)?;
+	if profile.mode != analysis::ModeGuess::Ecb {
+		return Err(format!("oracle does not appear to be using ecb: {:?}", profile.mode));
+	}
+	let prefix_len = profile.prefix_len.ok_or("could not determine prefix length")?;
+	let suffix_len = profile.suffix_len.ok_or("could not determine suffix length")?;
+	byte_at_a_time(oracle, profile.block_size, prefix_len, suffix_len)
+}
+
+// the dictionary loop itself. with the suffix lined up so its next unknown byte is the last one of some
+// block, the right guess is the one whose ciphertext matches the target's up to the end of that block.
+// everything before it is the same input either way, so this works for anything deterministic that
+// encrypts a block the same whenever what came before it is the same: ecb, or cbc with a fixed iv
+pub fn byte_at_a_time<O: Oracle>(oracle: &O, block_size: usize, prefix_len: usize, suffix_len: usize) -> Result<Vec<u8>, String> {
+	let prefix_filler = vec![b'B'; (block_size - prefix_len % block_size) % block_size];
+	let skip = prefix_len + prefix_filler.len();
+
+	let mut known: Vec<u8> = vec![];
+	for i in 0..suffix_len {
+		let mut filler = prefix_filler.clone();
+		filler.append(&mut vec![b'A'; block_size - 1 - (i % block_size)]);
+		let end = skip + (i / block_size + 1) * block_size;
+		let target = oracle.query(&filler)?;
+		let target = target.get(..end).ok_or(format!("ciphertext too short for suffix byte {}", i))?;
+
+		let mut guess = filler.clone();
+		guess.append(&mut known.clone());
+		guess.push(0);
+		let last = guess.len() - 1;
+		let mut found = false;
+		for b in 0..=255 {
+			guess[last] = b;
+			if oracle.query(&guess)?.get(..end) == Some(target) {
+				known.push(b);
+				found = true;
+				break;
+			}
+		}
+		if !found {
+			return Err(format!("no dictionary match for suffix byte {}", i));
+		}
+	}
+	Ok(known)
+}
+
 pub fn suffix() -> Vec<u8> {
 	utils::base64_to_bytes(&"Um9sbGluJyBpbiBteSA1LjAKV2l0aCBteSByYWctdG9wIGRvd24gc28gbXkg
 aGFpciBjYW4gYmxvdwpUaGUgZ2lybGllcyBvbiBzdGFuZGJ5IHdhdmluZyBq
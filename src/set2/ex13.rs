@@ -43,12 +43,13 @@ Using only the user input to profile_for() (as an oracle to generate "valid" cip
 
 */
 
+use crate::cookie::{Cookie, DuplicatePolicy, Format, ParseError, Role, UserProfile};
+use crate::cut_and_paste;
+use crate::oracle::Oracle;
 use crate::set2::ex12;
-use crate::utils;
 use aes::Aes128;
 use block_modes::{BlockMode, BlockModeError, Ecb};
 use block_modes::block_padding::Pkcs7;
-use std::collections::HashMap;
 
 type Aes128EcbPkcs7 = Ecb<Aes128, Pkcs7>;
 
@@ -58,110 +59,45 @@ impl ex12::SingleKeyEncrypter {
 		let cipher = Aes128EcbPkcs7::new_varkey(&self.key).expect("problem creating key");
 		cipher.decrypt_pad(inp)
 	}
+}
 
-	pub fn make_forged_block(&self) -> Vec<u8> {
-		// find block size. make "email=" + chars = blocksize, then add "admin00000000..."
-		let block_size = ex12::discover_key_size(&self);
-		let leading_char_num = block_size - ("email=".len() % block_size);
-
-		let mut offset_plus_forged_block: Vec<u8> = vec![];
-		offset_plus_forged_block.append(&mut "0".repeat(leading_char_num).into_bytes());
-		offset_plus_forged_block.append(&mut "admin".as_bytes().to_vec());
-		let pad_num = (block_size - "admin".len()) as u8;
-		let mut padding: Vec<u8> = Vec::new();
-		for _i in 0..pad_num {
-			padding.push(pad_num);
-		};
-		offset_plus_forged_block.append(&mut padding);
-	
-		let mut bytes_with_forgery = profile_for(&mut offset_plus_forged_block);
-		println!("bytes_with_forgery:");
-		utils::print_invalid_string(&bytes_with_forgery);
-		let encrypted_with_forgery = self.encrypt_aes128ecb(&mut bytes_with_forgery, None);
-		// will be email.len() / block size + 1
-		let fb_index = ("email=".len() / block_size) + 1;
-		encrypted_with_forgery[(fb_index * block_size)..((fb_index + 1) * block_size)].to_vec()
-	}
-
-	pub fn align_role_block(&self) -> Vec<u8> {
-		// find amount of padding. start with 1 char, increment till block is added?
-		// not necessary. just make "email=" + chars + "&uid=10&role=" % block size == 0.
-		let block_size = ex12::discover_key_size(&self);
-		let num_chars = block_size - ( ("email=".len() + "&uid=10&role=".len()) % block_size );
-		let mut inp = profile_for(&mut "A".repeat(num_chars).into_bytes());
-		self.encrypt_aes128ecb(&mut inp, None)
-	}
+pub fn kv_parse(inp: &str) -> Result<Cookie, ParseError> {
+	Cookie::parse(inp, Format::Ampersand, DuplicatePolicy::Reject)
 }
 
-pub fn kv_parse(inp: &str) -> HashMap<String, String> {
-	// split string along &
-	// for each substring, split along =
-	// make left side key, right side value. append to result.
-	let mut res = HashMap::new();
-
-	for s in inp.split('&') {
-		let mut pair = s.split('=');
-		match (pair.next(), pair.next()) {
-			(Some(k), Some(v)) => {
-				res.insert(k.to_string(), v.to_string());
-			},
-			_ => println!("invalid input")
-		}
-	}
-	res
+// metacharacters get percent-escaped by the codec, so "foo@bar.com&role=admin" stays inside the email field.
+// the codec works on strings, so an email that isn't utf-8 is refused rather than quietly changed
+pub fn profile_for(inp: &[u8]) -> Result<Vec<u8>, String> {
+	let email = std::str::from_utf8(inp).map_err(|_| "email is not valid utf-8".to_string())?;
+	Ok(UserProfile {
+		email: email.to_string(),
+		uid: 10,
+		role: Role::User,
+	}.encode(Format::Ampersand).into_bytes())
 }
 
-pub fn profile_for(inp: &mut Vec<u8>) -> Vec<u8> {
-	// clean input
-	inp.retain(|c| !(c == &b'&' || c == &b'='));
-	
-	let mut res: Vec<u8> = Vec::new();
-	let x = [
-		("email".to_string().into_bytes(), inp.clone()),
-		("uid".to_string().into_bytes(), "10".to_string().into_bytes()),
-		("role".to_string().into_bytes(), "user".to_string().into_bytes())
-	];
-	// let mut map = HashMap::new();
-	// for (i, j) in x.iter() {
-	// 	map.insert(i, j);
-	// }
-
-	for (i, (key, val)) in x.iter().enumerate() {
-		if i != 0 {
-			res.push(b'&');
-		}
-		let (mut k, mut v) = ((*key).clone(), (*val).clone());
-		res.append(&mut k);
-		res.push(b'=');
-		res.append(&mut v);
+// the attacker's view: an email goes in, an encrypted profile comes out
+struct ProfileOracle<'a>(&'a ex12::SingleKeyEncrypter);
+
+impl<'a> Oracle for ProfileOracle<'a> {
+	fn query(&self, inp: &[u8]) -> Result<Vec<u8>, String> {
+		Ok(self.0.encrypt_aes128ecb(&mut profile_for(inp)?, None))
 	}
-	res
 }
 
-pub fn ecb_cut_and_paste() -> HashMap<String, String> {
+pub fn ecb_cut_and_paste() -> Result<UserProfile, String> {
 	let kc = ex12::SingleKeyEncrypter::new();
-	let block_size = ex12::discover_key_size(&kc);
-	// step one: get forged block
-	let forged_block = kc.make_forged_block();
-	println!("forged_block: {:02x?}", forged_block);
-	// step two: get encrypted bytes with aligned role
-	let mut aligned_role_block = kc.align_role_block();
-	println!("pre-slice : {:02x?}", aligned_role_block);
-	// step three: swap out last block of aligned block with forged block
-		// get last block: len - blocksize .. len
-	let last_block_start = aligned_role_block.len() - block_size;
-	let block_len = aligned_role_block.len();
-	aligned_role_block.splice(last_block_start..block_len, forged_block);
-	println!("post-slice: {:02x?}", aligned_role_block);
-	// step four: decrypt swapped bytes
+	let oracle = ProfileOracle(&kc);
+	let mut forged = cut_and_paste::forge_token(&cut_and_paste::TokenTemplate::ex13_profile(), b"role", b"admin", &oracle)?;
+	println!("forged: {:02x?}", forged);
+
 	let mut decrypted_bytes: Vec<u8> = vec![];
-	match kc.decrypt_aes128ecb(&mut aligned_role_block) {
+	match kc.decrypt_aes128ecb(&mut forged) {
 		Ok(x) => {decrypted_bytes = x.to_vec();},
 		Err(x) => {println!("decrypt error: {:?}", x);}
 	}
-	// step five: parse swapped bytes and return as Hashmap
 	let cookie_string = String::from_utf8_lossy(&decrypted_bytes).clone();
-	kv_parse(&cookie_string)
+	UserProfile::decode(&cookie_string, Format::Ampersand)
 }
 
 /*
@@ -183,3 +119,21 @@ email=0000000000|000&uid=10&role=|userTTTTTTTTTTTT|                | aligned las
 
 
 */
+
+#[cfg(test)]
+mod tests {
+	use crate::cookie;
+	use crate::cut_and_paste::TokenTemplate;
+	use crate::set2::ex13::*;
+
+	#[test]
+	fn test_profile_for_matches_template() {
+		let template = TokenTemplate::ex13_profile();
+		for email in [&b"foo@bar.com"[..], b"foo@bar.com&role=admin", b"100%=;", "caf\u{e9}@b\u{fc}ro.de".as_bytes()].iter() {
+			assert_eq!(profile_for(email).unwrap(), template.encode(email, &[]));
+			assert_eq!((template.value_encoder)(email), cookie::escape(email));
+		}
+		assert!(profile_for(b"foo\xff@bar.com").is_err());
+		assert!(ProfileOracle(&ex12::SingleKeyEncrypter::new()).query(&[0xc3]).is_err());
+	}
+}
@@ -24,6 +24,7 @@ Think "STIMULUS" and "RESPONSE".
 // or make a struct with an enum to match on type. no, because we need prefix AND unknown suffix.
 // need nesting type.
 
+use crate::analysis;
 use crate::set2::ex12;
 use std::collections::HashMap;
 use rand::{thread_rng, Rng};
@@ -35,6 +36,10 @@ pub struct SKEWithPrefix {
 
 impl SKEWithPrefix {
 	pub fn new() -> Self {
+		SKEWithPrefix::with_config(ex12::EncrypterConfig::default())
+	}
+
+	pub fn with_config(config: ex12::EncrypterConfig) -> Self {
 		let mut g = thread_rng();
 		let prefix_len: usize = g.gen_range(0, 40);
 		println!("prefix len: {}", prefix_len);
@@ -44,7 +49,7 @@ impl SKEWithPrefix {
 		println!("prefix: {:02x?}", prefix);
 		SKEWithPrefix{
 			prefix: prefix.to_vec(),
-			ske: ex12::SingleKeyEncrypter::new(),
+			ske: ex12::SingleKeyEncrypter::with_config(config),
 		}
 	}
 
@@ -56,19 +61,16 @@ impl SKEWithPrefix {
 		let encrypted = &self.ske.encrypt_aes128ecb(&mut input_with_prefix, suffix);
 		encrypted.to_vec()
 	}
-}
-
 
-fn find_padding(skewp: &SKEWithPrefix) -> usize {
-	let init_len = skewp.encrypt_aes128ecb(&mut vec![], Some(ex12::suffix())).len();
-	for i in 0..40 {
-		if init_len != skewp.encrypt_aes128ecb(&mut vec!['A' as u8; i], Some(ex12::suffix())).len() {
-			return i;
-		}
+	// same, through ske's encrypt so the config applies
+	pub fn encrypt(&self, inp: &mut Vec<u8>, suffix: Option<Vec<u8>>) -> Vec<u8> {
+		let mut input_with_prefix = self.prefix.clone();
+		input_with_prefix.append(inp);
+		self.ske.encrypt(&mut input_with_prefix, suffix)
 	}
-	0
 }
 
+
 pub fn make_dictionary(inp: &Vec<u8>, skewp: &SKEWithPrefix, suffix: &mut Vec<u8>) -> HashMap<u8, Vec<u8>> {
 	let mut res = HashMap::new();
 	for i in 0..=255 {
@@ -83,13 +85,12 @@ pub fn make_dictionary(inp: &Vec<u8>, skewp: &SKEWithPrefix, suffix: &mut Vec<u8
 
 pub fn decrypt_suffix_with_random_prefix() -> String {
 	let skewp = SKEWithPrefix::new();
-	let block_size = ex12::discover_key_size(&skewp.ske);
-	let encrypted_len = skewp.encrypt_aes128ecb(&mut vec![], Some(ex12::suffix())).len();
-
-	let padding_len = find_padding(&skewp);
-	println!("padding_len: {}", padding_len);
+	let oracle = |inp: &[u8]| skewp.encrypt_aes128ecb(&mut inp.to_vec(), Some(ex12::suffix()));
+	let profile = analysis::analyse(&oracle).expect("could not analyse oracle");
+	analysis::print_profile(&profile);
 
-	let prefix_len = encrypted_len - padding_len - ex12::suffix().len();
+	let block_size = profile.block_size;
+	let prefix_len = profile.prefix_len.expect("could not find prefix length");
 	let byte_trap_filler_len = block_size - 1 - (prefix_len % block_size);
 
 	let mut res = String::new();
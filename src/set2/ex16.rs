@@ -43,27 +43,20 @@ Before you implement this attack, answer this question: why does CBC mode have t
 // they are all scrambled, but the bit that changes in CIPHERTEXT A will be the same bit that changes in the next block of PLAINTEXT B.
 // so: encrypt version without equal sign, look one block back, flip necessary bit, decrypt that, check for change?
 
-use crate::set2::{ex9, ex10, ex15};
+use crate::{bitflip, cookie, mac};
+use crate::cookie::{Cookie, DuplicatePolicy, Format};
+use crate::set2::{ex9, ex10, ex11, ex15};
 use crate::utils;
 use rand::{thread_rng, Rng};
 
 pub fn assemble_and_encrypt(inp: &mut Vec<u8>, key: Vec<u8>, iv: &[u8; 16]) -> Vec<u8> {
-	let mut escaped_input = vec![];
-	inp.into_iter().for_each(|x| {
-		escaped_input.append(&mut match x {
-			b';' => "%3B".as_bytes().to_vec(),
-			b'=' => "%3D".as_bytes().to_vec(),
-			_ => vec![x.clone()],
-		});
-	});
+	let mut escaped_input = cookie::escape(inp);
 	let (mut prefix, mut suffix) = (
 		"comment1=cooking%20MCs;userdata=".as_bytes().to_vec(),
 		";comment2=%20like%20a%20pound%20of%20bacon".as_bytes().to_vec()
 	);
 	prefix.append(&mut escaped_input);
 	prefix.append(&mut suffix);
-	println!("assembled plaintext:");
-	utils::print_invalid_string(&prefix);
 
 	let padded_bytes = ex9::pkcs7_padding(prefix.as_slice(), 16);
 	ex10::encrypt_aes128cbc(&padded_bytes, &key, iv)
@@ -77,36 +70,99 @@ pub fn is_encrypted_admin(ciphertext: &mut Vec<u8>, key: &[u8], iv: &[u8; 16]) -
 	println!("decrypted modified:");
 	utils::print_invalid_string(ciphertext);
 	let unencrypted = ex15::strip_padding(ciphertext)?;
-	let s = String::from_utf8_lossy(&unencrypted);
-	Ok(s.contains(";admin=true;"))
+	cookie_is_admin(&unencrypted)
+}
+
+// the split-on-";" version, with the cookie codec. the plaintext is taken as text, scrambled block and all,
+// and if admin= turns up more than once the last one wins
+pub fn cookie_is_admin(plaintext: &[u8]) -> Result<bool, String> {
+	let cookie = Cookie::parse(&String::from_utf8_lossy(plaintext), Format::Semicolon, DuplicatePolicy::KeepLast)
+		.map_err(|e| e.to_string())?;
+	Ok(cookie.get("admin") == Some("true"))
+}
+
+// the default is the exercise as written
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct CookieConfig {
+	pub random_iv: bool,			// a fresh iv in front of each cookie instead of all zeroes
+	pub encrypt_then_mac: bool,		// hmac over iv and ciphertext on the end, checked before decrypting
+}
+
+// both ends of the exercise, holding the keys
+pub struct CookieService {
+	key: [u8; 16],
+	mac_key: Vec<u8>,
+	config: CookieConfig,
+}
+
+impl CookieService {
+	pub fn new(config: CookieConfig) -> CookieService {
+		CookieService{key: ex11::gen_aes128_key(), mac_key: ex11::gen_aes128_key().to_vec(), config: config}
+	}
+
+	pub fn encrypt(&self, userdata: &[u8]) -> Vec<u8> {
+		let mut iv = [0u8; 16];
+		let mut res = match self.config.random_iv {
+			true => {
+				thread_rng().fill(&mut iv);
+				iv.to_vec()
+			},
+			false => vec![],
+		};
+		res.append(&mut assemble_and_encrypt(&mut userdata.to_vec(), self.key.to_vec(), &iv));
+		if self.config.encrypt_then_mac {
+			let mut tag = mac::hmac_sha1(&self.mac_key, &res);
+			res.append(&mut tag);
+		}
+		res
+	}
+
+	pub fn is_admin(&self, token: &[u8]) -> Result<bool, String> {
+		let body = match self.config.encrypt_then_mac {
+			true => mac::check_tag(&self.mac_key, token)?,
+			false => token.to_vec(),
+		};
+		let mut iv = [0u8; 16];
+		let mut ciphertext = match self.config.random_iv {
+			true if body.len() >= 16 => {
+				iv.copy_from_slice(&body[..16]);
+				body[16..].to_vec()
+			},
+			true => return Err("token too short".to_string()),
+			false => body,
+		};
+		is_encrypted_admin(&mut ciphertext, &self.key, &iv)
+	}
 }
 
 pub fn bitflipping_attack() {
-	let mut key = [0u8; 16];
-	thread_rng().fill(&mut key);
-	println!("key: {:02x?}", key);
-	let iv = &[0; 16];
-	let mut plaintext: Vec<u8> = "AAAA:admin<true".as_bytes().to_vec();
-	let original_ct = assemble_and_encrypt(&mut plaintext, key.to_vec(), iv);
-
-	// print!("unmodified:");
-	// for (i,v) in original_ct.iter().enumerate() {
-	// 	if i % 16 == 0 {
-	// 		println!();
-	// 	}
-	// 	print!("{:02x} ", v);
-	// }
-	// println!();
-
-	// < sign is at index 43. 43 - 16 = 27. flip last bit of byte[26].
-	// : at 37, so [20]
-	let mut modified_ct = original_ct.clone();
-	modified_ct[26] ^= 1;
-	modified_ct[20] ^= 1;
-	match is_encrypted_admin(&mut modified_ct, &key, iv) {
-		Ok(b) => println!("is admin: {}", b),
-		Err(e) => println!("couldn't decrypt: {}", e),
+	let service = CookieService::new(CookieConfig::default());
+
+	// bitflip works out which bytes to submit and which ciphertext bits to flip
+	let oracle = |inp: &[u8]| service.encrypt(inp);
+	let verify = |ct: &[u8]| service.is_admin(ct).unwrap_or(false);
+	match bitflip::inject(&oracle, &verify, b";admin=true", Some(bitflip::FlipMode::Cbc), None) {
+		Ok((plan, modified_ct)) => {
+			println!("submitted: {}, flipped: {:?}", String::from_utf8_lossy(&plan.input), plan.deltas);
+			match service.is_admin(&modified_ct) {
+				Ok(b) => println!("is admin: {}", b),
+				Err(e) => println!("couldn't decrypt: {}", e),
+			};
+		},
+		Err(e) => println!("injection failed: {}", e),
 	};
 }
 
-
+#[cfg(test)]
+mod tests {
+	use crate::set2::ex16::*;
+
+	#[test]
+	fn test_cookie_is_admin() {
+		assert_eq!(cookie_is_admin(b"comment1=cooking%20MCs;userdata=\xff\x00garbage;admin=true;comment2=x"), Ok(true));
+		assert_eq!(cookie_is_admin(b"comment1=cooking%20MCs;userdata=;admin=true;admin=false"), Ok(false));
+		// escaped by assemble_and_encrypt, so just a value
+		assert_eq!(cookie_is_admin(b"comment1=cooking%20MCs;userdata=%3Badmin%3Dtrue%3B;comment2=x"), Ok(false));
+		assert!(cookie_is_admin(b"userdata=;;admin=true").is_err());
+	}
+}
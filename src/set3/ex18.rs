@@ -43,27 +43,12 @@ Most modern cryptography relies on CTR mode to adapt block ciphers into stream c
 
 */
 
-use crate::set2::ex10;
+use crate::keystream::{CtrKeystream, StreamCipher};
 use crate::utils;
-use std::mem::transmute;
 
 pub fn encrypt_ctr(text: &Vec<u8>, key: &Vec<u8>, nonce: &Vec<u8>) -> Vec<u8> {
-	let block_size = 16;
-	let mut keystream = vec![];
-	for counter in 0..utils::ceil(text.len(), block_size) {
-		let bytes: [u8; 8] = unsafe { transmute(counter.to_le()) };
-		// println!("bytes: {:?}", bytes);
-		let mut bv = bytes.to_vec();
-		let mut material = &mut nonce.clone();
-		material.append(&mut bv);
-		ex10::encrypt_aes128ecb(&mut material, key).expect("couldn't encrypt with ecb");
-		keystream.append(material);
-	}
-	// println!("keystream: {:?}", keystream);
 	let mut res = text.clone();
-	for i in 0..res.len() {
-		res[i] = res[i] ^ keystream[i];
-	}
+	StreamCipher::new(CtrKeystream::new(key, nonce)).apply(&mut res);
 	res
 }
 
@@ -72,136 +72,300 @@ const int upper_mask = lowest w bits of (not lower_mask)
 
 */
 
+use rand::{Error, RngCore, SeedableRng};
+use std::fmt::Debug;
+use std::ops::{BitAnd, BitOr, BitXor, Not, Shl, Shr};
 
-pub struct MtPrng {
+// what the twister needs from its word type: u32 for mt19937 and mt11213, u64 for mt19937-64
+pub trait MtWord: Copy + PartialEq + Debug
+	+ BitAnd<Output = Self> + BitOr<Output = Self> + BitXor<Output = Self> + Not<Output = Self>
+	+ Shl<u32, Output = Self> + Shr<u32, Output = Self> {
+	const BITS: u32;
+	fn from_u64(x: u64) -> Self;	// keeps the low bits
+	fn wrapping_add(self, other: Self) -> Self;
+	fn wrapping_sub(self, other: Self) -> Self;
+	fn wrapping_mul(self, other: Self) -> Self;
+}
+
+impl MtWord for u32 {
+	const BITS: u32 = 32;
+	fn from_u64(x: u64) -> u32 { x as u32 }
+	fn wrapping_add(self, other: u32) -> u32 { u32::wrapping_add(self, other) }
+	fn wrapping_sub(self, other: u32) -> u32 { u32::wrapping_sub(self, other) }
+	fn wrapping_mul(self, other: u32) -> u32 { u32::wrapping_mul(self, other) }
+}
+
+impl MtWord for u64 {
+	const BITS: u32 = 64;
+	fn from_u64(x: u64) -> u64 { x }
+	fn wrapping_add(self, other: u64) -> u64 { u64::wrapping_add(self, other) }
+	fn wrapping_sub(self, other: u64) -> u64 { u64::wrapping_sub(self, other) }
+	fn wrapping_mul(self, other: u64) -> u64 { u64::wrapping_mul(self, other) }
+}
+
+// the coefficients from the pseudocode, plus the two multipliers init_by_array uses.
+// w has to be the width of the word type
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MtParams<W: MtWord> {
 	pub w: u32,
-	pub n: u32,
-	pub m: u32,
+	pub n: usize,
+	pub m: usize,
 	pub r: u32,
-	pub a: u32,
+	pub a: W,
 	pub u: u32,
-	pub d: u32,
+	pub d: W,
 	pub s: u32,
-	pub b: u32,
+	pub b: W,
 	pub t: u32,
-	pub c: u32,
+	pub c: W,
 	pub l: u32,
-	pub f: u32,
-	pub index: u32,
-	pub lower_mask: u32,
-	pub upper_mask: u32,
-	pub mt: [u32; 624] // len n
+	pub f: W,
+	pub array_f1: W,
+	pub array_f2: W,
+}
+
+pub const MT19937: MtParams<u32> = MtParams {
+	w: 32, n: 624, m: 397, r: 31,
+	a: 0x9908B0DF,
+	u: 11, d: 0xFFFFFFFF,
+	s: 7, b: 0x9D2C5680,
+	t: 15, c: 0xEFC60000,
+	l: 18,
+	f: 1812433253,
+	array_f1: 1664525,
+	array_f2: 1566083941,
+};
+
+// from mt19937-64.c
+pub const MT19937_64: MtParams<u64> = MtParams {
+	w: 64, n: 312, m: 156, r: 31,
+	a: 0xB5026F5AA96619E9,
+	u: 29, d: 0x5555555555555555,
+	s: 17, b: 0x71D67FFFEDA60000,
+	t: 37, c: 0xFFF7EEE000000000,
+	l: 43,
+	f: 6364136223846793005,
+	array_f1: 3935559000370003845,
+	array_f2: 2862933555777941757,
+};
+
+// the smaller 32 bit twister, period 2^11213 - 1. these are boost's mt11213b
+pub const MT11213: MtParams<u32> = MtParams {
+	w: 32, n: 351, m: 175, r: 19,
+	a: 0xCCAB8EE7,
+	u: 11, d: 0xFFFFFFFF,
+	s: 7, b: 0x31B6AB00,
+	t: 15, c: 0xFFE50000,
+	l: 17,
+	f: 1812433253,
+	array_f1: 1664525,
+	array_f2: 1566083941,
+};
+
+impl<W: MtWord> MtParams<W> {
+	pub fn temper(&self, x: W) -> W {
+		let mut y = x;
+		y = y ^ ((y >> self.u) & self.d);
+		y = y ^ ((y << self.s) & self.b);
+		y = y ^ ((y << self.t) & self.c);
+		y = y ^ (y >> self.l);
+		y
+	}
+}
+
+// only constructible seeded, so extract_number can't hit the "never seeded" case
+pub struct MersenneTwister<W: MtWord> {
+	pub params: MtParams<W>,
+	lower_mask: W,
+	upper_mask: W,
+	index: usize,
+	mt: Vec<W>, // len n
+}
+
+pub type MtPrng = MersenneTwister<u32>;
+pub type MtPrng64 = MersenneTwister<u64>;
+
+impl MersenneTwister<u32> {
+	// mt19937
+	pub fn new(seed: u32) -> MtPrng {
+		MersenneTwister::with_params(MT19937, seed)
+	}
+
+	// init_by_array from the reference mt19937ar.c
+	pub fn from_key(init_key: &[u32]) -> MtPrng {
+		MersenneTwister::with_params_and_key(MT19937, init_key)
+	}
+
+	// a double in [0, 1) from the top 27 and 26 bits of two outputs, also from mt19937ar.c
+	pub fn genrand_res53(&mut self) -> f64 {
+		let a = (self.extract_number() >> 5) as u64;
+		let b = (self.extract_number() >> 6) as u64;
+		((a << 26) + b) as f64 / 9007199254740992.0
+	}
+}
+
+impl MersenneTwister<u64> {
+	// mt19937-64
+	pub fn new(seed: u64) -> MtPrng64 {
+		MersenneTwister::with_params(MT19937_64, seed)
+	}
+
+	// init_by_array64 from the reference mt19937-64.c
+	pub fn from_key(init_key: &[u64]) -> MtPrng64 {
+		MersenneTwister::with_params_and_key(MT19937_64, init_key)
+	}
 }
 
-impl MtPrng {
-	pub fn new() -> MtPrng {
-		let w = 32;
-		let n = 624;
-		let r = 31;
-		let mt = [0; 624];
-		let index = n + 1;
-		let lower_mask = (1 << r) - 1;
-		let upper_mask = !lower_mask & 0xFFFFFFFF;
-
-		MtPrng {
-			w: w,
-			n: n,
-			m: 397,
-			r: r,
-			a: 0x9908B0DF,
-			u: 11,
-			d: 0xFFFFFFFF,
-			s: 7,
-			b: 0x9D2C5680,
-			t: 15,
-			c: 0xEFC60000,
-			l: 18,
-			f: 1812433253,
-			index: index,
+impl<W: MtWord> MersenneTwister<W> {
+	pub fn with_params(params: MtParams<W>, seed: W) -> MersenneTwister<W> {
+		let mut twister = MersenneTwister::unseeded(params);
+		twister.seed_mt(seed);
+		twister
+	}
+
+	pub fn with_params_and_key(params: MtParams<W>, init_key: &[W]) -> MersenneTwister<W> {
+		let mut twister = MersenneTwister::unseeded(params);
+		twister.seed_by_array(init_key);
+		twister
+	}
+
+	// picks up right after the generator that had this state produced its last n outputs
+	pub fn from_state(params: MtParams<W>, state: &[W]) -> Result<MersenneTwister<W>, String> {
+		MersenneTwister::from_state_at(params, state, params.n)
+	}
+
+	// same, but with only index outputs taken from this state so far
+	pub fn from_state_at(params: MtParams<W>, state: &[W], index: usize) -> Result<MersenneTwister<W>, String> {
+		if state.len() != params.n {
+			return Err(format!("state should be {} words, got {}", params.n, state.len()));
+		}
+		if index > params.n {
+			return Err(format!("index {} is past the end of the state", index));
+		}
+		let mut twister = MersenneTwister::unseeded(params);
+		twister.mt = state.to_vec();
+		twister.index = index;
+		Ok(twister)
+	}
+
+	fn unseeded(params: MtParams<W>) -> MersenneTwister<W> {
+		assert_eq!(params.w, W::BITS, "word size doesn't match the parameters");
+		let lower_mask = (W::from_u64(1) << params.r).wrapping_sub(W::from_u64(1));
+		MersenneTwister {
+			params: params,
 			lower_mask: lower_mask,
-			upper_mask: upper_mask,
-			mt: mt,
+			upper_mask: !lower_mask,
+			index: params.n + 1,
+			mt: vec![W::from_u64(0); params.n],
 		}
 	}
 
-	pub fn seed_mt(&mut self, seed: u32) {
-		self.index = self.n;
+	pub fn seed_mt(&mut self, seed: W) {
+		self.index = self.params.n;
 		self.mt[0] = seed;
-		for _i in 1..self.n {
-			let i = _i as usize;
-			self.mt[i] = (self.f).wrapping_mul( 
-				self.mt[i-1] ^ (self.mt[i-1] >> (self.w - 2))
-			).wrapping_add(i as u32);
+		for i in 1..self.params.n {
+			let prev = self.mt[i-1];
+			self.mt[i] = self.params.f.wrapping_mul(
+				prev ^ (prev >> (self.params.w - 2))
+			).wrapping_add(W::from_u64(i as u64));
 		}
 	}
 
-	pub fn extract_number(&mut self) -> Result<u32, String> {
-		if self.index >= self.n {
-			if self.index > self.n {
-				return Err("generator was never seeded".to_string());
-			}
+	pub fn extract_number(&mut self) -> W {
+		if self.index >= self.params.n {
 			self.twist();
 		}
-		let mut y = self.mt[self.index as usize];
-		y ^= y >> self.u;
-		y ^= (y << self.s) & self.b;
-		y ^= (y << self.t) & self.c;
-		y ^= y >> self.l;
-
+		let y = self.params.temper(self.mt[self.index]);
 		self.index += 1;
-		Ok(y)
+		y
 	}
 
 	pub fn twist(&mut self) {
-		for _i in 0..self.n {
-			let i = _i as usize;
-			let x = (self.mt[i] & self.upper_mask) + (self.mt[(i + 1) % self.n as usize] & self.lower_mask);
+		let n = self.params.n;
+		for i in 0..n {
+			let x = (self.mt[i] & self.upper_mask) | (self.mt[(i + 1) % n] & self.lower_mask);
 			let mut x_a = x >> 1;
-			if (x % 2) != 0 {
-				x_a ^= self.a;
+			if x & W::from_u64(1) != W::from_u64(0) {
+				x_a = x_a ^ self.params.a;
 			}
-			self.mt[i] = self.mt[(i + self.m as usize) % self.n as usize] ^ x_a;
+			self.mt[i] = self.mt[(i + self.params.m) % n] ^ x_a;
 		}
 		self.index = 0;
 	}
 
-	// pub fn twist(&mut self) {
-	// 	let mag01 = [0, self.a];
-	// 	for _kk in 0..self.n-self.m {
-	// 		let kk = _kk as usize;
-	// 		let y = (self.mt[kk] & self.upper_mask) | (self.mt[kk+1] & self.lower_mask);
-	// 		self.mt[kk] = self.mt[kk + self.m as usize] ^
-	// 								(y >> 1) ^ 
-	// 								mag01[y as usize & 1];
-	// 	}
-	// 	for _kk in (self.n - self.m) .. (self.n - 1) {
-	// 		let kk = _kk as usize;
-	// 		let y = (self.mt[kk] & self.upper_mask) | (self.mt[kk + 1] & self.lower_mask);
-	// 		let nindex = kk as isize + (397 - 624);
-	// 		self.mt[kk] = self.mt[nindex as usize] ^ (y >> 1) ^ mag01[y as usize & 1];
-	// 	}
-	// 	let y = (self.mt[self.n as usize - 1] & self.upper_mask) | 
-	// 			(self.mt[0] & self.lower_mask);
-	// 	self.mt[self.n as usize - 1] = self.mt[self.m as usize - 1] ^ (y >> 1) ^ mag01[y as usize & 1];
-		
-	// 	self.index = 0
-	// }
-
-	pub fn seed_by_array(&mut self, init_key: &[u32]) {
+	// the inverse of twist, so the generator goes back n outputs. twist only uses the top bit of mt[0],
+	// so the rest of it comes back as 0, and untwisting again spreads that to a few more words
+	pub fn untwist(&mut self) {
+		let (n, m) = (self.params.n, self.params.m);
+		let one = W::from_u64(1);
+		let top_bit = one << (self.params.w - 1);
+		assert!(self.params.a & top_bit == top_bit, "can't tell odd x from even if a's top bit is clear");
+		let mut old = vec![W::from_u64(0); n];
+		for i in (0..n).rev() {
+			// mt[i] = mt[i + m] ^ x_a, where mt[i + m] had already been replaced if it wrapped around
+			let y = match i + m >= n {
+				true => self.mt[i + m - n],
+				false => old[i + m],
+			};
+			let x_a = self.mt[i] ^ y;
+			// x >> 1 always has a clear top bit, so if x_a's is set a was xored in and x was odd
+			let x = match x_a & top_bit == top_bit {
+				true => ((x_a ^ self.params.a) << 1) | one,
+				false => x_a << 1,
+			};
+			old[i] = old[i] | (x & self.upper_mask);
+			if i + 1 < n {
+				old[i + 1] = old[i + 1] | (x & self.lower_mask);
+			}
+		}
+		self.mt = old;
+	}
+
+	// inverts seed_mt: the seed it would have needed to leave the last word of the state as it is.
+	// doesn't check that the rest matches
+	pub fn seed_candidate(&self) -> W {
+		// mt[i] = f * (mt[i-1] ^ (mt[i-1] >> (w - 2))) + i, and f is odd so it has an inverse mod 2^w
+		let f = self.params.f;
+		let mut f_inv = f;
+		for _ in 0..6 {
+			f_inv = f_inv.wrapping_mul(W::from_u64(2).wrapping_sub(f.wrapping_mul(f_inv)));
+		}
+		let shift = self.params.w - 2;
+		let mut word = self.mt[self.params.n - 1];
+		for i in (1..self.params.n).rev() {
+			let y = word.wrapping_sub(W::from_u64(i as u64)).wrapping_mul(f_inv);
+			// the shift is more than half the word, so one xor undoes it
+			word = y ^ (y >> shift);
+		}
+		word
+	}
+
+	pub fn state(&self) -> &[W] {
+		&self.mt
+	}
+
+	// the n outputs the current state gives (or gave) between twists
+	pub fn generation(&self) -> Vec<W> {
+		self.mt.iter().map(|&x| self.params.temper(x)).collect()
+	}
+
+	pub fn seed_by_array(&mut self, init_key: &[W]) {
+		let n = self.params.n;
+		let shift = self.params.w - 2;
 		let key_length = init_key.len();
-		self.seed_mt(19650218);
+		self.seed_mt(W::from_u64(19650218));
 		let (mut i, mut j) = (1, 0);
-		let mut k = match self.n as usize > key_length {
-			true => self.n as usize,
+		let mut k = match n > key_length {
+			true => n,
 			false => key_length,
 		};
 		while k > 0 {
-			self.mt[i] = (self.mt[i] ^ ((self.mt[i-1] ^ (self.mt[i-1] >> 30)).wrapping_mul(1664525)))
-				+ init_key[j] + j as u32;
-			self.mt[i] &= self.d;
+			let prev = self.mt[i-1];
+			self.mt[i] = (self.mt[i] ^ (prev ^ (prev >> shift)).wrapping_mul(self.params.array_f1))
+				.wrapping_add(init_key[j]).wrapping_add(W::from_u64(j as u64));
 			i += 1; j += 1;
-			if i >= self.n as usize {
-				self.mt[0] = self.mt[self.n as usize - 1];
+			if i >= n {
+				self.mt[0] = self.mt[n - 1];
 				i = 1;
 			}
 			if j >= key_length {
@@ -210,34 +374,119 @@ impl MtPrng {
 			k -=1;
 		}
 
-		k = self.n as usize - 1;
+		k = n - 1;
 		while k > 0 {
-			self.mt[i] = (self.mt[i] ^ ((self.mt[i-1] ^ (self.mt[i-1] >> 30)).wrapping_mul(1566083941)))
-				- i as u32;
-			self.mt[i] &= self.d;
+			let prev = self.mt[i-1];
+			self.mt[i] = (self.mt[i] ^ (prev ^ (prev >> shift)).wrapping_mul(self.params.array_f2))
+				.wrapping_sub(W::from_u64(i as u64));
 			i += 1;
-			if i >= self.n as usize {
-				self.mt[0] = self.mt[self.n as usize - 1];
+			if i >= n {
+				self.mt[0] = self.mt[n - 1];
 				i = 1;
 			}
 			k -= 1;
 		}
 
-		self.mt[0] = self.upper_mask;
+		// msb is 1, so the state isn't all zeros
+		self.mt[0] = W::from_u64(1) << (self.params.w - 1);
+		self.index = n;
+	}
+}
+
+impl RngCore for MtPrng {
+	fn next_u32(&mut self) -> u32 {
+		self.extract_number()
+	}
+
+	// low word first, same as rand_core's next_u64_via_u32
+	fn next_u64(&mut self) -> u64 {
+		let low = self.extract_number() as u64;
+		let high = self.extract_number() as u64;
+		(high << 32) | low
+	}
+
+	// little endian words, a partial one at the end
+	fn fill_bytes(&mut self, dest: &mut [u8]) {
+		for chunk in dest.chunks_mut(4) {
+			let word = self.extract_number().to_le_bytes();
+			chunk.copy_from_slice(&word[..chunk.len()]);
+		}
+	}
+
+	fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+		self.fill_bytes(dest);
+		Ok(())
+	}
+}
+
+impl RngCore for MtPrng64 {
+	// the low half, same as rand_core's next_u32_via_u64
+	fn next_u32(&mut self) -> u32 {
+		self.extract_number() as u32
+	}
+
+	fn next_u64(&mut self) -> u64 {
+		self.extract_number()
+	}
+
+	fn fill_bytes(&mut self, dest: &mut [u8]) {
+		for chunk in dest.chunks_mut(8) {
+			let word = self.extract_number().to_le_bytes();
+			chunk.copy_from_slice(&word[..chunk.len()]);
+		}
+	}
+
+	fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+		self.fill_bytes(dest);
+		Ok(())
+	}
+}
+
+// seeds mt19937, use with_params for mt11213
+impl SeedableRng for MtPrng {
+	type Seed = [u8; 4];
+
+	// the seed is a little endian u32 for seed_mt
+	fn from_seed(seed: [u8; 4]) -> MtPrng {
+		MtPrng::new(u32::from_le_bytes(seed))
+	}
+
+	// split into 32 bit words for init_by_array, like python's random.seed(int) does
+	fn seed_from_u64(state: u64) -> MtPrng {
+		match state >> 32 {
+			0 => MtPrng::from_key(&[state as u32]),
+			high => MtPrng::from_key(&[state as u32, high as u32]),
+		}
+	}
+}
+
+impl SeedableRng for MtPrng64 {
+	type Seed = [u8; 8];
+
+	fn from_seed(seed: [u8; 8]) -> MtPrng64 {
+		MtPrng64::new(u64::from_le_bytes(seed))
+	}
+
+	fn seed_from_u64(state: u64) -> MtPrng64 {
+		MtPrng64::new(state)
 	}
 }
 
 
 pub fn test_mersenne_twister_prng() {
-	let mut twister = MtPrng::new();
-	// twister.seed_mt(5489);
-	twister.seed_by_array(&[0x123u32, 0x234, 0x345, 0x456]);
+	// let mut twister = MtPrng::new(5489);
+	let mut twister = MtPrng::from_key(&[0x123u32, 0x234, 0x345, 0x456]);
 	
 	for i in 0..50 {
-		let x = twister.extract_number().unwrap();
+		let x = twister.extract_number();
 		assert!(x == MT_TEST_OUTPUT[i]);
 	}
 
+	// and the 64 bit one, from the start of mt19937-64.out.txt
+	let mut twister = MtPrng64::from_key(&[0x12345, 0x23456, 0x34567, 0x45678]);
+	assert!(twister.extract_number() == 7266447313870364031);
+	assert!(twister.extract_number() == 4946485549665804864);
+
 	println!("output matches test vec");
 }
 
@@ -444,3 +693,98 @@ const MT_TEST_OUTPUT: &[u32] = &[
 	 988064871, 3515461600, 4089077232, 2225147448, 1249609188,
 	2643151863, 3896204135, 2416995901, 1397735321, 3460025646,
 ];
+
+#[cfg(test)]
+mod tests {
+	use crate::set3::ex21::{MersenneTwister, MtPrng, MtPrng64, MT11213, MT_TEST_OUTPUT};
+	use rand::{Rng, RngCore, SeedableRng};
+
+	#[test]
+	fn test_rand_traits() {
+		let mut twister = MtPrng::seed_from_u64(0x123);
+		let mut reference = MtPrng::from_key(&[0x123]);
+		assert_eq!(twister.next_u32(), reference.extract_number());
+
+		let mut twister = MtPrng::from_seed(5489u32.to_le_bytes());
+		let mut reference = MtPrng::new(5489);
+		// first output of the reference implementation's default seed
+		assert_eq!(twister.next_u32(), 3499211612);
+		reference.extract_number();
+		let (low, high) = (reference.extract_number() as u64, reference.extract_number() as u64);
+		assert_eq!(twister.next_u64(), (high << 32) | low);
+		let mut bytes = [0u8; 6];
+		twister.fill_bytes(&mut bytes);
+		assert_eq!(bytes[..4], reference.extract_number().to_le_bytes());
+		assert_eq!(bytes[4..], reference.extract_number().to_le_bytes()[..2]);
+	}
+
+	#[test]
+	fn test_presets() {
+		// the 10000th output from the default seed, as checked by c++'s <random> and boost
+		let mut mt19937 = MtPrng::new(5489);
+		let mut mt19937_64 = MtPrng64::new(5489);
+		let mut mt11213 = MersenneTwister::with_params(MT11213, 5489);
+		for _ in 0..9999 {
+			mt19937.extract_number();
+			mt19937_64.extract_number();
+			mt11213.extract_number();
+		}
+		assert_eq!(mt19937.extract_number(), 4123659995);
+		assert_eq!(mt19937_64.extract_number(), 9981545732273789042);
+		assert_eq!(mt11213.extract_number(), 3809585648);
+
+		// start of mt19937-64.out.txt
+		let mut twister = MtPrng64::from_key(&[0x12345, 0x23456, 0x34567, 0x45678]);
+		assert_eq!(twister.extract_number(), 7266447313870364031);
+		assert_eq!(twister.extract_number(), 4946485549665804864);
+	}
+
+	#[test]
+	fn test_untwist() {
+		let mut target = MersenneTwister::with_params(MT11213, 0xfeedface);
+		target.twist();
+		let before = target.state().to_vec();
+		target.twist();
+		target.untwist();
+		assert_eq!(target.state()[0] >> 31, before[0] >> 31);
+		assert_eq!(target.state()[1..], before[1..]);
+
+		// rewinding leaves the position alone, so the same outputs come round again
+		let mut twister = MtPrng64::new(99);
+		let first: Vec<u64> = (0..400).map(|_| twister.extract_number()).collect();
+		twister.untwist();
+		let again: Vec<u64> = (0..400).map(|_| twister.extract_number()).collect();
+		assert_eq!(first[88..], again[..312]);
+	}
+
+	#[test]
+	fn test_seed_candidate() {
+		for &seed in [0u32, 1, 5489, 0xdeadbeef, 0xffffffff].iter() {
+			assert_eq!(MtPrng::new(seed).seed_candidate(), seed);
+			assert_eq!(MersenneTwister::with_params(MT11213, seed).seed_candidate(), seed);
+		}
+		let seed = 0x0123456789abcdef;
+		assert_eq!(MtPrng64::new(seed).seed_candidate(), seed);
+	}
+
+	#[test]
+	fn test_reseeding() {
+		let mut twister = MtPrng::new(1);
+		twister.seed_by_array(&[0x123u32, 0x234, 0x345, 0x456]);
+		for i in 0..MT_TEST_OUTPUT.len() {
+			assert_eq!(twister.extract_number(), MT_TEST_OUTPUT[i]);
+		}
+	}
+
+	#[test]
+	fn test_drives_rng() {
+		let mut a = MtPrng::new(42);
+		let mut b = MtPrng::new(42);
+		let mut deck_a: Vec<u32> = (0..52).collect();
+		let mut deck_b = deck_a.clone();
+		a.shuffle(&mut deck_a);
+		b.shuffle(&mut deck_b);
+		assert_eq!(deck_a, deck_b);
+		assert_eq!(a.gen_range(0, 1000), b.gen_range(0, 1000));
+	}
+}
@@ -25,25 +25,21 @@ use rand::{Rng, thread_rng};
 use std::time::{Duration, SystemTime};
 
 fn seed_with_timestamp_and_generate() -> u32 {
-	let mut twister = ex21::MtPrng::new();
 	let time = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).expect("could not get time");
 	let seed_time = time + Duration::from_secs(thread_rng().gen_range(40,1000));
 	let sts = seed_time.as_secs() as u32;
 	// println!("{}", sts);
-	twister.seed_mt(sts);
-	twister.extract_number().expect("could not generate random number")
+	ex21::MtPrng::new(sts).extract_number()
 }
 
 pub fn crack_mt19937_seed() {
 	let sample = seed_with_timestamp_and_generate();
 	println!("sample: {}", sample);
 	let mut future_time = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).expect("could not get time").as_secs() as u32 + 4000;
-	let mut twister = ex21::MtPrng::new();
 	let min_time = future_time - 10_000;
 
 	while future_time > min_time {
-		twister.seed_mt(future_time);
-		if twister.extract_number().expect("could not generate random number") == sample {
+		if ex21::MtPrng::new(future_time).extract_number() == sample {
 			println!("seed: {}", future_time);
 			return;
 		}
@@ -20,96 +20,121 @@ How would you modify MT19937 to make this attack hard? What would happen if you
 
 */
 
-use crate::set3::ex21;
-use std::cmp::min;
-
-fn low_n_bits(inp: u32, n: u32) -> u32 {
-	inp ^ ((inp >> n) << n)
-}
-
-fn undo_xor_with_right_shift(given: u32, rsv: u32) -> u32 {
-	assert!(rsv <= 32);
-
-	let mut known_bits = given >> (32 - rsv);	// must be left-shifted when appended to
-	let mut num_known_bits = rsv;
-	let mut i = 0;
-	while num_known_bits < 32 {
-		let num_remainder_bits = 32 - num_known_bits;
-		let remainder = low_n_bits(given, num_remainder_bits);
-		// grab up to rsv bits to work on
-		let num_working_bits = min(rsv, num_remainder_bits);
-		let next_chunk = remainder >> (32 - num_known_bits - num_working_bits);
-		let relevant_bits = low_n_bits(known_bits, num_known_bits - (rsv * i)) >> (rsv - num_working_bits);
-		let extension = relevant_bits ^ next_chunk;
-		known_bits = (known_bits << num_working_bits) + extension;
-		num_known_bits += num_working_bits;
-		i += 1;
+use crate::set3::ex21::{self, MersenneTwister, MtParams, MtWord};
+use rand::{Rng, thread_rng};
+
+// y = x ^ ((x >> rsv) & magic_number). the top rsv bits of y are x's, and each pass
+// gets another rsv bits right below them
+fn undo_xor_with_right_shift<W: MtWord>(given: W, rsv: u32, magic_number: W) -> W {
+	assert!(rsv > 0 && rsv <= W::BITS);
+	let mut known_bits = given;
+	for _ in 0..W::BITS / rsv {
+		known_bits = given ^ ((known_bits >> rsv) & magic_number);
 	}
 	known_bits
 }
 
-fn undo_xor_with_left_shift_and(given: u32, lsv: u32, magic_number: u32) -> u32 {
-	assert!(lsv <= 32);
-	let mut known_bits = low_n_bits(given, lsv);
-	let mut num_known_bits = lsv;
-	let mut i = 0;
-	while num_known_bits < 32 {
-		let offset = lsv * (i + 1);
-		let num_working_bits = min(lsv, 32 - num_known_bits);
-		let lsb = low_n_bits(known_bits >> (i * lsv), num_working_bits) << offset;
-		let magic_bits = low_n_bits(magic_number >> offset, num_working_bits) << offset;
-		let mask = lsb & magic_bits;
-		let relevant_bits_of_given = low_n_bits(given >> offset, num_working_bits) << offset;
-		// println!("num_known_bits: {}, i: {}, offset: {}, num_working_bits: {}",
-		// 	num_known_bits, i, offset, num_working_bits
-		// );
-		// println!("known_bits:             {:032b}", known_bits);
-		// println!("lsb:                    {:032b}", lsb);
-		// println!("magic_bits:             {:032b}", magic_bits);
-		// println!("mask:                   {:032b}", mask);
-		// println!("relevant_bits_of_given: {:032b}", relevant_bits_of_given);
-		// println!("given:                  {:032b}", given);
-		// println!("===================================\n");
-		known_bits += relevant_bits_of_given ^ mask;
-		num_known_bits += num_working_bits;
-		i += 1;
+// same thing from the bottom up
+fn undo_xor_with_left_shift_and<W: MtWord>(given: W, lsv: u32, magic_number: W) -> W {
+	assert!(lsv > 0 && lsv <= W::BITS);
+	let mut known_bits = given;
+	for _ in 0..W::BITS / lsv {
+		known_bits = given ^ ((known_bits << lsv) & magic_number);
 	}
 	known_bits
 }
 
-fn untemper(_y: u32) -> u32 {
-	// (u, d) = (11, 0xFFFFFFFF)
-	// (s, b) = (7, 0x9D2C5680)
-	// (t, c) = (15, 0xEFC60000)
-	// l = 18
-
+pub fn untemper<W: MtWord>(params: &MtParams<W>, _y: W) -> W {
 	// y := y xor ((y >> u) and d)
 	// y := y xor ((y << s) and b)
 	// y := y xor ((y << t) and c)
 	// y := y xor (y >> l)
 
 	let mut y = _y;
-	y = undo_xor_with_right_shift(y, 18);
-	y = undo_xor_with_left_shift_and(y, 15, 0xEFC60000);
-	y = undo_xor_with_left_shift_and(y, 7, 0x9D2C5680);
-	y = undo_xor_with_right_shift(y, 11);
+	y = undo_xor_with_right_shift(y, params.l, !W::from_u64(0));
+	y = undo_xor_with_left_shift_and(y, params.t, params.c);
+	y = undo_xor_with_left_shift_and(y, params.s, params.b);
+	y = undo_xor_with_right_shift(y, params.u, params.d);
 	y
 }
 
+// outputs has to start right after a twist (at the start of the stream, say), and the
+// clone carries on from after the first n of them
+pub fn clone_mt<W: MtWord>(params: MtParams<W>, outputs: &[W]) -> Result<MersenneTwister<W>, String> {
+	if outputs.len() < params.n {
+		return Err(format!("need {} outputs to clone, got {}", params.n, outputs.len()));
+	}
+	let internal_state: Vec<W> = outputs[..params.n].iter().map(|&y| untemper(&params, y)).collect();
+	MersenneTwister::from_state(params, &internal_state)
+}
+
+fn clone_and_compare<W: MtWord>(name: &str, target: &mut MersenneTwister<W>) {
+	let outputs: Vec<W> = (0..target.params.n).map(|_| target.extract_number()).collect();
+	let mut clone = clone_mt(target.params, &outputs).expect("could not clone");
+	for _ in 0..1000 {
+		assert_eq!(clone.extract_number(), target.extract_number());
+	}
+	println!("{}: clone predicts the next 1000 outputs", name);
+}
 
 pub fn clone_mt19947_prng() {
-	let mut target = ex21::MtPrng::new();
-	target.seed_mt(5);
+	clone_and_compare("mt19937", &mut ex21::MtPrng::new(5));
+	clone_and_compare("mt19937-64", &mut ex21::MtPrng64::new(5));
+	clone_and_compare("mt11213", &mut MersenneTwister::with_params(ex21::MT11213, 5));
+}
 
-	let mut internal_state = [0; 624];
-	for i in 0..internal_state.len() {
-		internal_state[i] = untemper(target.extract_number().expect("could not extract number"));
+// the outputs from the generations before the clone's state, oldest first. the first output of
+// each one is a guess, and untwisting further back gets a few more wrong each time
+pub fn past_outputs<W: MtWord>(clone: &MersenneTwister<W>, generations: usize) -> Vec<W> {
+	let mut rewound = MersenneTwister::from_state(clone.params, clone.state()).expect("clone has the wrong size state");
+	let mut res = vec![];
+	for _ in 0..generations {
+		rewound.untwist();
+		let mut generation = rewound.generation();
+		generation.append(&mut res);
+		res = generation;
 	}
-	let mut clone = ex21::MtPrng::new();
-	clone.mt = internal_state;
-	clone.index = 624;	// what it would be after being seeded
-	for _ in 0..1000 {
-		assert_eq!(clone.extract_number(), target.extract_number());
+	res
+}
+
+// the seed, and how many twists ago seed_mt was called, looking at most max_generations back
+pub fn recover_seed<W: MtWord>(clone: &MersenneTwister<W>, max_generations: usize) -> Result<(W, usize), String> {
+	let mut rewound = MersenneTwister::from_state(clone.params, clone.state())?;
+	for generations in 1..=max_generations {
+		rewound.untwist();
+		let seed = rewound.seed_candidate();
+		// untwisting loses a few bits, so check by going forwards instead
+		let mut check = MersenneTwister::with_params(clone.params, seed);
+		for _ in 0..generations {
+			check.twist();
+		}
+		if check.state() == clone.state() {
+			return Ok((seed, generations));
+		}
+	}
+	Err(format!("state didn't come from seed_mt in the last {} twists", max_generations))
+}
+
+// tokens were handed out before we started watching
+pub fn rewind_mt19937_prng() {
+	let seed: u32 = thread_rng().gen();
+	let mut server = ex21::MtPrng::new(seed);
+	let issued: Vec<u32> = (0..3 * 624).map(|_| server.extract_number()).collect();
+	let seen: Vec<u32> = (0..624).map(|_| server.extract_number()).collect();
+	let clone = clone_mt(ex21::MT19937, &seen).expect("could not clone");
+
+	let past = past_outputs(&clone, 3);
+	let right = past.iter().zip(issued.iter()).filter(|(a, b)| a == b).count();
+	println!("untwisted: {} of {} earlier tokens right", right, issued.len());
+
+	match recover_seed(&clone, 10) {
+		Ok((recovered, generations)) => {
+			let mut replay = ex21::MtPrng::new(recovered);
+			let replayed: Vec<u32> = (0..issued.len()).map(|_| replay.extract_number()).collect();
+			println!("seed {:08x} from {} twists back, right: {}, every earlier token right: {}",
+				recovered, generations, recovered == seed, replayed == issued);
+		},
+		Err(e) => println!("could not recover seed: {}", e),
 	}
 }
 
@@ -121,22 +146,22 @@ mod tests {
 
 	#[test]
 	fn test_undo_xor_with_right_shift() {
-		let mut mt = ex21::MtPrng::new();
-		mt.seed_mt(thread_rng().gen::<u32>());
-		let test_vec: Vec<u32> = (0..1000).map(|_| mt.extract_number().expect("could not extract number")).collect();
+		let mut mt = ex21::MtPrng::new(thread_rng().gen::<u32>());
+		let test_vec: Vec<u32> = (0..1000).map(|_| mt.extract_number()).collect();
 		for val in test_vec {
 			for rsv in 1..32 {
 				let modified = val ^ (val >> rsv);
-				assert_eq!(val, super::undo_xor_with_right_shift(modified, rsv));
+				assert_eq!(val, super::undo_xor_with_right_shift(modified, rsv, 0xFFFFFFFF));
+				let masked = val ^ ((val >> rsv) & 0x5555AAAA);
+				assert_eq!(val, super::undo_xor_with_right_shift(masked, rsv, 0x5555AAAA));
 			}
 		}
 	}
 
 	#[test]
 	fn test_undo_xor_with_left_shift_and() {
-		let mut mt = ex21::MtPrng::new();
-		mt.seed_mt(thread_rng().gen::<u32>());
-		let test_vec: Vec<u32> = (0..1000).map(|_| mt.extract_number().expect("could not extract number")).collect();
+		let mut mt = ex21::MtPrng::new(thread_rng().gen::<u32>());
+		let test_vec: Vec<u32> = (0..1000).map(|_| mt.extract_number()).collect();
 		for val in test_vec {
 			let m1 = val ^ ((val << 7) & 0x9D2C5680);
 			let m2 = val ^ ((val << 15) & 0xEFC60000);
@@ -144,6 +169,48 @@ mod tests {
 			assert_eq!(val, super::undo_xor_with_left_shift_and(m2, 15, 0xEFC60000));
 		}
 	}
+
+	#[test]
+	fn test_rewind() {
+		let seed = thread_rng().gen::<u32>();
+		let mut target = ex21::MersenneTwister::with_params(ex21::MT11213, seed);
+		let issued: Vec<u32> = (0..3 * 351).map(|_| target.extract_number()).collect();
+		let seen: Vec<u32> = (0..351).map(|_| target.extract_number()).collect();
+		let clone = super::clone_mt(ex21::MT11213, &seen).unwrap();
+
+		let past = super::past_outputs(&clone, 3);
+		assert_eq!(past.len(), issued.len());
+		// one untwist back only loses the first output
+		assert_eq!(past[2 * 351 + 1..], issued[2 * 351 + 1..]);
+		assert_eq!(super::recover_seed(&clone, 10).unwrap(), (seed, 4));
+
+		let mut seeded_by_array = ex21::MtPrng::from_key(&[seed]);
+		let seen: Vec<u32> = (0..624).map(|_| seeded_by_array.extract_number()).collect();
+		assert!(super::recover_seed(&super::clone_mt(ex21::MT19937, &seen).unwrap(), 10).is_err());
+	}
+
+	#[test]
+	fn test_clone_every_variant() {
+		let mut target = ex21::MtPrng64::new(thread_rng().gen::<u64>());
+		let outputs: Vec<u64> = (0..ex21::MT19937_64.n).map(|_| target.extract_number()).collect();
+		for &y in outputs.iter().take(20) {
+			assert_eq!(ex21::MT19937_64.temper(super::untemper(&ex21::MT19937_64, y)), y);
+		}
+		let mut clone = super::clone_mt(ex21::MT19937_64, &outputs).unwrap();
+		for _ in 0..1000 {
+			assert_eq!(clone.extract_number(), target.extract_number());
+		}
+
+		for params in [ex21::MT19937, ex21::MT11213].iter() {
+			let mut target = ex21::MersenneTwister::with_params(*params, thread_rng().gen::<u32>());
+			let outputs: Vec<u32> = (0..params.n).map(|_| target.extract_number()).collect();
+			let mut clone = super::clone_mt(*params, &outputs).unwrap();
+			for _ in 0..1000 {
+				assert_eq!(clone.extract_number(), target.extract_number());
+			}
+			assert!(super::clone_mt(*params, &outputs[1..]).is_err());
+		}
+	}
 }
 
 // fn bit_length(inp: &u32) -> u32 {
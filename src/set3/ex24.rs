@@ -16,32 +16,18 @@ Write a function to check if any given password token is actually the product of
 
 */
 
-use crate::set3::ex21;
+use crate::keystream::{self, StreamCipher};
 use rand::{Rng, thread_rng};
 use rand::distributions::Alphanumeric;
 use std::time::SystemTime;
 
 
-fn encrypt_mt19937_stream_cipher(inp: &mut Vec<u8>, seed: &u32) {
-	let mut twister = ex21::MtPrng::new();
-	twister.seed_mt(*seed);
-	let mut next_four = [0u8; 4];
-	for i in 0..inp.len() {
-		if i % 4 == 0 {
-			unsafe { 
-				next_four = std::mem::transmute(
-					twister.extract_number()
-						.expect("could not extract number")
-						.to_be()
-				);
-			}
-		}
-		inp[i] ^= next_four[i % 4];
-	}
+pub fn encrypt_mt19937_stream_cipher(inp: &mut Vec<u8>, seed: &u32) {
+	StreamCipher::new(keystream::mt19937_keystream(*seed)).apply(inp);
 }
 
-fn gen_password_reset_token(inp: &mut Vec<u8>) -> Vec<u8> {
-	let mut rng = thread_rng();
+// works with any rng, so an MtPrng can stand in for thread_rng
+fn gen_password_reset_token<R: Rng>(inp: &mut Vec<u8>, rng: &mut R) -> Vec<u8> {
 	let (prefix_length, suffix_length): (usize, usize) = (rng.gen_range(0, 16), rng.gen_range(0, 16));
 	let mut plaintext: Vec<u8> = rng.sample_iter(&Alphanumeric).take(prefix_length).map(|x| x as u8).collect();
 	plaintext.append(inp);
@@ -63,18 +49,8 @@ pub fn crack_mt19937_stream_cipher_with_16_bit_seed() {
 	plaintext.append(&mut "AAAAAAAAAAAAAA".as_bytes().to_vec());
 	plaintext.append(&mut rng.sample_iter(&Alphanumeric).take(suffix_length).map(|x| x as u8).collect());
 	encrypt_mt19937_stream_cipher(&mut plaintext, &(seed as u32));
-	let mut test_rng = ex21::MtPrng::new();
-	let mut result: u16 = 0;
-	for i in 0u16 ..= 0xffff {
-		// if i % 10000 == 0 { println!("on {}", i); }
-		test_rng.seed_mt(i as u32);
-		let mut clone = plaintext.clone();
-		encrypt_mt19937_stream_cipher(&mut clone, &(i as u32));
-		if String::from_utf8_lossy(&clone).contains("AAAAAAAAAAAAAA") {
-			result = i;
-			break;
-		}
-	}
+	let result = keystream::find_seed(|s| keystream::mt19937_keystream(s as u32), 0..0x10000, &plaintext, b"AAAAAAAAAAAAAA")
+		.expect("could not find seed") as u16;
 	assert!(result == seed);
 	encrypt_mt19937_stream_cipher(&mut plaintext, &(result as u32));
 	println!("seed found: {}\n{}", result, String::from_utf8_lossy(&plaintext));
@@ -84,20 +60,13 @@ fn is_token_from_mt19937_with_timestamp_seed(encrypted_token: Vec<u8>, known_tex
 	let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH)
 		.expect("could not get time").as_secs() as u32;
 	// look back 5 minutes for clock drift?
-	for i in 0..300 {
-		let mut clone = encrypted_token.clone();
-		encrypt_mt19937_stream_cipher(&mut clone, &(now - i));
-		if String::from_utf8_lossy(&clone).contains(known_text) {
-			return true
-		}
-	}
-	false
+	keystream::find_seed(|s| keystream::mt19937_keystream(s as u32), now as u64 - 299..now as u64 + 1, &encrypted_token, known_text.as_bytes()).is_ok()
 }
 
 pub fn test_is_from_mt19937_with_timestamp_seed() {
 	let known_text = "AAAAAAAAAAAAAA";
 
-	let mut good_token = gen_password_reset_token(&mut known_text.as_bytes().to_vec());
+	let mut good_token = gen_password_reset_token(&mut known_text.as_bytes().to_vec(), &mut thread_rng());
 	let mut bad_token = good_token.clone();
 	
 	encrypt_with_timestamp(&mut good_token);
@@ -132,4 +101,14 @@ mod tests {
 		println!("{}", String::from_utf8_lossy(&sample_data));
 		assert_eq!(sample_data, clone_data);
 	}
+
+	#[test]
+	fn test_token_from_mt19937() {
+		use crate::set3::ex21;
+		let known_text = b"AAAAAAAAAAAAAA";
+		let token = super::gen_password_reset_token(&mut known_text.to_vec(), &mut ex21::MtPrng::new(1234));
+		let again = super::gen_password_reset_token(&mut known_text.to_vec(), &mut ex21::MtPrng::new(1234));
+		assert_eq!(token, again);
+		assert!(String::from_utf8_lossy(&token).contains("AAAAAAAAAAAAAA"));
+	}
 }
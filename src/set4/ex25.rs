@@ -16,11 +16,9 @@ A folkloric supposed benefit of CTR mode is the ability to easily "seek forward"
 */
 
 use crate::{ex7, ex10, ex11, ex18};
+use crate::oracle::{InstrumentedOracle, Oracle};
 use crate::utils;
 use rand::{thread_rng, Rng};
-use std::io;
-use std::io::Write;
-use std::mem::transmute;
 
 // attacker controls offset and new text. ciphertext is xor of plaintext and keystream. keystream is aes of key and nonce + counter. we know counter.
 // we don't know nonce but we do because we who are writing the edit function have the key, we're just exposing the function. so we have key.
@@ -29,26 +27,77 @@ use std::mem::transmute;
 
 // edit entire first block - 1 with 0, note ciphertext. then edit entire first block - 1 with 0, loop through other values of byte, and compare to ciphertext.
 
-struct CtrEncrypter {
+// the default is the exercise as written
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct EditConfig {
+	pub per_message_nonce: bool,	// every edit is re-encrypted under a new nonce, sent in front of the ciphertext
+}
+
+pub struct CtrEncrypter {
 	key: Vec<u8>,
 	nonce: Vec<u8>,
+	config: EditConfig,
 }
 
 impl CtrEncrypter {
-	fn new(key: &Vec<u8>, nonce: &Vec<u8>) -> CtrEncrypter {
-		CtrEncrypter{key: key.clone(), nonce: nonce.clone()}
+	pub fn new(key: &Vec<u8>, nonce: &Vec<u8>) -> CtrEncrypter {
+		CtrEncrypter::with_config(key, nonce, EditConfig::default())
+	}
+
+	// with per_message_nonce, nonce isn't used
+	pub fn with_config(key: &Vec<u8>, nonce: &Vec<u8>, config: EditConfig) -> CtrEncrypter {
+		CtrEncrypter{key: key.clone(), nonce: nonce.clone(), config: config}
+	}
+
+	pub fn encrypt(&self, plaintext: &Vec<u8>) -> Vec<u8> {
+		match self.config.per_message_nonce {
+			true => {
+				let mut nonce = [0u8; 8];
+				thread_rng().fill(&mut nonce);
+				let mut res = nonce.to_vec();
+				res.append(&mut ex18::encrypt_ctr(plaintext, &self.key, &nonce.to_vec()));
+				res
+			},
+			false => ex18::encrypt_ctr(plaintext, &self.key, &self.nonce),
+		}
+	}
+
+	// writing past the end makes the ciphertext longer, but there's no skipping ahead of it
+	pub fn edit(&self, ciphertext: &Vec<u8>, offset: usize, newtext: &Vec<u8>) -> Result<Vec<u8>, String> {
+		match self.config.per_message_nonce {
+			true => self.edit_and_reseal(ciphertext, offset, newtext),
+			false => self.edit_in_place(ciphertext, offset, newtext),
+		}
+	}
+
+	fn edit_and_reseal(&self, ciphertext: &Vec<u8>, offset: usize, newtext: &Vec<u8>) -> Result<Vec<u8>, String> {
+		if ciphertext.len() < 8 {
+			return Err("ciphertext is missing its nonce".to_string());
+		}
+		let (nonce, body) = ciphertext.split_at(8);
+		let mut plaintext = ex18::encrypt_ctr(&body.to_vec(), &self.key, &nonce.to_vec());
+		if offset > plaintext.len() {
+			return Err(format!("offset {} is past the end of the ciphertext", offset));
+		}
+		if plaintext.len() < offset + newtext.len() {
+			plaintext.resize(offset + newtext.len(), 0);
+		}
+		plaintext[offset..offset + newtext.len()].copy_from_slice(newtext);
+		Ok(self.encrypt(&plaintext))
 	}
-	fn edit(&self, ciphertext: &Vec<u8>, offset: usize, newtext: &Vec<u8>) -> Vec<u8> {
-		assert!(offset + newtext.len() < ciphertext.len());
+
+	fn edit_in_place(&self, ciphertext: &Vec<u8>, offset: usize, newtext: &Vec<u8>) -> Result<Vec<u8>, String> {
+		if offset > ciphertext.len() {
+			return Err(format!("offset {} is past the end of the ciphertext", offset));
+		}
 		let block_size = 16;
 		let low_block = offset / block_size;
 		let high_block = (offset + newtext.len()) / block_size;
 		let num_blocks = 1 + high_block - low_block;
-		// need keystream for those blocks, so need nonce and to transmute block nums, then aes with key
+		// need keystream for those blocks, so need nonce and the block nums as little endian bytes, then aes with key
 		let mut keystream: Vec<u8> = vec![];
 		for block_num in low_block..=high_block {
-			let bytes: [u8; 8] = unsafe { transmute(block_num.to_le()) };
-			let mut bv = bytes.to_vec();
+			let mut bv = (block_num as u64).to_le_bytes().to_vec();
 			let mut material = &mut self.nonce.clone();
 			material.append(&mut bv);
 			ex10::encrypt_aes128ecb(&mut material, &self.key).expect("couldn't encrypt with ecb");
@@ -61,8 +110,11 @@ impl CtrEncrypter {
 		for i in 0..text_to_edit.len() {
 			text_to_edit[i] = text_to_edit[i] ^ keystream[i];
 		}
-		// text_to_edit is now in plaintext form, time to edit
+		// text_to_edit is now in plaintext form, time to edit. the keystream covers whatever newtext adds on the end
 		let local_offset = offset % block_size;
+		if text_to_edit.len() < local_offset + newtext.len() {
+			text_to_edit.resize(local_offset + newtext.len(), 0);
+		}
 		for (i, b) in newtext.iter().enumerate() {
 			text_to_edit[local_offset + i] = *b;
 		}
@@ -75,28 +127,41 @@ impl CtrEncrypter {
 		new_ciphertext.append(&mut text_to_edit);
 		let mut original_end = ciphertext[utils::min(ciphertext.len(), block_size*(high_block+1))..].to_vec();
 		new_ciphertext.append(&mut original_end);
-		new_ciphertext
+		Ok(new_ciphertext)
 	}
 }
 
-pub fn break_random_access_read_write() {
-	let block_size = 16;
+// the edit "api call" as bytes on the wire: 8-byte little-endian offset, then the new text
+fn edit_request(offset: usize, newtext: &[u8]) -> Vec<u8> {
+	let mut request = (offset as u64).to_le_bytes().to_vec();
+	request.extend_from_slice(newtext);
+	request
+}
 
-	let plaintext_b64 = String::from_utf8(utils::read_file("./src/resources/25.txt")).expect("could not convert to string");
-	let mut plaintext = utils::base64_to_bytes(&plaintext_b64);
+pub fn parse_edit_request(request: &[u8]) -> Result<(usize, Vec<u8>), String> {
+	if request.len() < 8 {
+		return Err("edit request too short".to_string());
+	}
+	let mut offset_bytes = [0u8; 8];
+	offset_bytes.copy_from_slice(&request[..8]);
+	Ok((u64::from_le_bytes(offset_bytes) as usize, request[8..].to_vec()))
+}
 
-	// misread the prompt. thought "the recovered plaintext from this file (the ECB exercise)" meant recovered by base64-decoding,
-	// but in ex7, the ecb exercise, this was still encrypted after decoding. so we need to ecb decrypt it.
-	ex7::decrypt_aes128ecb(&mut plaintext, "YELLOW SUBMARINE".as_bytes()).expect("could not decrypt");
-	
-	let key = ex11::gen_aes128_key().to_vec();
-	let mut _nonce = [0u8; 8];
-	thread_rng().fill(&mut _nonce);
-	let nonce = _nonce.to_vec();
+// the edit api in front of the server's copy of the ciphertext
+pub struct EditOracle<'a> {
+	pub encrypter: &'a CtrEncrypter,
+	pub encrypted: &'a Vec<u8>,
+}
 
-	let encrypter = CtrEncrypter::new(&key, &nonce);
-	let encrypted = ex18::encrypt_ctr(&plaintext, &key, &nonce);
+impl<'a> Oracle for EditOracle<'a> {
+	fn query(&self, request: &[u8]) -> Result<Vec<u8>, String> {
+		let (offset, newtext) = parse_edit_request(request)?;
+		self.encrypter.edit(self.encrypted, offset, &newtext)
+	}
+}
 
+pub fn recover_plaintext_with_edit_oracle<O: Oracle>(encrypted: &Vec<u8>, oracle: &O) -> Result<Vec<u8>, String> {
+	let block_size = 16;
 	let mut known_bytes = Vec::new();
 	let mut bytes_left = encrypted.len();
 	// for each block
@@ -108,24 +173,51 @@ pub fn break_random_access_read_write() {
 
 		// for each byte of block
 		for test_byte_index in 0..(upper_bound - lower_bound) {
-			let reference_ct = encrypter.edit(&mut encrypted.clone(), lower_bound + test_byte_index + 1, &vec![b'0'; block_size-1-test_byte_index]);
+			let reference_ct = oracle.query(&edit_request(lower_bound + test_byte_index + 1, &vec![b'0'; block_size-1-test_byte_index]))?;
 
 			// for all possible values
+			let mut found = false;
 			for b in 0..=255 {
 				let mut test_data = vec![b];
 				test_data.append(&mut vec![b'0'; block_size-1-test_byte_index]);
-				let test_ct = encrypter.edit(&mut encrypted.clone(), lower_bound + test_byte_index, &test_data);
+				let test_ct = oracle.query(&edit_request(lower_bound + test_byte_index, &test_data))?;
 				if test_ct == reference_ct {
-					print!("{}", b as char);
-					io::stdout().flush().expect("could not flush stdout");
 					known_bytes.push(b);
+					found = true;
 					break;
 				}
 			}
+			if !found {
+				return Err(format!("no edit reproduced the reference ciphertext for byte {}", lower_bound + test_byte_index));
+			}
 		}
 		bytes_left -= upper_bound - lower_bound
 	}
-	// println!("{}", String::from_utf8_lossy(&known_bytes));
+	Ok(known_bytes)
+}
+
+pub fn break_random_access_read_write() {
+	let plaintext_b64 = String::from_utf8(utils::read_file("./src/resources/25.txt")).expect("could not convert to string");
+	let mut plaintext = utils::base64_to_bytes(&plaintext_b64);
+
+	// misread the prompt. thought "the recovered plaintext from this file (the ECB exercise)" meant recovered by base64-decoding,
+	// but in ex7, the ecb exercise, this was still encrypted after decoding. so we need to ecb decrypt it.
+	ex7::decrypt_aes128ecb(&mut plaintext, "YELLOW SUBMARINE".as_bytes()).expect("could not decrypt");
+	
+	let key = ex11::gen_aes128_key().to_vec();
+	let mut _nonce = [0u8; 8];
+	thread_rng().fill(&mut _nonce);
+	let nonce = _nonce.to_vec();
+
+	let encrypter = CtrEncrypter::new(&key, &nonce);
+	let encrypted = encrypter.encrypt(&plaintext);
+
+	// attacker only gets to call edit on the server's copy of the ciphertext
+	let oracle = InstrumentedOracle::new(EditOracle{encrypter: &encrypter, encrypted: &encrypted}, None);
+	let known_bytes = recover_plaintext_with_edit_oracle(&encrypted, &oracle).expect("could not recover plaintext");
+	println!("{}", String::from_utf8_lossy(&known_bytes));
+	println!("recovered matches: {}", known_bytes == plaintext);
+	oracle.stats().print();
 }
 
 mod tests {
@@ -143,10 +235,24 @@ mod tests {
 		let encrypter = super::CtrEncrypter::new(&key, &nonce);
 
 		let encrypted = ex18::encrypt_ctr(&plaintext, &key, &nonce);
-		let edited = encrypter.edit(&encrypted, 4, &"what's up".bytes().collect());
+		let edited = encrypter.edit(&encrypted, 4, &"what's up".bytes().collect()).unwrap();
 
 		let unencrypted = ex18::encrypt_ctr(&edited, &key, &nonce);
 		let res: Vec<u8> = "HEY what's upS HERE'S THE TEXT WE'RE GONNA ENCRYPT".bytes().collect();
 		assert_eq!(unencrypted, res);
+
+		// off the end, and starting right at it
+		let edited = encrypter.edit(&encrypted, 43, &"DECRYPT LATER".bytes().collect()).unwrap();
+		assert_eq!(ex18::encrypt_ctr(&edited, &key, &nonce), "HEY THERE THIS HERE'S THE TEXT WE'RE GONNA DECRYPT LATER".as_bytes().to_vec());
+		let edited = encrypter.edit(&encrypted, plaintext.len(), &"!".bytes().collect()).unwrap();
+		assert_eq!(ex18::encrypt_ctr(&edited, &key, &nonce), "HEY THERE THIS HERE'S THE TEXT WE'RE GONNA ENCRYPT!".as_bytes().to_vec());
+		assert!(encrypter.edit(&encrypted, plaintext.len() + 1, &"!".bytes().collect()).is_err());
+	}
+
+	#[test]
+	fn test_parse_edit_request() {
+		assert_eq!(super::parse_edit_request(&super::edit_request(300, b"abc")), Ok((300, b"abc".to_vec())));
+		assert_eq!(super::parse_edit_request(&[0; 8]), Ok((0, vec![])));
+		assert!(super::parse_edit_request(&[1, 2, 3]).is_err());
 	}
 }
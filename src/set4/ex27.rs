@@ -26,54 +26,111 @@ P'_1 XOR P'_3
 
 */
 
-use crate::{ex10, ex16};
+use crate::{key_iv, mac};
+use crate::oracle::Oracle;
+use crate::{ex10, ex11, ex16, utils};
 use rand::{Rng, thread_rng};
 
-fn assemble_and_encrypt(inp: &mut Vec<u8>, key: &[u8; 16]) -> Vec<u8> {
-	ex16::assemble_and_encrypt(inp, key.to_vec(), key)
-}
-
-fn decrypt(mut inp: &mut Vec<u8>, key: &[u8]) -> Result<(), String> {
-	match ex10::decrypt_aes128cbc(&mut inp, key, key) {
+fn decrypt(mut inp: &mut Vec<u8>, key: &[u8], iv: &[u8]) -> Result<(), String> {
+	match ex10::decrypt_aes128cbc(&mut inp, key, iv) {
 		Ok(()) => (),
 		Err(_) => return Err("block mode error".to_string()),
 	}
 	for c in inp.iter() {
 		match c.is_ascii() {
 			true => (),
-			false => return Err("non ascii char".to_string()),
+			false => return Err(format!("non ascii char: {}", utils::bytes_to_hex_string(inp))),
 		}
 	}
 	Ok(())
 }
 
-pub fn crack_cbc_key_equals_iv() {
-	let key_size = 16;
-	let mut key = [0u8; 16];
-	thread_rng().fill(&mut key);
-	// println!("key: {:02x?}", key);
+// the default is the exercise as written
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ReceiverConfig {
+	pub random_iv: bool,			// a fresh iv in front of each message instead of the key
+	pub generic_errors: bool,		// "invalid message" whatever went wrong, and no plaintext
+	pub encrypt_then_mac: bool,		// hmac over iv and ciphertext on the end, checked before decrypting
+}
 
-	let mut plaintext: Vec<u8> = "looooooooooooongboi, many blocks".as_bytes().to_vec();
-	let original_ct = assemble_and_encrypt(&mut plaintext, &key);
+// the receiving end, as far as the attacker can see it
+pub struct Receiver {
+	key: [u8; 16],
+	mac_key: Vec<u8>,
+	config: ReceiverConfig,
+}
 
+impl Receiver {
+	pub fn new(config: ReceiverConfig) -> Receiver {
+		Receiver{key: ex11::gen_aes128_key(), mac_key: ex11::gen_aes128_key().to_vec(), config: config}
+	}
 
-	let mut modified_ct = original_ct[..key_size].to_vec();
-	modified_ct.append(&mut [0;16].to_vec());
-	let mut repeat = original_ct[..key_size].to_vec();
-	modified_ct.append(&mut repeat);
+	pub fn key(&self) -> &[u8] {
+		&self.key
+	}
 
-	match decrypt(&mut modified_ct, &key) {
-		Ok(()) => (),
-		Err(e) => {
-			println!("decryption error: {}", e);
-			println!("plaintext: {:02x?}", modified_ct)
+	// what the sender puts on the wire for some userdata
+	pub fn seal(&self, userdata: &[u8]) -> Vec<u8> {
+		let mut res = match self.config.random_iv {
+			true => {
+				let mut iv = [0u8; 16];
+				thread_rng().fill(&mut iv);
+				let mut res = iv.to_vec();
+				res.append(&mut ex16::assemble_and_encrypt(&mut userdata.to_vec(), self.key.to_vec(), &iv));
+				res
+			},
+			false => ex16::assemble_and_encrypt(&mut userdata.to_vec(), self.key.to_vec(), &self.key),
+		};
+		if self.config.encrypt_then_mac {
+			let mut tag = mac::hmac_sha1(&self.mac_key, &res);
+			res.append(&mut tag);
+		}
+		res
+	}
+
+	fn error(&self, specific: String) -> String {
+		match self.config.generic_errors {
+			true => "invalid message".to_string(),
+			false => specific,
 		}
 	}
+}
+
+impl Oracle for Receiver {
+	fn query(&self, message: &[u8]) -> Result<Vec<u8>, String> {
+		let body = match self.config.encrypt_then_mac {
+			true => mac::check_tag(&self.mac_key, message).map_err(|e| self.error(e))?,
+			false => message.to_vec(),
+		};
+		let (iv, mut ciphertext) = match self.config.random_iv {
+			true if body.len() >= 16 => (body[..16].to_vec(), body[16..].to_vec()),
+			true => return Err(self.error("message too short".to_string())),
+			false => (self.key.to_vec(), body),
+		};
+		decrypt(&mut ciphertext, &self.key, &iv).map(|_| vec![]).map_err(|e| self.error(e))
+	}
+}
+
+pub fn extract_leak(e: &str) -> Option<Vec<u8>> {
+	let prefix = "non ascii char: ";
+	match e.starts_with(prefix) {
+		true => Some(utils::hex_string_to_bytes(&e[prefix.len()..])),
+		false => None,
+	}
+}
 
-	let returned_plaintext = modified_ct;
-	let p1 = returned_plaintext[..key_size].to_vec();
-	let p3 = returned_plaintext[key_size*2..key_size*3].to_vec();
-	let recovered_key: Vec<u8> = p1.iter().enumerate().map(|(i, c)| c ^ p3[i]).collect();
-	// println!("recovered key: {:02x?}", recovered_key);
-	println!("original key matches recovered: {}", key[..] == recovered_key[..]);
+pub fn crack_cbc_key_equals_iv() {
+	let receiver = Receiver::new(ReceiverConfig::default());
+	// println!("key: {:02x?}", receiver.key());
+
+	let captured = receiver.seal(b"looooooooooooongboi, many blocks");
+
+	match key_iv::recover_key(&receiver, &extract_leak, &key_iv::decrypt_aes128cbc_key_as_iv, &captured, 16) {
+		Ok(recovered) => {
+			// println!("recovered key: {:02x?}", recovered.key);
+			println!("captured message: {}", String::from_utf8_lossy(&recovered.plaintext));
+			println!("original key matches recovered: {}", recovered.key[..] == receiver.key()[..]);
+		},
+		Err(e) => println!("key recovery failed: {}", e),
+	}
 }
@@ -14,50 +14,64 @@ Verify that you cannot tamper with the message without breaking the MAC you've p
 
 */
 
+use crate::hashes::sha1::Sha1;
+use crate::mac::{self, Hmac, SecretPrefixMac};
+use crate::set4::ex31;
 use rand::{Rng, thread_rng};
-use sha1::{Sha1, Digest};
+use std::thread;
+use std::time::Duration;
 
+// the default is the exercise as written: a prefix mac, compared with early exit
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct MacConfig {
+	pub hmac: bool,						// hmac-sha1 instead of sha1(key || message)
+	pub constant_time_compare: bool,	// instead of ex31's insecure_compare
+	pub compare_delay: Duration,		// slept for every byte the comparison looks at, so it can be timed
+}
+
+// just the key: every message gets a fresh hasher, so one tag doesn't depend on the ones before it
 pub struct Sha1KeyedMac {
-	pub sha1: sha1::Sha1,
 	key: Vec<u8>,
+	config: MacConfig,
 }
 
 impl Sha1KeyedMac {
 	pub fn new(key: &Vec<u8>) -> Sha1KeyedMac {
-		let hasher = Sha1::new();
+		Sha1KeyedMac::with_config(key, MacConfig::default())
+	}
+
+	pub fn with_config(key: &Vec<u8>, config: MacConfig) -> Sha1KeyedMac {
 		Sha1KeyedMac {
-			sha1: hasher,
 			key: key.clone(),
+			config: config,
 		}
 	}
 
-	// pub fn custom(registers: [u32; 5], key: &Vec<u8>) -> Sha1KeyedMac {
-	// 	let mut hasher = Sha1::new();
-	// 	hasher.h = registers;
-	// 	Sha1KeyedMac {
-	// 		sha1: hasher,
-	// 		key: key.clone(),
-	// 	}		
-	// }
-
-	pub fn gen(&mut self, message: &Vec<u8>) -> Vec<u8> {
-		let mut inp = self.key.clone();
-		inp.append(&mut message.clone());
-		// println!("generating: {:02x?}", inp);
-		self.sha1.input(inp);
-		self.sha1.clone().result().to_vec()
+	pub fn gen(&self, message: &Vec<u8>) -> Vec<u8> {
+		match self.config.hmac {
+			true => mac::mac::<Hmac<Sha1>>(&self.key, message),
+			false => mac::mac::<SecretPrefixMac<Sha1>>(&self.key, message),
+		}
+	}
+
+	pub fn authenticate(&self, mac: &Vec<u8>, message: &Vec<u8>) -> bool {
+		self.authenticate_with_sleep(mac, message, thread::sleep)
 	}
 
-	pub fn authenticate(&mut self, mac: &Vec<u8>, message: &Vec<u8>) -> bool {
-		let x = self.gen(message);
-		// println!("hashed: {:02x?}\nmac:    {:02x?}", x, mac);
-		x == *mac
+	// sleep is handed compare_delay for every byte compared. thread::sleep, or a simulated clock
+	pub fn authenticate_with_sleep<S: Fn(Duration)>(&self, mac: &Vec<u8>, message: &Vec<u8>, sleep: S) -> bool {
+		let expected = self.gen(message);
+		let delay = self.config.compare_delay;
+		match self.config.constant_time_compare {
+			true => mac::constant_time_eq_with(&expected, mac, || sleep(delay)),
+			false => ex31::insecure_compare_with(&expected, mac, || sleep(delay)),
+		}
 	}
 }
 
 pub fn sha1_keyed_mac() {
 	let key = (0..16).map(|_| thread_rng().gen::<u8>()).collect();
-	let mut s1km = Sha1KeyedMac::new(&key);
+	let s1km = Sha1KeyedMac::new(&key);
 	let message = &b"very important data indeed".to_vec();
 	let mac = s1km.gen(message);
 	println!("{:02x?}", mac);
@@ -66,7 +80,7 @@ pub fn sha1_keyed_mac() {
 	// Verify that you cannot tamper with the message without breaking the MAC you've produced
 	assert!(!s1km.authenticate(&mac, &b"not so important data".to_vec()));
 	// and that you can't produce a new MAC without knowing the secret key.
-	let mut different_s1km = Sha1KeyedMac::new(&(0..16).map(|_| thread_rng().gen::<u8>()).collect());
+	let different_s1km = Sha1KeyedMac::new(&(0..16).map(|_| thread_rng().gen::<u8>()).collect());
 	assert!(mac != different_s1km.gen(message));
 	println!("authenticate function works");
 }
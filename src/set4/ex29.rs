@@ -38,8 +38,8 @@ For instance: Thai Duong and Juliano Rizzo, who got to this attack before we did
 // use crate::utils;
 use crate::ex28;
 use rand::{Rng, thread_rng};
-// use sha1::digest::Input;
-use sha1::Digest;
+use crate::hashes::sha1::Sha1;
+use crate::length_extension;
 
 fn pad_message(message: &Vec<u8>, excess: usize) -> Vec<u8> {
     // message needs to be multiple of 512 bits/64 bytes
@@ -66,6 +66,18 @@ fn pad_message(message: &Vec<u8>, excess: usize) -> Vec<u8> {
     padded
 }
 
+// takes the mac of orig_message and returns a message ending in new_message along with its mac, assuming the key is key_len bytes
+pub fn extend_sha1_mac(orig_message: &Vec<u8>, orig_mac: &[u8], new_message: &[u8], key_len: usize) -> (Vec<u8>, Vec<u8>) {
+    // resume sha1 from the mac's registers, as if it had just hashed (secret_key + message + padding)
+    let forgery = length_extension::forge::<Sha1>(orig_message, orig_mac, new_message, key_len)
+        .expect("mac isn't a sha-1 digest");
+    // the glue in the middle should be exactly our padding
+    let mut expected = pad_message(orig_message, key_len);
+    expected.extend_from_slice(new_message);
+    assert_eq!(forgery.message, expected, "padding doesn't match sha-1's");
+    (forgery.message, forgery.mac)
+}
+
 pub fn break_sha1_keyed_mac() {
     let orig_message = "comment1=cooking%20MCs;userdata=foo;comment2=%20like%20a%20pound%20of%20bacon"
         .as_bytes().to_vec();
@@ -75,49 +87,40 @@ pub fn break_sha1_keyed_mac() {
     // so, forgery = fakekeyofunknownlength + original message which we know + padding bytes + new message
     // then the SHA1 lib will add the real final padding for us, and we adjust the fake key's length until one passes the auth function.
 
-    // using key length 16 arbitrarily, should randomize this. anything shorter than 128 - orig_message.len() should be fine as it's written now.
+    // using key length 16 arbitrarily, should randomize this.
     let key = (0..16).map(|_| thread_rng().gen::<u8>()).collect();
 
     // we (will pretend we) don't know the secret prefix length, so iterate over a range
     for secret_prefix_len in 0..20 {
 
         // get first hash of secret prefix and message with normally seeded SHA1
-        let mut s1km = ex28::Sha1KeyedMac::new(&key);
+        let s1km = ex28::Sha1KeyedMac::new(&key);
         let orig_hash = s1km.gen(&orig_message);
 
-        // take those registers, turn them into 32-bit [a, b, c, d, e] values
-        let mut registers = [0u32; 5];
-        for i in 0..5 {
-            for j in 0..4 {
-                registers[i] <<= 8;
-                registers[i] |= orig_hash[(4*i) + j] as u32
-            }
-        }
-
-        // custom_hasher starts out normal, and needs to be fed the amount of data as (secret_key + message + padding)
-        // original message was 77 bytes, so more than 64 bytes/512 bits, so as long as the secret prefix isn't too long we'll be working with 3 blocks of 512 bits ultimately
-        let mut custom_hasher = ex28::Sha1KeyedMac::new(&key);
-        custom_hasher.sha1.input(vec![0; 128]); // two blocks worth of data, just zeroes, doesn't matter
-
-        // now we set the registers to what they were after hashing the real secret prefix + message
-        custom_hasher.sha1.h = registers;
-        
-        // now hash the new data. because we fed this SHA1 instance the 128 bytes of zeroes, the message length at the end of the padding will be correct
-        let mut new_message = ";admin=true".as_bytes().to_vec();
-        custom_hasher.sha1.input(&new_message);
-        let new_hash = custom_hasher.sha1.result().to_vec();
-
-        // construct "orig_message + padding + new_message"
-        let mut forgery = pad_message(&orig_message, secret_prefix_len);
-        forgery.append(&mut new_message);
-        let mut clean_hasher = ex28::Sha1KeyedMac::new(&key);
-
-        // because we stole the state of the SHA1 machine after it hashed the first two 512-bit blocks with the secret prefix,
-        // and padded our forgery such that the boundary between the (secret prefix + orig_message + padding) and new_message is congruent to 512 bits,
+        // because we stole the state of the SHA1 machine after it hashed the secret prefix + orig_message + padding,
+        // and padded our forgery such that the boundary between that and new_message is congruent to 512 bits,
         // the "real", "server" hasher will result in our new_hash when it processes the forgery (if we've guessed the prefix length correctly).
+        let (forgery, new_hash) = extend_sha1_mac(&orig_message, &orig_hash, b";admin=true", secret_prefix_len);
+        let clean_hasher = ex28::Sha1KeyedMac::new(&key);
         match clean_hasher.authenticate(&new_hash, &forgery) {
             true => println!("len {}: forged!", secret_prefix_len),
             false => println!("len {}: failed", secret_prefix_len),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::hashes::{MdHasher, sha1::Sha1};
+
+    // "verify that you're generating the same padding that your SHA-1 implementation is using"
+    #[test]
+    fn test_pad_message() {
+        for (len, excess) in [(0, 0), (3, 16), (55, 0), (56, 0), (50, 14), (100, 7)].iter() {
+            let message = vec![b'a'; *len];
+            let mut expected = message.clone();
+            expected.append(&mut MdHasher::<Sha1>::padding((len + excess) as u64));
+            assert_eq!(super::pad_message(&message, *excess), expected);
+        }
+    }
+}
@@ -7,33 +7,37 @@ You're thinking, why did we bother with this?
 Blame Stripe. In their second CTF game, the second-to-last challenge involved breaking an H(k, m) MAC with SHA1. Which meant that SHA1 code was floating all over the Internet. MD4 code, not so much.
 */
 
-use md4::{Md4, Digest};
+use crate::hashes;
+use crate::length_extension;
+use crate::mac::{self, SecretPrefixMac};
 use rand::{Rng, thread_rng};
 
 pub struct Md4KeyedMac {
-    pub md4: md4::Md4,
     key: Vec<u8>,
 }
 
 impl Md4KeyedMac {
     pub fn new(key: &Vec<u8>) -> Md4KeyedMac {
-        let hasher = Md4::new();
         Md4KeyedMac {
-            md4: hasher,
             key: key.clone(),
         }
     }
 
-    pub fn gen(&mut self, message: &Vec<u8>) -> Vec<u8> {
-        let mut inp = self.key.clone();
-        inp.append(&mut message.clone());
-        self.md4.update(&inp);
-        self.md4.clone().finalize().to_vec()
+    pub fn gen(&self, message: &Vec<u8>) -> Vec<u8> {
+        mac::mac::<SecretPrefixMac<hashes::md4::Md4>>(&self.key, message)
     }
 }
 
-pub fn md4_keyed_mac() {
-	let key = (0..16).map(|_| thread_rng().gen::<u8>()).collect();
-	let mut _s1km = Md4KeyedMac::new(&key);
-
-}
\ No newline at end of file
+// same as ex29, with md4 instead
+pub fn break_md4_keyed_mac() {
+    let orig_message = b"comment1=cooking%20MCs;userdata=foo;comment2=%20like%20a%20pound%20of%20bacon".to_vec();
+    let key: Vec<u8> = (0..thread_rng().gen_range(1, 32)).map(|_| thread_rng().gen::<u8>()).collect();
+    let orig_mac = Md4KeyedMac::new(&key).gen(&orig_message);
+
+    let server = Md4KeyedMac::new(&key);
+    let verify = |message: &[u8], mac: &[u8]| server.gen(&message.to_vec()) == mac;
+    match length_extension::find_forgery::<hashes::md4::Md4, _>(verify, &orig_message, &orig_mac, b";admin=true", 0..64) {
+        Ok(forgery) => println!("forged with key length {}: {}", forgery.key_len, String::from_utf8_lossy(&forgery.message)),
+        Err(e) => println!("{}", e),
+    }
+}
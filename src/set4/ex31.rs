@@ -0,0 +1,306 @@
+/*
+
+Implement and break HMAC-SHA1 with an artificial timing leak
+
+The pseudocode on Wikipedia should be enough. HMAC is very easy.
+
+Using the web framework of your choosing (Sinatra, web.py, whatever), write a tiny application that has a URL that takes a "file" argument and a "signature" argument, like so:
+
+http://localhost:9000/test?file=foo&signature=46b4ec586117154dacd49d664e5d63fdc88efb51
+
+Have the server generate an HMAC key, and then verify that the "signature" on incoming requests is valid for "file", using the "==" operator to compare the valid MAC for a file with the "signature" parameter (in other words, verify the HMAC the way any normal programmer would verify it).
+
+Write a function, call it "insecure_compare", that implements the == operation by doing byte-at-a-time comparisons with early exit (ie, return false at the first non-matching byte).
+
+In the loop for "insecure_compare", add a 50ms sleep (sleep 50ms after each byte).
+
+Use your "insecure_compare" function to verify the HMACs on incoming requests, and test that the whole contraption works. Return a 500 if the MAC is invalid, and a 200 if it's OK.
+
+Using the timing leak in this application, write a program that discovers the valid MAC for any file.
+
+Why artificial delays?
+Early-exit string compares are probably the most common source of cryptographic timing leaks, but they aren't especially easy to exploit. In fact, many timing leaks (for instance, any in C, C++, Ruby, or Python) probably aren't exploitable over a wide-area network at all. To play with attacking real-world timing leaks, you have to start writing low-level timing code. We're keeping things cryptographic in these challenges.
+
+*/
+
+// no web framework here, just enough http over std::net to answer GET /test?file=...&signature=...
+// with a 200, 500, 400 or 404. every connection gets its own thread and is closed after one response
+
+use crate::hashes::sha1::Sha1;
+use crate::mac::{self, Hmac};
+use crate::utils;
+use rand::{Rng, thread_rng};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub const SIGNATURE_LEN: usize = 20;
+
+// == the way anyone would write it, plus a sleep for every byte that matched
+pub fn insecure_compare(a: &[u8], b: &[u8], delay: Duration) -> bool {
+	insecure_compare_with(a, b, || thread::sleep(delay))
+}
+
+// on_byte in place of the sleep
+pub fn insecure_compare_with<F: FnMut()>(a: &[u8], b: &[u8], mut on_byte: F) -> bool {
+	for (x, y) in a.iter().zip(b.iter()) {
+		if x != y {
+			return false;
+		}
+		on_byte();
+	}
+	a.len() == b.len()
+}
+
+// runs until dropped
+pub struct TimingServer {
+	pub addr: SocketAddr,
+	stopped: Arc<AtomicBool>,
+}
+
+impl TimingServer {
+	// on a free port on localhost
+	pub fn start(key: &[u8], delay: Duration) -> Result<TimingServer, String> {
+		TimingServer::bind("127.0.0.1:0", key, delay)
+	}
+
+	pub fn bind(addr: &str, key: &[u8], delay: Duration) -> Result<TimingServer, String> {
+		let listener = TcpListener::bind(addr).map_err(|e| format!("couldn't listen on {}: {}", addr, e))?;
+		let local = listener.local_addr().map_err(|e| e.to_string())?;
+		let stopped = Arc::new(AtomicBool::new(false));
+		let flag = stopped.clone();
+		let key = key.to_vec();
+		thread::spawn(move || {
+			for stream in listener.incoming() {
+				if flag.load(Ordering::SeqCst) {
+					break;
+				}
+				if let Ok(stream) = stream {
+					let key = key.clone();
+					thread::spawn(move || handle(stream, &key, delay));
+				}
+			}
+		});
+		Ok(TimingServer{addr: local, stopped: stopped})
+	}
+}
+
+impl Drop for TimingServer {
+	fn drop(&mut self) {
+		self.stopped.store(true, Ordering::SeqCst);
+		// accept blocks, so knock once to wake it up
+		let _ = TcpStream::connect(self.addr);
+	}
+}
+
+fn handle(stream: TcpStream, key: &[u8], delay: Duration) {
+	let _ = stream.set_nodelay(true);
+	let mut reader = BufReader::new(&stream);
+	let mut request_line = String::new();
+	if reader.read_line(&mut request_line).is_err() {
+		return;
+	}
+	// the headers don't matter, but they have to be read before answering
+	let mut header = String::new();
+	loop {
+		header.clear();
+		match reader.read_line(&mut header) {
+			Ok(n) if n > 0 && header.trim_end() != "" => (),
+			_ => break,
+		}
+	}
+	let status = respond(&request_line, key, delay);
+	let response = format!("HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status);
+	let _ = (&stream).write_all(response.as_bytes());
+}
+
+// the status for a request line
+fn respond(request_line: &str, key: &[u8], delay: Duration) -> &'static str {
+	let target = match request_line.split_whitespace().collect::<Vec<&str>>()[..] {
+		["GET", target, _] => target,
+		_ => return "400 Bad Request",
+	};
+	let (path, query) = match target.find('?') {
+		Some(i) => (&target[..i], &target[i + 1..]),
+		None => (target, ""),
+	};
+	if path != "/test" {
+		return "404 Not Found";
+	}
+	let file = query_param(query, "file");
+	let signature = query_param(query, "signature").and_then(|s| decode_hex(&s));
+	match (file, signature) {
+		(Some(file), Some(signature)) => match insecure_compare(&mac::mac::<Hmac<Sha1>>(key, &file), &signature, delay) {
+			true => "200 OK",
+			false => "500 Internal Server Error",
+		},
+		_ => "400 Bad Request",
+	}
+}
+
+fn query_param(query: &str, name: &str) -> Option<Vec<u8>> {
+	query.split('&').filter_map(|pair| {
+		let mut parts = pair.splitn(2, '=');
+		match (parts.next(), parts.next()) {
+			(Some(k), Some(v)) if k == name => url_decode(v),
+			_ => None,
+		}
+	}).next()
+}
+
+fn url_decode(s: &str) -> Option<Vec<u8>> {
+	let bytes = s.as_bytes();
+	let mut res = vec![];
+	let mut i = 0;
+	while i < bytes.len() {
+		match bytes[i] {
+			b'%' => {
+				let hex = s.get(i + 1..i + 3)?;
+				res.push(u8::from_str_radix(hex, 16).ok()?);
+				i += 3;
+			},
+			b'+' => {
+				res.push(b' ');
+				i += 1;
+			},
+			b => {
+				res.push(b);
+				i += 1;
+			},
+		}
+	}
+	Some(res)
+}
+
+// everything but letters, digits and -._~ as %XX
+fn url_encode(data: &[u8]) -> String {
+	data.iter().map(|&b| match b.is_ascii_alphanumeric() || b"-._~".contains(&b) {
+		true => (b as char).to_string(),
+		false => format!("%{:02X}", b),
+	}).collect()
+}
+
+// utils::hex_string_to_bytes panics on bad input, which a server shouldn't
+fn decode_hex(s: &[u8]) -> Option<Vec<u8>> {
+	let s = std::str::from_utf8(s).ok()?;
+	if s.len() % 2 != 0 {
+		return None;
+	}
+	(0..s.len()).step_by(2).map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok()).collect()
+}
+
+// the status code, and how long it took from connecting to the end of the response
+pub fn request(addr: SocketAddr, file: &[u8], signature: &[u8]) -> Result<(u16, Duration), String> {
+	let start = Instant::now();
+	let mut stream = TcpStream::connect(addr).map_err(|e| format!("couldn't connect to {}: {}", addr, e))?;
+	stream.set_nodelay(true).map_err(|e| e.to_string())?;
+	let req = format!("GET /test?file={}&signature={} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\n\r\n",
+		url_encode(file), utils::bytes_to_hex_string(signature), addr);
+	stream.write_all(req.as_bytes()).map_err(|e| format!("couldn't send request: {}", e))?;
+	let mut response = vec![];
+	stream.read_to_end(&mut response).map_err(|e| format!("couldn't read response: {}", e))?;
+	let elapsed = start.elapsed();
+	match String::from_utf8_lossy(&response).split_whitespace().nth(1).and_then(|s| s.parse().ok()) {
+		Some(status) => Ok((status, elapsed)),
+		None => Err("malformed response".to_string()),
+	}
+}
+
+// recovers the first n bytes of a tag: at each position, the guess that takes longest got furthest
+pub fn recover_tag_by_timing<F: Fn(&[u8]) -> Result<Duration, String>>(time_tag: F, tag_len: usize, n: usize) -> Result<Vec<u8>, String> {
+	let mut tag = vec![0u8; tag_len];
+	for i in 0..n {
+		let mut slowest = (0, Duration::from_secs(0));
+		for b in 0..=255 {
+			tag[i] = b;
+			let elapsed = time_tag(&tag)?;
+			if elapsed > slowest.1 {
+				slowest = (b, elapsed);
+			}
+		}
+		tag[i] = slowest.0;
+	}
+	Ok(tag[..n].to_vec())
+}
+
+// one request per guess and the slowest wins, which is plenty at 50ms. ex32 for when it isn't
+pub fn discover_mac(addr: SocketAddr, file: &[u8], n: usize) -> Result<Vec<u8>, String> {
+	let time = |tag: &[u8]| request(addr, file, tag).map(|(_, elapsed)| elapsed);
+	recover_tag_by_timing(time, SIGNATURE_LEN, n)
+}
+
+// the first n bytes of the signature. each byte is 256 requests that all take 50ms longer than the last
+// byte's, so the whole signature is most of an hour. with n = 0 it's just the server answering
+pub fn break_hmac_with_timing_leak(n: usize) {
+	let key: Vec<u8> = (0..16).map(|_| thread_rng().gen::<u8>()).collect();
+	let server = match TimingServer::start(&key, Duration::from_millis(50)) {
+		Ok(server) => server,
+		Err(e) => return println!("{}", e),
+	};
+	let file = b"foo";
+	let valid = mac::mac::<Hmac<Sha1>>(&key, file);
+	println!("listening on {}", server.addr);
+	println!("valid signature: {:?}", request(server.addr, file, &valid).map(|r| r.0));
+	println!("wrong signature: {:?}", request(server.addr, file, &[0; SIGNATURE_LEN]).map(|r| r.0));
+	if n == 0 {
+		return;
+	}
+	match discover_mac(server.addr, file, n) {
+		Ok(recovered) => println!("first {} bytes: {} (really {})", n, utils::bytes_to_hex_string(&recovered), utils::bytes_to_hex_string(&valid[..n])),
+		Err(e) => println!("{}", e),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::set4::ex31::*;
+
+	#[test]
+	fn test_insecure_compare() {
+		let delay = Duration::from_millis(0);
+		assert!(insecure_compare(b"abc", b"abc", delay));
+		assert!(!insecure_compare(b"abc", b"abd", delay));
+		assert!(!insecure_compare(b"abc", b"ab", delay));
+		assert!(!insecure_compare(b"abc", b"abcd", delay));
+		// stops at the first difference
+		let mut looked_at = 0;
+		assert!(!insecure_compare_with(b"abcx", b"abcd", || looked_at += 1));
+		assert_eq!(looked_at, 3);
+	}
+
+	// no delay and no attack, just the http side over localhost, so it is quick. the attacks are the ignored
+	// tests in ex32
+	#[test]
+	fn test_server() {
+		let key = b"YELLOW SUBMARINE";
+		let server = TimingServer::start(key, Duration::from_millis(0)).unwrap();
+		let status = |file: &[u8], signature: &[u8]| request(server.addr, file, signature).unwrap().0;
+		let file = b"some file&name=x y%";
+		let valid = mac::mac::<Hmac<Sha1>>(key, file);
+		assert_eq!(status(file, &valid), 200);
+		assert_eq!(status(file, &valid[..19]), 500);
+		assert_eq!(status(file, &[0; SIGNATURE_LEN]), 500);
+		assert_eq!(status(b"foo", &valid), 500);
+
+		let raw = |line: &str| {
+			let mut stream = TcpStream::connect(server.addr).unwrap();
+			stream.write_all(format!("{}\r\n\r\n", line).as_bytes()).unwrap();
+			let mut response = String::new();
+			stream.read_to_string(&mut response).unwrap();
+			response.split_whitespace().nth(1).unwrap().to_string()
+		};
+		assert_eq!(raw(&format!("GET /test?file=foo&signature={} HTTP/1.1", utils::bytes_to_hex_string(&mac::mac::<Hmac<Sha1>>(key, b"foo")))), "200");
+		assert_eq!(raw("GET /test?file=foo&signature=zz HTTP/1.1"), "400");
+		assert_eq!(raw("GET /test?file=foo HTTP/1.1"), "400");
+		assert_eq!(raw("GET /other?file=foo&signature=00 HTTP/1.1"), "404");
+		assert_eq!(raw("nonsense"), "400");
+
+		// nothing listening is an error, not a very fast server
+		let addr = server.addr;
+		drop(server);
+		assert!(discover_mac(addr, b"foo", 1).is_err());
+	}
+}
@@ -0,0 +1,228 @@
+/*
+
+Break HMAC-SHA1 with a slightly less artificial timing leak
+
+Reduce the sleep in your "insecure_compare" until your previous solution breaks. (Try 5ms to start.)
+
+Now break it again.
+
+*/
+
+// ex31's attack takes one timing per guess, so as soon as the delay is anywhere near the noise in a
+// round trip the slowest guess is just whichever request got unlucky. instead:
+//
+// - every guess gets timed, interleaved so a slow patch on the machine hits all of them rather than one
+// - noise only ever makes a request slower, so guesses are scored by their fastest timing
+// - only the slowest quarter go on to the next round, and get timed some more
+// - a guess wins once it's been ahead of the runner-up by several times the spread of everyone else's
+//   scores for two rounds in a row, not counting the first, when it's still up against every other guess
+// - if no guess ever pulls ahead, the byte before was probably wrong, so it gets redone
+//
+// the last byte doesn't need timing at all: the right one gets a 200
+
+use crate::hashes::sha1::Sha1;
+use crate::mac::{self, Hmac};
+use crate::set4::ex31::{self, SIGNATURE_LEN, TimingServer};
+use crate::utils;
+use rand::{Rng, thread_rng};
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimingAttack {
+	pub samples: usize,		// timings per guess per round
+	pub rounds: usize,		// before giving up on a byte
+	pub confidence: f64,	// how many times the spread the winner has to be ahead by
+	pub backtracks: usize,	// bytes redone before giving up on the whole thing
+}
+
+impl Default for TimingAttack {
+	fn default() -> TimingAttack {
+		TimingAttack{samples: 2, rounds: 8, confidence: 4.0, backtracks: 10}
+	}
+}
+
+fn median(xs: &[f64]) -> f64 {
+	let mut sorted = xs.to_vec();
+	sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+	match sorted.len() % 2 {
+		1 => sorted[sorted.len() / 2],
+		_ => (sorted[sorted.len() / 2 - 1] + sorted[sorted.len() / 2]) / 2.0,
+	}
+}
+
+// a standard deviation that a few wild values can't drag around
+fn robust_sd(xs: &[f64]) -> f64 {
+	let centre = median(xs);
+	1.4826 * median(&xs.iter().map(|x| (x - centre).abs()).collect::<Vec<f64>>())
+}
+
+// the byte after known, or None if no guess stood out
+fn next_byte<F: FnMut(&[u8]) -> Result<Duration, String>>(time: &mut F, known: &[u8], attack: &TimingAttack) -> Result<Option<u8>, String> {
+	let mut guess = known.to_vec();
+	guess.resize(SIGNATURE_LEN, 0);
+	let mut timings: Vec<(u8, Vec<f64>)> = (0..=255).map(|b| (b, vec![])).collect();
+	let mut ahead = None;
+	for round in 0..attack.rounds {
+		for _ in 0..attack.samples {
+			for (b, times) in timings.iter_mut() {
+				guess[known.len()] = *b;
+				times.push(time(&guess)?.as_secs_f64() * 1e6);
+			}
+		}
+		// the fastest of each guess's timings is as close as we get to what the comparison took
+		let mut scores: Vec<(u8, f64)> = timings.iter().map(|(b, times)| (*b, times.iter().cloned().fold(f64::MAX, f64::min))).collect();
+		scores.sort_by(|x, y| y.1.partial_cmp(&x.1).unwrap());
+		let noise = robust_sd(&scores[1..].iter().map(|s| s.1).collect::<Vec<f64>>());
+		// twice running, so one lucky round isn't enough
+		match round > 0 && scores[0].1 - scores[1].1 > attack.confidence * noise {
+			true if ahead == Some(scores[0].0) => return Ok(Some(scores[0].0)),
+			true => ahead = Some(scores[0].0),
+			false => ahead = None,
+		}
+
+		let keep: Vec<u8> = scores.iter().take((timings.len() / 4).max(16)).map(|s| s.0).collect();
+		timings.retain(|(b, _)| keep.contains(b));
+	}
+	Ok(None)
+}
+
+// the first len bytes of the signature for file. with len = SIGNATURE_LEN it's the whole thing, and
+// only returned once the server has accepted it
+pub fn recover_signature(addr: SocketAddr, file: &[u8], len: usize, attack: &TimingAttack) -> Result<Vec<u8>, String> {
+	let len = len.min(SIGNATURE_LEN);
+	let mut time = |guess: &[u8]| ex31::request(addr, file, guess).map(|(_, elapsed)| elapsed);
+	let mut known = vec![];
+	let mut backtracks = 0;
+	while known.len() < len {
+		let next = match known.len() == SIGNATURE_LEN - 1 {
+			true => last_byte(addr, file, &known)?,
+			false => next_byte(&mut time, &known, attack)?,
+		};
+		match next {
+			Some(b) => known.push(b),
+			None if backtracks < attack.backtracks => {
+				backtracks += 1;
+				known.pop();
+			},
+			None => return Err(format!("lost the signature after {} bytes", known.len())),
+		}
+	}
+	Ok(known)
+}
+
+fn last_byte(addr: SocketAddr, file: &[u8], known: &[u8]) -> Result<Option<u8>, String> {
+	let mut guess = known.to_vec();
+	guess.push(0);
+	for b in 0..=255 {
+		guess[SIGNATURE_LEN - 1] = b;
+		if ex31::request(addr, file, &guess)?.0 == 200 {
+			return Ok(Some(b));
+		}
+	}
+	Ok(None)
+}
+
+fn attack(delay: Duration, len: usize) {
+	let len = len.min(SIGNATURE_LEN);
+	let key: Vec<u8> = (0..16).map(|_| thread_rng().gen::<u8>()).collect();
+	let server = match TimingServer::start(&key, delay) {
+		Ok(server) => server,
+		Err(e) => return println!("{}", e),
+	};
+	let file = b"foo";
+	let valid = mac::mac::<Hmac<Sha1>>(&key, file);
+	let start = Instant::now();
+	match recover_signature(server.addr, file, len, &TimingAttack::default()) {
+		Ok(recovered) => println!("{:?} delay, {} bytes: {} (really {}) in {:?}", delay, len, utils::bytes_to_hex_string(&recovered),
+			utils::bytes_to_hex_string(&valid[..len]), start.elapsed()),
+		Err(e) => println!("{:?} delay: {}", delay, e),
+	}
+}
+
+pub fn break_hmac_with_smaller_timing_leak() {
+	// the whole signature at 5ms takes the best part of a quarter of an hour, so the start of it, and
+	// then all of it with a delay under a millisecond
+	attack(Duration::from_millis(5), 4);
+	attack(Duration::from_micros(500), SIGNATURE_LEN);
+}
+
+// with no arguments, ex31's attack and then this one's, which takes a good few minutes
+pub fn run_cli(args: &[String]) -> Result<(), String> {
+	let usage = "usage: hmactiming [<delay in microseconds> [bytes to recover]]".to_string();
+	let number = |s: &str| s.parse::<u64>().map_err(|_| format!("{} is not a number", s));
+	match args {
+		[] => {
+			ex31::break_hmac_with_timing_leak(2);
+			break_hmac_with_smaller_timing_leak();
+		},
+		[delay] => attack(Duration::from_micros(number(delay)?), SIGNATURE_LEN),
+		[delay, len] => attack(Duration::from_micros(number(delay)?), number(len)? as usize),
+		_ => return Err(usage),
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::set3::ex21::MersenneTwister;
+	use crate::set4::ex32::*;
+
+	fn check(delay: Duration, len: usize) {
+		let key = b"YELLOW SUBMARINE";
+		let server = TimingServer::start(key, delay).unwrap();
+		let valid = mac::mac::<Hmac<Sha1>>(key, b"foo");
+		assert_eq!(recover_signature(server.addr, b"foo", len, &TimingAttack::default()).unwrap(), valid[..len].to_vec(), "{:?}", delay);
+	}
+
+	#[test]
+	fn test_median() {
+		assert_eq!(median(&[3.0, 1.0, 2.0]), 2.0);
+		assert_eq!(median(&[4.0, 1.0, 2.0, 100.0]), 3.0);
+		assert_eq!(robust_sd(&[1.0, 1.0, 1.0, 1.0, 1000.0]), 0.0);
+	}
+
+	// the statistics without the network: 100us for every byte that matches, under jitter of most of that and
+	// the odd 2ms stall, from a seeded generator so it comes out the same every time
+	#[test]
+	fn test_next_byte_simulated() {
+		let valid = mac::mac::<Hmac<Sha1>>(b"YELLOW SUBMARINE", b"foo");
+		let mut rng = MersenneTwister::<u32>::new(5489);
+		let mut time = |guess: &[u8]| -> Result<Duration, String> {
+			let matched = guess.iter().zip(valid.iter()).take_while(|(a, b)| a == b).count();
+			let stall = if rng.gen::<f64>() < 0.05 { 2000.0 } else { 0.0 };
+			Ok(Duration::from_secs_f64((100.0 + 100.0 * matched as f64 + 60.0 * rng.gen::<f64>() + stall) / 1e6))
+		};
+		let mut known = vec![];
+		for _ in 0..4 {
+			known.push(next_byte(&mut time, &known, &TimingAttack::default()).unwrap().unwrap());
+		}
+		assert_eq!(known, valid[..4].to_vec());
+
+		// no guess stands out, so no byte
+		let mut flat = |_: &[u8]| -> Result<Duration, String> { Ok(Duration::from_micros(100)) };
+		assert_eq!(next_byte(&mut flat, &[], &TimingAttack::default()), Ok(None));
+	}
+
+	// these two are the real thing over localhost, and take a while and a quiet machine: cargo test -- --ignored
+	#[test]
+	#[ignore]
+	fn test_5ms() {
+		check(Duration::from_millis(5), 3);
+	}
+
+	#[test]
+	#[ignore]
+	fn test_sub_millisecond() {
+		check(Duration::from_micros(300), 8);
+	}
+
+	#[test]
+	fn test_last_byte() {
+		let key = b"YELLOW SUBMARINE";
+		let server = TimingServer::start(key, Duration::from_micros(0)).unwrap();
+		let valid = mac::mac::<Hmac<Sha1>>(key, b"foo");
+		assert_eq!(last_byte(server.addr, b"foo", &valid[..19]), Ok(Some(valid[19])));
+		assert_eq!(last_byte(server.addr, b"foo", &[0; 19]), Ok(None));
+	}
+}
@@ -4,3 +4,5 @@ pub mod ex27;
 pub mod ex28;
 pub mod ex29;
 pub mod ex30;
+pub mod ex31;
+pub mod ex32;
@@ -0,0 +1,383 @@
+/*
+
+Time seeded tokens
+
+ex22 and ex24 get the seed back by trying every second near now. Real code also seeds from
+milliseconds or microseconds, mixes in the pid, and uses whatever generator the language came with.
+So: given a token, try every timestamp in a window at some resolution, turn each one into seeds
+with each formula, and see which generator makes that token from which seed. Anything that matches
+also gives us every token it'll make after that one.
+
+A token here is the first bytes the generator gives, the way each of them normally makes bytes:
+
+	mt19937			std::mt19937 seeded with the low 32 bits, each output copied in little endian
+	mt19937-stream	ex24's keystream, outputs big endian (xor out any known plaintext first)
+	mt19937-64		std::mt19937_64, little endian
+	python			random.seed(n); random.randbytes(len)
+	glibc, msvc		srand(seed); the low byte of each rand()
+	java			new Random(seed).nextBytes(token)
+	pcg32			pcg32_srandom_r(seed, 54), little endian
+
+Each token takes whole outputs, anything left over from the last one is thrown away, so the next
+token starts on a fresh output (which is what randbytes and nextBytes do).
+
+*/
+
+use crate::py_random::PyRandom;
+use crate::set3::ex21::{MtPrng, MtPrng64};
+use crate::utils;
+use crate::weak_prng::{GlibcRand, JavaRandom, MsvcRand, Pcg32};
+use rand::{Rng, thread_rng};
+use std::time::SystemTime;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Generator {
+	Mt19937,
+	Mt19937Stream,
+	Mt19937_64,
+	Python,
+	Glibc,
+	Msvc,
+	Java,
+	Pcg32,
+}
+
+pub const GENERATORS: &[Generator] = &[Generator::Mt19937, Generator::Mt19937Stream, Generator::Mt19937_64,
+	Generator::Python, Generator::Glibc, Generator::Msvc, Generator::Java, Generator::Pcg32];
+
+// bytes from whole words until there's enough
+fn take_words<F: FnMut() -> Vec<u8>>(mut next: F, len: usize) -> Vec<u8> {
+	let mut res = vec![];
+	while res.len() < len {
+		res.append(&mut next());
+	}
+	res.truncate(len);
+	res
+}
+
+impl Generator {
+	pub fn name(&self) -> &'static str {
+		match self {
+			Generator::Mt19937 => "mt19937",
+			Generator::Mt19937Stream => "mt19937-stream",
+			Generator::Mt19937_64 => "mt19937-64",
+			Generator::Python => "python",
+			Generator::Glibc => "glibc",
+			Generator::Msvc => "msvc",
+			Generator::Java => "java",
+			Generator::Pcg32 => "pcg32",
+		}
+	}
+
+	pub fn from_name(name: &str) -> Result<Generator, String> {
+		GENERATORS.iter().find(|g| g.name() == name).cloned()
+			.ok_or(format!("unknown generator {}, try one of: {}", name, GENERATORS.iter().map(|g| g.name()).collect::<Vec<&str>>().join(", ")))
+	}
+
+	// the first count tokens of len bytes after seeding with seed
+	pub fn tokens(&self, seed: u64, len: usize, count: usize) -> Vec<Vec<u8>> {
+		let mut next: Box<dyn FnMut() -> Vec<u8>> = match self {
+			Generator::Mt19937 => {
+				let mut rng = MtPrng::new(seed as u32);
+				Box::new(move || take_words(|| rng.extract_number().to_le_bytes().to_vec(), len))
+			},
+			Generator::Mt19937Stream => {
+				let mut rng = MtPrng::new(seed as u32);
+				Box::new(move || take_words(|| rng.extract_number().to_be_bytes().to_vec(), len))
+			},
+			Generator::Mt19937_64 => {
+				let mut rng = MtPrng64::new(seed);
+				Box::new(move || take_words(|| rng.extract_number().to_le_bytes().to_vec(), len))
+			},
+			// getrandbits(8 * len) shifts the last word down rather than dropping its low bytes
+			Generator::Python => {
+				let mut rng = PyRandom::new(seed);
+				Box::new(move || {
+					let mut res = vec![];
+					while res.len() < len {
+						let k = 8 * (len - res.len()).min(4) as u32;
+						res.extend_from_slice(&(rng.getrandbits(k) as u32).to_le_bytes()[..k as usize / 8]);
+					}
+					res
+				})
+			},
+			Generator::Glibc => {
+				let mut rng = GlibcRand::new(seed as u32);
+				Box::new(move || (0..len).map(|_| rng.rand() as u8).collect())
+			},
+			Generator::Msvc => {
+				let mut rng = MsvcRand::new(seed as u32);
+				Box::new(move || (0..len).map(|_| rng.rand() as u8).collect())
+			},
+			Generator::Java => {
+				let mut rng = JavaRandom::new(seed as i64);
+				Box::new(move || take_words(|| rng.next_int().to_le_bytes().to_vec(), len))
+			},
+			Generator::Pcg32 => {
+				let mut rng = Pcg32::new(seed, 54);
+				Box::new(move || take_words(|| rng.next_u32().to_le_bytes().to_vec(), len))
+			},
+		};
+		(0..count).map(|_| next()).collect()
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Resolution {
+	Seconds,
+	Millis,
+	Micros,
+}
+
+impl Resolution {
+	pub fn per_second(&self) -> u64 {
+		match self {
+			Resolution::Seconds => 1,
+			Resolution::Millis => 1000,
+			Resolution::Micros => 1000000,
+		}
+	}
+}
+
+// how the timestamp (in the search's resolution) becomes a seed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SeedFormula {
+	Time,
+	PidXorTime,	// seed = getpid() ^ time(NULL), for every pid in the search's range (many pairs give the same seed)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Search {
+	pub from: u64,	// unix seconds, inclusive
+	pub to: u64,
+	pub resolution: Resolution,
+	pub formulas: Vec<SeedFormula>,
+	pub pids: (u64, u64),	// inclusive
+	pub generators: Vec<Generator>,
+	pub predictions: usize,
+}
+
+fn now() -> u64 {
+	SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).expect("could not get time").as_secs()
+}
+
+impl Default for Search {
+	// what ex24 checked: the last five minutes, in seconds
+	fn default() -> Search {
+		let now = now();
+		Search{
+			from: now - 300,
+			to: now,
+			resolution: Resolution::Seconds,
+			formulas: vec![SeedFormula::Time],
+			pids: (1, 32768),
+			generators: GENERATORS.to_vec(),
+			predictions: 3,
+		}
+	}
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Match {
+	pub generator: Generator,
+	pub formula: SeedFormula,
+	pub timestamp: u64,	// in the search's resolution
+	pub pid: Option<u64>,
+	pub seed: u64,
+	pub next_tokens: Vec<Vec<u8>>,
+}
+
+// every (timestamp, pid, seed) the search covers
+fn candidates(search: &Search, formula: SeedFormula) -> Box<dyn Iterator<Item = (u64, Option<u64>, u64)>> {
+	// to is an inclusive second, so every tick of it
+	let per_second = search.resolution.per_second();
+	let times = search.from * per_second..=(search.to + 1) * per_second - 1;
+	match formula {
+		SeedFormula::Time => Box::new(times.map(|t| (t, None, t))),
+		SeedFormula::PidXorTime => {
+			let (first, last) = search.pids;
+			Box::new(times.flat_map(move |t| (first..=last).map(move |pid| (t, Some(pid), pid ^ t))))
+		},
+	}
+}
+
+pub fn find_seeds(token: &[u8], search: &Search) -> Result<Vec<Match>, String> {
+	if token.is_empty() {
+		return Err("empty token".to_string());
+	}
+	if search.from > search.to || search.pids.0 > search.pids.1 {
+		return Err("empty search window".to_string());
+	}
+	let mut res = vec![];
+	for &formula in search.formulas.iter() {
+		for (timestamp, pid, seed) in candidates(search, formula) {
+			for &generator in search.generators.iter() {
+				let mut tokens = generator.tokens(seed, token.len(), 1 + search.predictions);
+				if tokens[0] == token {
+					tokens.remove(0);
+					res.push(Match{generator: generator, formula: formula, timestamp: timestamp, pid: pid, seed: seed, next_tokens: tokens});
+				}
+			}
+		}
+	}
+	Ok(res)
+}
+
+// hex and base64 are checked first, utils panics on anything it can't decode
+pub fn decode_token(token: &str, encoding: &str) -> Result<Vec<u8>, String> {
+	match encoding {
+		"raw" => Ok(token.as_bytes().to_vec()),
+		"hex" => match token.len() % 2 == 0 && token.chars().all(|c| c.is_ascii_hexdigit()) {
+			true => Ok(utils::hex_string_to_bytes(token)),
+			false => Err(format!("{} is not hex", token)),
+		},
+		"base64" => {
+			let body = token.trim_end_matches('=');
+			match token.len() % 4 == 0 && body.len() + 2 >= token.len()
+				&& body.chars().all(|c| c.is_ascii_alphanumeric() || c == '+' || c == '/') {
+				true => {
+					let mut res = utils::base64_to_bytes(&token.to_string());
+					res.truncate(body.len() * 3 / 4);
+					Ok(res)
+				},
+				false => Err(format!("{} is not base64", token)),
+			}
+		},
+		_ => Err(format!("unknown encoding {}, try raw, hex or base64", encoding)),
+	}
+}
+
+pub fn run_cli(args: &[String]) -> Result<(), String> {
+	let usage = "usage: tokens <token> [--encoding raw|hex|base64] [--from <unix seconds>] [--to <unix seconds>] \
+		[--resolution s|ms|us] [--formulas time,pid-xor-time] [--pids <first>-<last>] [--generators <names>] [--predict <n>]".to_string();
+	let (token, options) = match args.split_first() {
+		Some((token, options)) if options.len() % 2 == 0 => (token, options),
+		_ => return Err(usage),
+	};
+	let mut search = Search::default();
+	let mut encoding = "raw";
+	let number = |s: &str| s.parse::<u64>().map_err(|_| format!("{} is not a number", s));
+	for option in options.chunks(2) {
+		let value = option[1].as_str();
+		match option[0].as_str() {
+			"--encoding" => encoding = value,
+			"--from" => search.from = number(value)?,
+			"--to" => search.to = number(value)?,
+			"--resolution" => search.resolution = match value {
+				"s" => Resolution::Seconds,
+				"ms" => Resolution::Millis,
+				"us" => Resolution::Micros,
+				_ => return Err(usage),
+			},
+			"--formulas" => search.formulas = value.split(',').map(|f| match f {
+				"time" => Ok(SeedFormula::Time),
+				"pid-xor-time" => Ok(SeedFormula::PidXorTime),
+				_ => Err(format!("unknown formula {}, try time or pid-xor-time", f)),
+			}).collect::<Result<Vec<SeedFormula>, String>>()?,
+			"--pids" => search.pids = match value.split('-').collect::<Vec<&str>>()[..] {
+				[first, last] => (number(first)?, number(last)?),
+				_ => return Err(usage),
+			},
+			"--generators" => search.generators = value.split(',').map(Generator::from_name).collect::<Result<Vec<Generator>, String>>()?,
+			"--predict" => search.predictions = number(value)? as usize,
+			_ => return Err(usage),
+		}
+	}
+	let matches = find_seeds(&decode_token(token, encoding)?, &search)?;
+	if matches.is_empty() {
+		println!("no seed in the window makes this token");
+	}
+	for m in matches.iter() {
+		let pid = match m.pid {
+			Some(pid) => format!(" pid {}", pid),
+			None => "".to_string(),
+		};
+		println!("{} seeded with {} (time {}{})", m.generator.name(), m.seed, m.timestamp, pid);
+		for t in m.next_tokens.iter() {
+			println!("\tnext: {}", utils::bytes_to_hex_string(t));
+		}
+	}
+	Ok(())
+}
+
+pub fn demonstrate() {
+	let mut rng = thread_rng();
+	let now_ms = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).expect("could not get time").as_millis() as u64;
+	let seeded_at = now_ms - rng.gen_range(0, 60000);
+	let tokens = Generator::Java.tokens(seeded_at, 16, 2);
+	let search = Search{
+		from: now_ms / 1000 - 60,
+		resolution: Resolution::Millis,
+		generators: vec![Generator::Java, Generator::Python],
+		..Search::default()
+	};
+	match find_seeds(&tokens[0], &search) {
+		Ok(matches) => println!("java token from a millisecond seed: {} match, next token predicted: {}",
+			matches.len(), matches.iter().any(|m| m.next_tokens[0] == tokens[1])),
+		Err(e) => println!("search failed: {}", e),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::token_seed::*;
+	use crate::utils;
+
+	// recorded from g++ (std::mt19937), gcc/glibc, openjdk and cpython 3.11
+	#[test]
+	fn test_tokens() {
+		let vectors = [
+			(Generator::Mt19937, 1700000123, ["ceee9ee756d21fecfa5b", "c0bdd2af2785a1e74d31"]),
+			(Generator::Glibc, 1700000000, ["270a8e14fc3f030c2e49", "f60c024aa289de4fccf0"]),
+			(Generator::Java, 1700000000123, ["01e73f11e6b06c20b47b", "e9c6dc18eaa720b7a97d"]),
+			(Generator::Python, 1700000000123, ["1689b65f5aaa24b3906d", "c25e41020ba1a6d700ce"]),
+			(Generator::Python, 1700000000, ["0b16105271fc9c570f31", "0c2d4b6b7d97681e3cf6"]),
+		];
+		for (generator, seed, expected) in vectors.iter() {
+			let tokens: Vec<String> = generator.tokens(*seed, 10, 2).iter().map(|t| utils::bytes_to_hex_string(t)).collect();
+			assert_eq!(tokens, expected.to_vec(), "{}", generator.name());
+		}
+	}
+
+	#[test]
+	fn test_find_seeds() {
+		let search = Search{from: 1700000000, to: 1700000002, resolution: Resolution::Millis, predictions: 1, ..Search::default()};
+		let token = decode_token("Aec/EeawbCC0ew==", "base64").unwrap();
+		let matches = find_seeds(&token, &search).unwrap();
+		assert_eq!(matches.len(), 1);
+		assert_eq!((matches[0].generator, matches[0].seed), (Generator::Java, 1700000000123));
+		assert_eq!(utils::bytes_to_hex_string(&matches[0].next_tokens[0]), "e9c6dc18eaa720b7a97d");
+
+		// the last tick of the last second is still in the window
+		let token = Generator::Java.tokens(1700000002999, 10, 1).remove(0);
+		let matches = find_seeds(&token, &Search{generators: vec![Generator::Java], ..search}).unwrap();
+		assert_eq!(matches.iter().map(|m| m.seed).collect::<Vec<u64>>(), vec![1700000002999]);
+
+		// srand(getpid() ^ time(NULL))
+		let token = Generator::Glibc.tokens(4242 ^ 1700000100, 12, 1).remove(0);
+		let search = Search{from: 1700000090, to: 1700000110, formulas: vec![SeedFormula::PidXorTime], pids: (4000, 5000),
+			generators: vec![Generator::Glibc, Generator::Msvc], ..Search::default()};
+		// lots of (time, pid) pairs xor to the same seed
+		let matches = find_seeds(&token, &search).unwrap();
+		assert!(matches.iter().all(|m| m.generator == Generator::Glibc && m.seed == 4242 ^ 1700000100));
+		assert!(matches.iter().any(|m| (m.timestamp, m.pid) == (1700000100, Some(4242))));
+
+		let search = Search{from: 1700000000, to: 1700000001, ..Search::default()};
+		assert!(find_seeds(&Generator::Mt19937.tokens(1700000005, 8, 1)[0], &search).unwrap().is_empty());
+		assert!(find_seeds(b"", &search).is_err());
+		assert!(find_seeds(b"x", &Search{from: 2, to: 1, ..Search::default()}).is_err());
+	}
+
+	#[test]
+	fn test_decode_token() {
+		assert_eq!(decode_token("00ff", "hex").unwrap(), vec![0, 255]);
+		assert_eq!(decode_token("AP8=", "base64").unwrap(), vec![0, 255]);
+		assert_eq!(decode_token("AP8A", "base64").unwrap(), vec![0, 255, 0]);
+		assert_eq!(decode_token("abc", "raw").unwrap(), b"abc".to_vec());
+		assert!(decode_token("0f0", "hex").is_err());
+		assert!(decode_token("zz", "hex").is_err());
+		assert!(decode_token("AP8", "base64").is_err());
+		assert!(decode_token("A===", "base64").is_err());
+		assert!(decode_token("abc", "rot13").is_err());
+	}
+}
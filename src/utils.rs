@@ -135,3 +135,7 @@ pub fn min(x: usize, y: usize) -> usize {
 		false => y,
 	}
 }
+
+pub fn bytes_to_hex_string(inp: &[u8]) -> String {
+	inp.iter().map(|b| format!("{:02x}", b)).collect()
+}
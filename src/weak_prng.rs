@@ -0,0 +1,617 @@
+/*
+
+Weak PRNGs
+
+What turns up in audits besides MT19937, and how to get their state back from what they leak.
+
+glibc rand() (TYPE_3, the default) is an additive lagged fibonacci generator: r[i] = r[i-31] + r[i-3]
+mod 2^32, handing out r[i] >> 1. The dropped low bits follow their own linear recurrence (the low bit
+of a sum is the xor), and whenever an output comes out one more than o[i-31] + o[i-3] it's because
+both dropped bits were 1. Collect enough of those, solve for the 31 low bits that decide the rest,
+and we have the whole window.
+
+java.util.Random is a 48 bit LCG, and next(bits) gives the top bits of the state. nextInt() hides 16
+bits and nextDouble() 22 of its first state, so try them all and keep the one that produces the
+outputs after it. No lattices needed unless all we get is nextInt(bound).
+
+MSVC rand() is a 32 bit LCG handing out bits 16..30. Bit 31 never affects anything below it, so
+guessing the low 16 bits is enough.
+
+xorshift128+ (V8's Math.random) is linear over GF(2), and V8 makes the double straight from
+state0 >> 12. Each Math.random() gives away 52 state bits, and a handful pin down all 128. V8
+generates 64 at a time into a cache and hands them out from the end, so a run of calls is the
+generator going backwards.
+
+PCG32's output permutation hides enough that getting the state from outputs is a research project,
+but it's only as good as its seed. Seeded from the time, search the window.
+
+*/
+
+use rand::{Rng, thread_rng};
+use std::ops::Range;
+use std::time::SystemTime;
+
+// gaussian elimination over GF(2), one bit per unknown (up to 128). undetermined ones come out as 0
+fn solve_gf2(equations: &[(u128, bool)], unknowns: usize) -> Result<(u128, usize), String> {
+	let mut pivots: Vec<Option<(u128, bool)>> = vec![None; unknowns];
+	let mut rank = 0;
+	for &(row, rhs) in equations.iter() {
+		let (mut row, mut rhs) = (row, rhs);
+		while row != 0 {
+			let col = row.trailing_zeros() as usize;
+			match pivots[col] {
+				Some((pivot, pivot_rhs)) => {
+					row ^= pivot;
+					rhs ^= pivot_rhs;
+				},
+				None => {
+					pivots[col] = Some((row, rhs));
+					rank += 1;
+					break;
+				},
+			}
+		}
+		if row == 0 && rhs {
+			return Err("equations are inconsistent".to_string());
+		}
+	}
+	let mut solution = 0u128;
+	for col in (0..unknowns).rev() {
+		if let Some((row, rhs)) = pivots[col] {
+			if ((row & solution).count_ones() % 2 == 1) != rhs {
+				solution |= 1 << col;
+			}
+		}
+	}
+	Ok((solution, rank))
+}
+
+pub struct GlibcRand {
+	r: Vec<u32>,	// the last 34 values
+}
+
+impl GlibcRand {
+	// srandom, which also throws away the first 310 values
+	pub fn new(seed: u32) -> GlibcRand {
+		let mut r = vec![0u32; 34];
+		r[0] = match seed {
+			0 => 1,
+			s => s,
+		};
+		for i in 1..31 {
+			// 16807 * r[i-1] % (2^31 - 1) on signed ints, without overflowing
+			let word = r[i-1] as i32 as i64;
+			let (hi, lo) = (word / 127773, word % 127773);
+			let mut word = 16807 * lo - 2836 * hi;
+			if word < 0 {
+				word += 2147483647;
+			}
+			r[i] = word as u32;
+		}
+		for i in 31..34 {
+			r[i] = r[i-31];
+		}
+		let mut rng = GlibcRand{r: r};
+		for _ in 34..344 {
+			rng.step();
+		}
+		rng
+	}
+
+	fn step(&mut self) -> u32 {
+		// r[i-31] + r[i-3]
+		let next = self.r[3].wrapping_add(self.r[31]);
+		self.r.remove(0);
+		self.r.push(next);
+		next
+	}
+
+	pub fn rand(&mut self) -> u32 {
+		self.step() >> 1
+	}
+}
+
+// from consecutive outputs, a few hundred to be sure of seeing enough carries
+pub fn recover_glibc_rand(outputs: &[u32]) -> Result<GlibcRand, String> {
+	let n = outputs.len();
+	if n < 34 {
+		return Err("need at least 34 consecutive outputs".to_string());
+	}
+	// each dropped low bit as a combination of the first 31
+	let mut low_bits: Vec<u128> = (0..31).map(|i| 1u128 << i).collect();
+	let mut equations = vec![];
+	for k in 31..n {
+		low_bits.push(low_bits[k-31] ^ low_bits[k-3]);
+		let sum = outputs[k-31].wrapping_add(outputs[k-3]) & 0x7FFFFFFF;
+		match outputs[k].wrapping_sub(sum) & 0x7FFFFFFF {
+			0 => (),
+			1 => {
+				equations.push((low_bits[k-31], true));
+				equations.push((low_bits[k-3], true));
+			},
+			_ => return Err(format!("output {} doesn't follow from the ones before it", k)),
+		}
+	}
+	let (solution, rank) = solve_gf2(&equations, 31)?;
+	if rank < 31 {
+		return Err(format!("not enough carries yet, {} of 31 low bits known", rank));
+	}
+
+	let full = |k: usize| (outputs[k] << 1) | ((low_bits[k] & solution).count_ones() & 1);
+	let mut rng = GlibcRand{r: (0..34).map(full).collect()};
+	// the carries we didn't use have to come out right too
+	for k in 34..n {
+		if rng.rand() != outputs[k] {
+			return Err(format!("recovered state doesn't reproduce output {}", k));
+		}
+	}
+	Ok(rng)
+}
+
+pub struct MsvcRand {
+	state: u32,
+}
+
+impl MsvcRand {
+	pub fn new(seed: u32) -> MsvcRand {
+		MsvcRand{state: seed}
+	}
+
+	pub fn rand(&mut self) -> u32 {
+		self.state = self.state.wrapping_mul(214013).wrapping_add(2531011);
+		(self.state >> 16) & 0x7FFF
+	}
+}
+
+pub fn recover_msvc_rand(outputs: &[u32]) -> Result<MsvcRand, String> {
+	if outputs.len() < 3 {
+		return Err("need at least 3 outputs".to_string());
+	}
+	let mut found: Vec<MsvcRand> = (0..1 << 16)
+		.map(|low| MsvcRand{state: (outputs[0] << 16) | low})
+		.filter_map(|mut rng| match outputs[1..].iter().all(|&o| rng.rand() == o) {
+			true => Some(rng),
+			false => None,
+		})
+		.collect();
+	match found.len() {
+		0 => Err("no state produces these outputs".to_string()),
+		1 => Ok(found.remove(0)),
+		n => Err(format!("{} states fit, need more outputs", n)),
+	}
+}
+
+const JAVA_MULTIPLIER: u64 = 0x5DEECE66D;
+const JAVA_MASK: u64 = (1 << 48) - 1;
+
+pub struct JavaRandom {
+	seed: u64,
+}
+
+impl JavaRandom {
+	pub fn new(seed: i64) -> JavaRandom {
+		JavaRandom{seed: (seed as u64 ^ JAVA_MULTIPLIER) & JAVA_MASK}
+	}
+
+	// the internal state, already scrambled
+	pub fn from_state(state: u64) -> JavaRandom {
+		JavaRandom{seed: state & JAVA_MASK}
+	}
+
+	pub fn next(&mut self, bits: u32) -> i32 {
+		self.seed = self.seed.wrapping_mul(JAVA_MULTIPLIER).wrapping_add(0xB) & JAVA_MASK;
+		(self.seed >> (48 - bits)) as i32
+	}
+
+	pub fn next_int(&mut self) -> i32 {
+		self.next(32)
+	}
+
+	pub fn next_int_bound(&mut self, bound: i32) -> i32 {
+		assert!(bound > 0, "bound must be positive");
+		let mut r = self.next(31);
+		let m = bound - 1;
+		if bound & m == 0 {
+			return ((bound as i64 * r as i64) >> 31) as i32;
+		}
+		let mut u = r;
+		r = u % bound;
+		// rejects the top partial range, the check overflows on purpose
+		while u.wrapping_sub(r).wrapping_add(m) < 0 {
+			u = self.next(31);
+			r = u % bound;
+		}
+		r
+	}
+
+	pub fn next_long(&mut self) -> i64 {
+		((self.next(32) as i64) << 32).wrapping_add(self.next(32) as i64)
+	}
+
+	pub fn next_double(&mut self) -> f64 {
+		(((self.next(26) as i64) << 27) + self.next(27) as i64) as f64 / (1u64 << 53) as f64
+	}
+
+	pub fn next_boolean(&mut self) -> bool {
+		self.next(1) != 0
+	}
+}
+
+// what one call to next(bits) gave
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct JavaOutput {
+	pub bits: u32,
+	pub value: i32,
+}
+
+impl JavaOutput {
+	pub fn int(x: i32) -> Vec<JavaOutput> {
+		vec![JavaOutput{bits: 32, value: x}]
+	}
+
+	pub fn long(x: i64) -> Vec<JavaOutput> {
+		let low = x as i32;
+		let high = (x.wrapping_sub(low as i64) >> 32) as i32;
+		vec![JavaOutput{bits: 32, value: high}, JavaOutput{bits: 32, value: low}]
+	}
+
+	pub fn double(x: f64) -> Vec<JavaOutput> {
+		let bits = (x * (1u64 << 53) as f64) as i64;
+		vec![JavaOutput{bits: 26, value: (bits >> 27) as i32}, JavaOutput{bits: 27, value: (bits & ((1 << 27) - 1)) as i32}]
+	}
+}
+
+// from consecutive calls, returns a generator that carries on after the last one
+pub fn recover_java_random(outputs: &[JavaOutput]) -> Result<JavaRandom, String> {
+	if outputs.len() < 2 {
+		return Err("need at least two outputs, one to guess from and one to check against".to_string());
+	}
+	let first = outputs[0];
+	let hidden = 48 - first.bits;
+	if hidden > 24 {
+		return Err(format!("{} hidden bits is too many to guess", hidden));
+	}
+	let top = (first.value as u32 as u64 & ((1 << first.bits) - 1)) << hidden;
+	let mut found = vec![];
+	for low in 0..1u64 << hidden {
+		let mut rng = JavaRandom::from_state(top | low);
+		if outputs[1..].iter().all(|o| rng.next(o.bits) == o.value) {
+			found.push(rng);
+		}
+	}
+	match found.len() {
+		0 => Err("no state produces these outputs".to_string()),
+		1 => Ok(found.remove(0)),
+		n => Err(format!("{} states fit, need more outputs", n)),
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct XorShift128Plus {
+	pub state0: u64,
+	pub state1: u64,
+}
+
+// y = x ^ (x >> k) and y = x ^ (x << k), each pass gets another k bits right
+fn undo_xorshift_right(y: u64, k: u32) -> u64 {
+	(0..64 / k).fold(y, |x, _| y ^ (x >> k))
+}
+
+fn undo_xorshift_left(y: u64, k: u32) -> u64 {
+	(0..64 / k).fold(y, |x, _| y ^ (x << k))
+}
+
+impl XorShift128Plus {
+	// v8's shifts
+	pub fn step(&mut self) {
+		let (mut s1, s0) = (self.state0, self.state1);
+		self.state0 = s0;
+		s1 ^= s1 << 23;
+		s1 ^= s1 >> 17;
+		s1 ^= s0;
+		s1 ^= s0 >> 26;
+		self.state1 = s1;
+	}
+
+	pub fn step_back(&mut self) {
+		let s0 = self.state0;
+		let s1 = undo_xorshift_left(undo_xorshift_right(self.state1 ^ s0 ^ (s0 >> 26), 17), 23);
+		self.state0 = s1;
+		self.state1 = s0;
+	}
+
+	pub fn next_u64(&mut self) -> u64 {
+		self.step();
+		self.state0.wrapping_add(self.state1)
+	}
+}
+
+// v8's ToDouble: the top 52 bits of state0 as the mantissa of a double in [1, 2), minus 1
+pub fn v8_to_double(state0: u64) -> f64 {
+	f64::from_bits((state0 >> 12) | 0x3FF0000000000000) - 1.0
+}
+
+const V8_CACHE_SIZE: usize = 64;
+
+// Math.random()
+pub struct V8MathRandom {
+	rng: XorShift128Plus,
+	cache: Vec<f64>,
+	index: usize,	// handed out from the end
+}
+
+impl V8MathRandom {
+	pub fn new(rng: XorShift128Plus) -> V8MathRandom {
+		V8MathRandom{rng: rng, cache: vec![0.0; V8_CACHE_SIZE], index: 0}
+	}
+
+	pub fn random(&mut self) -> f64 {
+		if self.index == 0 {
+			for i in 0..V8_CACHE_SIZE {
+				self.rng.step();
+				self.cache[i] = v8_to_double(self.rng.state0);
+			}
+			self.index = V8_CACHE_SIZE;
+		}
+		self.index -= 1;
+		self.cache[self.index]
+	}
+}
+
+fn symbolic_shl(word: &[u128], k: usize) -> Vec<u128> {
+	(0..64).map(|i| match i >= k { true => word[i - k], false => 0 }).collect()
+}
+
+fn symbolic_shr(word: &[u128], k: usize) -> Vec<u128> {
+	(0..64).map(|i| match i + k < 64 { true => word[i + k], false => 0 }).collect()
+}
+
+fn symbolic_xor(a: &[u128], b: &[u128]) -> Vec<u128> {
+	a.iter().zip(b.iter()).map(|(x, y)| x ^ y).collect()
+}
+
+// doubles in the order the generator made them. returns the state that made the first one
+pub fn recover_xorshift128plus(doubles: &[f64]) -> Result<XorShift128Plus, String> {
+	// each state bit as a combination of the unknowns: state0 is 0..63, state1 is 64..127
+	let mut state0: Vec<u128> = (0..64).map(|i| 1u128 << i).collect();
+	let mut state1: Vec<u128> = (64..128).map(|i| 1u128 << i).collect();
+	let mut equations = vec![];
+	for &x in doubles.iter() {
+		if !(0.0..1.0).contains(&x) {
+			return Err(format!("{} isn't from Math.random()", x));
+		}
+		let mantissa = (x + 1.0).to_bits() & ((1 << 52) - 1);
+		for i in 0..52 {
+			equations.push((state0[i + 12], (mantissa >> i) & 1 == 1));
+		}
+		let (mut s1, s0) = (state0, state1);
+		s1 = symbolic_xor(&s1, &symbolic_shl(&s1, 23));
+		s1 = symbolic_xor(&s1, &symbolic_shr(&s1, 17));
+		s1 = symbolic_xor(&s1, &s0);
+		s1 = symbolic_xor(&s1, &symbolic_shr(&s0, 26));
+		state0 = s0;
+		state1 = s1;
+	}
+	let (solution, rank) = solve_gf2(&equations, 128)?;
+	if rank < 128 {
+		return Err(format!("only {} of 128 state bits pinned down, need more doubles", rank));
+	}
+	Ok(XorShift128Plus{state0: solution as u64, state1: (solution >> 64) as u64})
+}
+
+// calls has to start right after a cache refill, like the first calls a fresh isolate makes
+pub fn predict_v8_math_random(calls: &[f64]) -> Result<V8MathRandom, String> {
+	let k = calls.len();
+	if k > V8_CACHE_SIZE {
+		return Err(format!("more than one cache's worth ({}) of calls", V8_CACHE_SIZE));
+	}
+	let generated: Vec<f64> = calls.iter().rev().cloned().collect();
+	let mut rng = recover_xorshift128plus(&generated)?;
+
+	// the rest of this cache was generated before the calls we saw
+	let mut cache = vec![0.0; V8_CACHE_SIZE];
+	let mut back = rng;
+	for i in (0..V8_CACHE_SIZE - k).rev() {
+		back.step_back();
+		cache[i] = v8_to_double(back.state0);
+	}
+	for i in V8_CACHE_SIZE - k..V8_CACHE_SIZE {
+		cache[i] = v8_to_double(rng.state0);
+		if i + 1 < V8_CACHE_SIZE {
+			rng.step();
+		}
+	}
+	Ok(V8MathRandom{rng: rng, cache: cache, index: V8_CACHE_SIZE - k})
+}
+
+pub struct Pcg32 {
+	state: u64,
+	inc: u64,
+}
+
+impl Pcg32 {
+	// pcg32_srandom_r
+	pub fn new(initstate: u64, initseq: u64) -> Pcg32 {
+		let mut rng = Pcg32{state: 0, inc: (initseq << 1) | 1};
+		rng.next_u32();
+		rng.state = rng.state.wrapping_add(initstate);
+		rng.next_u32();
+		rng
+	}
+
+	pub fn next_u32(&mut self) -> u32 {
+		let old = self.state;
+		self.state = old.wrapping_mul(6364136223846793005).wrapping_add(self.inc);
+		let xorshifted = (((old >> 18) ^ old) >> 27) as u32;
+		let rot = (old >> 59) as u32;
+		xorshifted.rotate_right(rot)
+	}
+}
+
+// when it was seeded with pcg32_srandom_r(seed, initseq) for some seed in a small range, like a time
+pub fn find_pcg32_seed(outputs: &[u32], initseq: u64, seeds: Range<u64>) -> Result<(u64, Pcg32), String> {
+	for seed in seeds {
+		let mut rng = Pcg32::new(seed, initseq);
+		if outputs.iter().all(|&o| rng.next_u32() == o) {
+			return Ok((seed, rng));
+		}
+	}
+	Err("no seed in range produces these outputs".to_string())
+}
+
+fn report<T: PartialEq>(name: &str, predicted: Result<Vec<T>, String>, actual: Vec<T>) {
+	match predicted {
+		Ok(predicted) => println!("{}: predicted the next {} outputs: {}", name, actual.len(), predicted == actual),
+		Err(e) => println!("{}: attack failed: {}", name, e),
+	}
+}
+
+pub fn demonstrate() {
+	let mut seeder = thread_rng();
+
+	let mut glibc = GlibcRand::new(seeder.gen());
+	let outputs: Vec<u32> = (0..500).map(|_| glibc.rand()).collect();
+	report("glibc rand()", recover_glibc_rand(&outputs).map(|mut rng| (0..10).map(|_| rng.rand()).collect()),
+		(0..10).map(|_| glibc.rand()).collect());
+
+	let mut msvc = MsvcRand::new(seeder.gen());
+	let outputs: Vec<u32> = (0..4).map(|_| msvc.rand()).collect();
+	report("msvc rand()", recover_msvc_rand(&outputs).map(|mut rng| (0..10).map(|_| rng.rand()).collect()),
+		(0..10).map(|_| msvc.rand()).collect());
+
+	let mut java = JavaRandom::new(seeder.gen());
+	let mut outputs = JavaOutput::int(java.next_int());
+	outputs.append(&mut JavaOutput::int(java.next_int()));
+	report("java.util.Random from nextInt()", recover_java_random(&outputs).map(|mut rng| (0..10).map(|_| rng.next_int_bound(100)).collect()),
+		(0..10).map(|_| java.next_int_bound(100)).collect());
+
+	// one nextDouble() is 53 bits from two calls, which is plenty
+	let mut java = JavaRandom::new(seeder.gen());
+	let outputs = JavaOutput::double(java.next_double());
+	report("java.util.Random from nextDouble()", recover_java_random(&outputs).map(|mut rng| (0..10).map(|_| rng.next_long()).collect()),
+		(0..10).map(|_| java.next_long()).collect());
+
+	let mut java = JavaRandom::new(seeder.gen());
+	let outputs = JavaOutput::long(java.next_long());
+	report("java.util.Random from nextLong()", recover_java_random(&outputs).map(|mut rng| (0..64).map(|_| rng.next_boolean()).collect()),
+		(0..64).map(|_| java.next_boolean()).collect());
+
+	let mut v8 = V8MathRandom::new(XorShift128Plus{state0: seeder.gen(), state1: seeder.gen()});
+	let calls: Vec<f64> = (0..5).map(|_| v8.random()).collect();
+	report("v8 Math.random()", predict_v8_math_random(&calls).map(|mut rng| (0..100).map(|_| rng.random()).collect()),
+		(0..100).map(|_| v8.random()).collect());
+
+	let now = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).expect("could not get time").as_secs();
+	let mut pcg = Pcg32::new(now - seeder.gen_range(0, 3600), 54);
+	let outputs: Vec<u32> = (0..2).map(|_| pcg.next_u32()).collect();
+	report("pcg32 seeded from the time", find_pcg32_seed(&outputs, 54, now - 7200..now + 1).map(|(_, mut rng)| (0..10).map(|_| rng.next_u32()).collect()),
+		(0..10).map(|_| pcg.next_u32()).collect());
+}
+
+#[cfg(test)]
+mod tests {
+	use crate::weak_prng::*;
+
+	#[test]
+	fn test_glibc_rand() {
+		// gcc, glibc 2.36
+		for (seed, expected) in [(1u32, [1804289383, 846930886, 1681692777]), (0, [1804289383, 846930886, 1681692777]),
+			(12345, [383100999, 858300821, 357768173]), (3000000000, [2058147116, 854483408, 922419988])].iter() {
+			let mut rng = GlibcRand::new(*seed);
+			assert_eq!([rng.rand(), rng.rand(), rng.rand()], *expected);
+		}
+
+		let mut target = GlibcRand::new(0xc0ffee);
+		let outputs: Vec<u32> = (0..400).map(|_| target.rand()).collect();
+		let mut predicted = recover_glibc_rand(&outputs).unwrap();
+		for _ in 0..1000 {
+			assert_eq!(predicted.rand(), target.rand());
+		}
+		assert!(recover_glibc_rand(&outputs[..40]).is_err());
+		let mut tampered = outputs.clone();
+		tampered[100] ^= 0x1000;
+		assert!(recover_glibc_rand(&tampered).is_err());
+	}
+
+	#[test]
+	fn test_msvc_rand() {
+		let mut rng = MsvcRand::new(1);
+		assert_eq!((0..5).map(|_| rng.rand()).collect::<Vec<u32>>(), vec![41, 18467, 6334, 26500, 19169]);
+
+		let mut target = MsvcRand::new(0xdeadbeef);
+		let outputs: Vec<u32> = (0..4).map(|_| target.rand()).collect();
+		let mut predicted = recover_msvc_rand(&outputs).unwrap();
+		for _ in 0..1000 {
+			assert_eq!(predicted.rand(), target.rand());
+		}
+	}
+
+	#[test]
+	fn test_java_random() {
+		// openjdk
+		let mut rng = JavaRandom::new(42);
+		assert_eq!([rng.next_int(), rng.next_int(), rng.next_int(), rng.next_int()], [-1170105035, 234785527, -1360544799, 205897768]);
+		let mut rng = JavaRandom::new(42);
+		assert_eq!([rng.next_int_bound(10), rng.next_int_bound(1000), rng.next_int_bound(1 << 20)], [0, 763, 716411]);
+		assert_eq!(rng.next_long(), 884324181205335268);
+		assert_eq!(rng.next_double(), 0.9420735430282128);
+		assert!(rng.next_boolean());
+
+		// new Random(-7): two nextInt()s, then the next two
+		let mut outputs = JavaOutput::int(1155869324);
+		outputs.append(&mut JavaOutput::int(-423064701));
+		let mut predicted = recover_java_random(&outputs).unwrap();
+		assert_eq!([predicted.next_int(), predicted.next_int()], [-1403676116, -232591881]);
+
+		// new Random(123456789): two nextDouble()s, then another and a nextInt()
+		let mut outputs = JavaOutput::double(0.664038103272266);
+		outputs.append(&mut JavaOutput::double(0.45695178590520646));
+		let mut predicted = recover_java_random(&outputs).unwrap();
+		assert_eq!(predicted.next_double(), 0.39050647939140426);
+		assert_eq!(predicted.next_int(), -458096230);
+
+		let mut target = JavaRandom::new(-1);
+		let outputs = JavaOutput::long(target.next_long());
+		let mut predicted = recover_java_random(&outputs).unwrap();
+		assert_eq!(predicted.next_long(), target.next_long());
+
+		assert!(recover_java_random(&[JavaOutput{bits: 8, value: 3}, JavaOutput{bits: 8, value: 4}]).is_err());
+	}
+
+	#[test]
+	fn test_xorshift128plus() {
+		// xorshift128plus.c with v8's shifts
+		let mut rng = XorShift128Plus{state0: 1, state1: 2};
+		assert_eq!([rng.next_u64(), rng.next_u64(), rng.next_u64(), rng.next_u64()], [8388677, 33554692, 70368777736387, 211106267148357]);
+		let before = rng;
+		rng.step();
+		rng.step_back();
+		assert_eq!(rng, before);
+	}
+
+	#[test]
+	fn test_v8_math_random() {
+		// node 20.20 --random_seed=1337, the first 4 calls, then 64..67 and 128..131
+		let calls = [0.9311600617849973, 0.3551442693830502, 0.7923158995678377, 0.787777942408997];
+		let mut predicted = predict_v8_math_random(&calls).unwrap();
+		let next: Vec<f64> = (4..132).map(|_| predicted.random()).collect();
+		assert_eq!(next[..4], [0.376372264303491, 0.23137147109312428, 0.19307439497737655, 0.05294192203272163]);
+		assert_eq!(next[60..64], [0.6082055562128088, 0.1722477549610657, 0.4070722906009103, 0.4286880686762242]);
+		assert_eq!(next[124..], [0.3141194610777249, 0.24645063859729044, 0.3054551041263407, 0.7899279273654127]);
+
+		assert!(recover_xorshift128plus(&calls[..1]).is_err());
+		assert!(recover_xorshift128plus(&[1.5]).is_err());
+	}
+
+	#[test]
+	fn test_pcg32() {
+		// pcg32-demo, and pcg32_srandom_r(1700000000, 0xda3e39cb94b95bdb)
+		let mut rng = Pcg32::new(42, 54);
+		let outputs: Vec<u32> = (0..6).map(|_| rng.next_u32()).collect();
+		assert_eq!(outputs, vec![0xa15c02b7, 0x7b47f409, 0xba1d3330, 0x83d2f293, 0xbfa4784b, 0xcbed606e]);
+		let (seed, mut predicted) = find_pcg32_seed(&[0x94ef17ca, 0x01b579d2], 0xda3e39cb94b95bdb, 1699990000..1700010000).unwrap();
+		assert_eq!(seed, 1700000000);
+		assert_eq!(predicted.next_u32(), 0xa613301e);
+		assert!(find_pcg32_seed(&[0x94ef17ca, 0x01b579d2], 0xda3e39cb94b95bdb, 0..1000).is_err());
+	}
+}